@@ -8,3 +8,96 @@ export function mode() {
     return "game";
 }
 
+export function now_seconds() {
+    return Date.now() / 1000;
+}
+
+export function library_load() {
+    return window.localStorage.getItem("story-library") || "";
+}
+
+export function library_save(json) {
+    window.localStorage.setItem("story-library", json);
+}
+
+export function save_file(filename, mime_type, bytes) {
+    const url = URL.createObjectURL(new Blob([bytes], { type: mime_type }));
+    const link = document.createElement("a");
+    link.href = url;
+    link.download = filename;
+    link.click();
+    URL.revokeObjectURL(url);
+}
+
+// accounts reported by the EIP-1193 provider, drained by `wallet_poll`;
+// an empty string means no account is connected
+const walletAccounts = [];
+
+function pushWalletAccount(accounts) {
+    walletAccounts.push(accounts && accounts.length > 0 ? accounts[0] : "");
+}
+
+export function wallet_listen() {
+    if (!window.ethereum) {
+        return;
+    }
+    window.ethereum.on("accountsChanged", pushWalletAccount);
+    window.ethereum.on("disconnect", () => pushWalletAccount([]));
+    window.ethereum
+        .request({ method: "eth_accounts" })
+        .then((accounts) => {
+            if (accounts.length > 0) {
+                pushWalletAccount(accounts);
+            }
+        })
+        .catch(() => {});
+}
+
+export function wallet_connect() {
+    if (!window.ethereum) {
+        pushWalletAccount([]);
+        return;
+    }
+    window.ethereum
+        .request({ method: "eth_requestAccounts" })
+        .then(pushWalletAccount, () => pushWalletAccount([]));
+}
+
+export function wallet_disconnect() {
+    pushWalletAccount([]);
+    if (window.ethereum) {
+        window.ethereum
+            .request({
+                method: "wallet_revokePermissions",
+                params: [{ eth_accounts: {} }],
+            })
+            .catch(() => {});
+    }
+}
+
+export function wallet_poll() {
+    return walletAccounts.shift();
+}
+
+// `personal_sign` results drained by `wallet_poll_signature`, in request
+// order; an empty string means the player rejected the request
+const walletSignatures = [];
+let walletSigning = Promise.resolve();
+
+export function wallet_sign(message, address) {
+    walletSigning = walletSigning
+        .then(() =>
+            window.ethereum.request({
+                method: "personal_sign",
+                params: [message, address],
+            })
+        )
+        .then(
+            (signature) => walletSignatures.push(signature),
+            () => walletSignatures.push("")
+        );
+}
+
+export function wallet_poll_signature() {
+    return walletSignatures.shift();
+}
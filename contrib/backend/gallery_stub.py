@@ -0,0 +1,67 @@
+"""
+Serves story NFTs from gallery_stub.json, to try the in-game gallery without
+minting anything:
+
+    python gallery_stub.py
+    NFT_LIST_ENDPOINT=http://localhost:5001/api/nft/owner cargo run
+
+Posters are read from the game assets.
+"""
+
+import json
+import os
+
+from flask import Flask, jsonify, send_from_directory
+
+STUB_URL = "http://localhost:5001"
+ASSETS_DIR = os.path.join(os.path.dirname(__file__), "..", "..", "assets")
+
+with open(os.path.join(os.path.dirname(__file__), "gallery_stub.json")) as f:
+    NFTS = json.load(f)
+
+WEBAPP = Flask(__name__)
+
+
+def metadata(nft):
+    return {
+        "name": nft["name"],
+        "description": nft["description"],
+        "poster": nft["poster"],
+        "image": f"{STUB_URL}/api/image/{nft['poster']}",
+        "scenario": json.dumps(nft["story"]),
+        "attributes": nft["attributes"],
+        "version": 2,
+    }
+
+
+@WEBAPP.route("/api/nft/owner/<owner>", methods=["GET"])
+def list_story_nfts(owner):
+    nfts = [
+        {
+            "nft_id": nft["nft_id"],
+            "name": nft["name"],
+            "image": f"{STUB_URL}/api/image/{nft['poster']}",
+            "url": f"{STUB_URL}/api/nft/{nft['nft_id']}",
+        }
+        for nft in NFTS
+        if nft["owner"].lower() == owner.lower()
+    ]
+    return jsonify({"nfts": nfts}), 200
+
+
+@WEBAPP.route("/api/nft/<int:nft_id>", methods=["GET"])
+def get_story_nft(nft_id):
+    for nft in NFTS:
+        if nft["nft_id"] == nft_id:
+            return jsonify(metadata(nft)), 200
+
+    return jsonify({"error": "NFT not found"}), 404
+
+
+@WEBAPP.route("/api/image/<path:path>", methods=["GET"])
+def serve_poster(path):
+    return send_from_directory(ASSETS_DIR, path)
+
+
+if __name__ == "__main__":
+    WEBAPP.run(host="127.0.0.1", port=5001, debug=False)
@@ -32,6 +32,8 @@ const nftContract = new ethers.Contract(
 
 async function mintNft(owner) {
   let txResponse = await nftContract.safeMint(owner);
+  // read by the server before the transaction confirms
+  console.log(txResponse.hash);
 
   const txReceipt = await txResponse.wait();
   let nftId = txReceipt.logs[0].args[2];
@@ -4,9 +4,13 @@ import json
 import uuid
 import subprocess
 import sqlite3
+import threading
 import uuid
 import os
 
+from eth_account import Account
+from eth_account.messages import encode_defunct
+from eth_utils import keccak
 from flask import Flask, request, jsonify, send_file
 from flask_cors import CORS
 from together import Together
@@ -27,6 +31,10 @@ OLLAMA_CLIENT = Client(
     host="http://192.168.88.242:11434",
 )
 
+# text the owner signs to mint a story, the game reads the same file
+with open(os.path.join(os.path.dirname(__file__), "mint_message.txt")) as file:
+    MINT_MESSAGE = file.read().strip()
+
 WEBAPP = Flask(__name__)
 CORS(WEBAPP)
 
@@ -92,25 +100,67 @@ def create_story_nft():
 
     scenario = data.get("scenario", "")
     owner = data.get("owner", "")
+    attributes = [
+        {"trait_type": attribute["trait_type"], "value": attribute["value"]}
+        for attribute in data.get("attributes", [])
+        if isinstance(attribute, dict) and "trait_type" in attribute and "value" in attribute
+    ]
 
-    command = ["node", "mint.js", "mint", owner]
-    result = subprocess.run(command, capture_output=True, text=True).stdout.strip("\n")
+    # only the owner may ask for a story to be minted to them
+    story_hash = "0x" + keccak(text=scenario).hex()
+    if data.get("story_hash", "").lower() != story_hash:
+        return jsonify({"error": "story_hash does not match the scenario"}), 400
 
-    nft_id = int(result)
+    signer = recover_mint_signer(story_hash, owner, data.get("signature", ""))
+    if signer is None or signer.lower() != owner.lower():
+        return jsonify({"error": "signature is not from the owner"}), 403
 
-    metadata = create_nft_metadata_from_scenario(nft_id, scenario)
-    metadata_json = json.dumps(metadata, indent=4)
+    job_id = str(uuid.uuid4())
 
     with sqlite3.connect("database.db") as conn:
         cursor = conn.cursor()
 
         cursor.execute(
-            "INSERT INTO scenarios (nft_id, data) VALUES (?, ?)",
-            (nft_id, metadata_json),
+            "INSERT INTO mint_jobs (job_id, owner, status) VALUES (?, ?, 'pending')",
+            (job_id, owner),
         )
         conn.commit()
 
-        return jsonify({"nft_id": nft_id}), 200
+    # the transaction takes a while to confirm, clients poll /api/nft/status
+    threading.Thread(
+        target=run_mint_job, args=(job_id, owner, scenario, attributes)
+    ).start()
+
+    return jsonify({"job_id": job_id}), 202
+
+
+@WEBAPP.route("/api/nft/status/<job_id>", methods=["GET"])
+def get_mint_status(job_id):
+    with sqlite3.connect("database.db") as conn:
+        cursor = conn.cursor()
+
+        query = "SELECT status, tx_hash, nft_id, error FROM mint_jobs WHERE job_id = ?"
+        cursor.execute(query, (job_id,))
+
+        result = cursor.fetchone()
+        if not result:
+            return jsonify({"error": "Mint job not found"}), 404
+
+        status, tx_hash, nft_id, error = result
+        return (
+            jsonify(
+                {
+                    "status": status,
+                    "tx_hash": tx_hash,
+                    "nft_id": nft_id,
+                    "error": error,
+                    # once a transaction is sent another attempt could mint
+                    # the story twice
+                    "retryable": status == "failed" and tx_hash is None,
+                }
+            ),
+            200,
+        )
 
 
 @WEBAPP.route("/api/nft/<nft_id>", methods=["GET"])
@@ -131,6 +181,34 @@ def get_story_nft(nft_id):
         return jsonify(nft_response), 200
 
 
+@WEBAPP.route("/api/nft/owner/<owner>", methods=["GET"])
+def list_story_nfts(owner):
+    with sqlite3.connect("database.db") as conn:
+        cursor = conn.cursor()
+
+        # every story with metadata, newest first
+        query = """
+            SELECT nft_id, data FROM scenarios
+            WHERE lower(owner) = lower(?)
+            ORDER BY nft_id DESC
+        """
+        cursor.execute(query, (owner,))
+
+        nfts = []
+        for nft_id, data in cursor.fetchall():
+            metadata = json.loads(data)
+            nfts.append(
+                {
+                    "nft_id": nft_id,
+                    "name": metadata.get("name", ""),
+                    "image": metadata.get("image", ""),
+                    "url": f"https://kakuseinosekainokokujoninarudaikinonisemono.space/api/nft/{nft_id}",
+                }
+            )
+
+        return jsonify({"nfts": nfts}), 200
+
+
 # ---------
 # LangChain
 # ---------
@@ -158,7 +236,7 @@ STRUCTURED_LLM = TOGETHER_STRUCTURED_MODEL.with_structured_output(Scenario)
 # -----------------
 
 
-def create_nft_metadata_from_scenario(nft_id, scenario):
+def create_nft_metadata_from_scenario(nft_id, scenario, attributes=()):
     clean_scenario = remove_hide_and_show(scenario)
     sce = STRUCTURED_LLM.invoke("Process this scenario " + clean_scenario)
     print(sce)
@@ -170,12 +248,133 @@ def create_nft_metadata_from_scenario(nft_id, scenario):
         "poster": sce.poster,
         "image": f"https://kakuseinosekainokokujoninarudaikinonisemono.space/api/image/v2/{image_response['hash']}",
         "scenario": scenario,
+        "attributes": list(attributes),
         "version": 2,
     }
 
     return nft_response
 
 
+def mint_message(story_hash, owner):
+    return MINT_MESSAGE.replace("{story_hash}", story_hash).replace(
+        "{owner}", owner.lower()
+    )
+
+
+def recover_mint_signer(story_hash, owner, signature):
+    """
+    Address that signed the mint message with EIP-191 `personal_sign`, None
+    when the signature is malformed.
+    """
+
+    try:
+        message = encode_defunct(text=mint_message(story_hash, owner))
+        return Account.recover_message(message, signature=signature)
+    except Exception:
+        return None
+
+
+def run_mint_job(job_id, owner, scenario, attributes):
+    """
+    Mints a story NFT and records the transaction on its mint job.
+
+    mint.js prints the transaction hash once it is sent and the token id once
+    it is confirmed. Both are stored as soon as they are known; a job that
+    minted but could not store the story ends as `metadata_failed`.
+    """
+
+    nft_id = None
+
+    try:
+        command = ["node", "mint.js", "mint", owner]
+        process = subprocess.Popen(
+            command, stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True
+        )
+
+        tx_hash = process.stdout.readline().strip()
+        if tx_hash:
+            update_mint_job(job_id, tx_hash=tx_hash)
+
+        stdout, stderr = process.communicate()
+        if process.returncode != 0:
+            raise RuntimeError(stderr.strip() or "mint transaction failed")
+
+        nft_id = int(stdout.strip())
+        update_mint_job(job_id, nft_id=nft_id)
+
+        metadata = create_nft_metadata_from_scenario(nft_id, scenario, attributes)
+        metadata_json = json.dumps(metadata, indent=4)
+
+        with sqlite3.connect("database.db") as conn:
+            cursor = conn.cursor()
+
+            cursor.execute(
+                "INSERT INTO scenarios (nft_id, data, owner) VALUES (?, ?, ?)",
+                (nft_id, metadata_json, owner),
+            )
+            conn.commit()
+
+        update_mint_job(job_id, status="confirmed")
+    except Exception as error:
+        status = "failed" if nft_id is None else "metadata_failed"
+        update_mint_job(job_id, status=status, error=str(error))
+
+
+def update_mint_job(job_id, **fields):
+    assignments = ", ".join(f"{field} = ?" for field in fields)
+
+    with sqlite3.connect("database.db") as conn:
+        cursor = conn.cursor()
+
+        cursor.execute(
+            f"UPDATE mint_jobs SET {assignments} WHERE job_id = ?",
+            (*fields.values(), job_id),
+        )
+        conn.commit()
+
+
+def init_database():
+    with sqlite3.connect("database.db") as conn:
+        cursor = conn.cursor()
+
+        cursor.execute(
+            """
+            CREATE TABLE IF NOT EXISTS mint_jobs (
+                job_id TEXT PRIMARY KEY,
+                owner TEXT NOT NULL,
+                status TEXT NOT NULL,
+                tx_hash TEXT,
+                nft_id INTEGER,
+                error TEXT
+            )
+            """
+        )
+        cursor.execute(
+            """
+            CREATE TABLE IF NOT EXISTS scenarios (
+                nft_id INTEGER PRIMARY KEY,
+                data TEXT NOT NULL,
+                owner TEXT
+            )
+            """
+        )
+
+        # databases from before the gallery have no owners on their stories,
+        # the ones minted through mint jobs get theirs back
+        columns = [column[1] for column in cursor.execute("PRAGMA table_info(scenarios)")]
+        if "owner" not in columns:
+            cursor.execute("ALTER TABLE scenarios ADD COLUMN owner TEXT")
+        cursor.execute(
+            """
+            UPDATE scenarios SET owner = (
+                SELECT owner FROM mint_jobs WHERE mint_jobs.nft_id = scenarios.nft_id
+            )
+            WHERE owner IS NULL
+            """
+        )
+        conn.commit()
+
+
 def remove_hide_and_show(renpy_string):
     """
     Removes lines starting with "show" or "hide" from a Ren'Py scenario string.
@@ -274,4 +473,5 @@ def prompt_llm(prompt):
 # -----------
 
 if __name__ == "__main__":
+    init_database()
     WEBAPP.run(host="0.0.0.0", port=5000, debug=False)
@@ -5,7 +5,12 @@ use bevy_wasm_tasks::*;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use crate::API_ENDPOINT;
+use crate::{api_tasks::spawn_task, API_ENDPOINT};
+
+/// A reply taking longer is given up on, so a summary or forge waiting for it
+/// can start over; browsers time out fetches on their own
+#[cfg(not(target_arch = "wasm32"))]
+const LLM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(180);
 
 #[derive(Default)]
 pub struct LLMPlugin;
@@ -24,19 +29,25 @@ struct LLMResponse {
 pub enum LLMRequestType {
     Story,
     Text2ImagePrompt,
+    Summary,
+    ForgeCard,
 }
 
 #[derive(Event)]
 pub struct EventLLMRequest {
     pub prompt: String,
-    pub who: Option<String>,
     pub request_type: LLMRequestType,
 }
 
 #[derive(Event)]
 pub struct EventLLMResponse {
     pub response: String,
-    pub who: Option<String>,
+    pub request_type: LLMRequestType,
+}
+
+/// The request got no usable reply, whoever asked stops waiting for it
+#[derive(Event)]
+pub struct EventLLMFailed {
     pub request_type: LLMRequestType,
 }
 
@@ -44,6 +55,7 @@ impl Plugin for LLMPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<EventLLMRequest>()
             .add_event::<EventLLMResponse>()
+            .add_event::<EventLLMFailed>()
             .add_systems(Update, handle_llm_request);
     }
 }
@@ -51,58 +63,24 @@ impl Plugin for LLMPlugin {
 fn handle_llm_request(mut er_llm_request: EventReader<EventLLMRequest>, tasks: Tasks) {
     for er in er_llm_request.read() {
         let prompt = er.prompt.clone();
-        let who = er.who.clone();
         let request_type = er.request_type;
 
-        // TODO: DEDUP
-        #[cfg(not(target_arch = "wasm32"))]
-        tasks.spawn_tokio(move |ctx| async move {
-            let llm_request = LLMRequest {
-                prompt: prompt.clone(),
-            };
-
-            let llm_response = api_llm_request(llm_request).await;
-
-            if llm_response.is_ok() {
-                let llm_response = llm_response.unwrap();
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventLLMResponse {
-                        response: llm_response.clone(),
-                        who: who.clone(),
-                        request_type,
-                    };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            } else {
-                panic!("error: {:?}", llm_response.err());
-            }
-        });
-        #[cfg(target_arch = "wasm32")]
-        tasks.spawn_wasm(move |ctx| async move {
-            let llm_request = LLMRequest {
-                prompt: prompt.clone(),
-            };
-
-            let llm_response = api_llm_request(llm_request).await;
-
-            if llm_response.is_ok() {
-                let llm_response = llm_response.unwrap();
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventLLMResponse {
-                        response: llm_response.clone(),
-                        who: who.clone(),
+        spawn_task(
+            &tasks,
+            api_llm_request(LLMRequest { prompt }),
+            move |world, llm_response| match llm_response {
+                Ok(response) => {
+                    world.send_event(EventLLMResponse {
+                        response,
                         request_type,
-                    };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            } else {
-                panic!("error: {:?}", llm_response.err());
-            }
-        });
+                    });
+                }
+                Err(err) => {
+                    error!("llm request: {:?}", err);
+                    world.send_event(EventLLMFailed { request_type });
+                }
+            },
+        );
     }
 }
 
@@ -111,8 +89,10 @@ async fn api_llm_request(prompt: LLMRequest) -> Result<String> {
     let payload_json = serde_json::to_string(&prompt)?;
 
     let client = Client::new();
-    let response = client
-        .post(url)
+    let request = client.post(url);
+    #[cfg(not(target_arch = "wasm32"))]
+    let request = request.timeout(LLM_TIMEOUT);
+    let response = request
         .header("Content-Type", "application/json")
         .body(payload_json)
         .send()
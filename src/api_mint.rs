@@ -0,0 +1,399 @@
+use anyhow::Result;
+use bevy::prelude::*;
+use bevy_hui::prelude::*;
+use bevy_wasm_tasks::*;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::{
+    api_nft::EventPersistScenarioRequest, api_tasks::spawn_task, story_attributes::NftAttribute,
+    story_export::StoryExport, AppState, API_ENDPOINT,
+};
+
+/// Transactions on Soneium Minato, where story NFTs are minted
+const EXPLORER_TX_URL: &str = "https://soneium-minato.blockscout.com/tx/";
+
+/// Seconds between two status requests of a pending mint
+const MINT_POLL_SECONDS: f32 = 3.0;
+
+/// Failed status requests in a row before the mint is given up on
+const MAX_POLL_ERRORS: usize = 10;
+
+/// Polls the backend until a minted story is confirmed and keeps the game
+/// over screen up to date
+pub struct MintPlugin;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum MintStatus {
+    /// nothing minted this run
+    #[default]
+    Idle,
+    /// waiting for the signature and for the backend to accept the mint
+    Submitting,
+    /// transaction sent, not confirmed yet
+    Pending {
+        job_id: String,
+        tx_hash: Option<String>,
+    },
+    Confirmed {
+        nft_id: usize,
+        tx_hash: Option<String>,
+    },
+    /// minted, but the story could not be stored with the NFT
+    MetadataFailed {
+        nft_id: usize,
+        tx_hash: Option<String>,
+    },
+    /// failed before a transaction was sent, can be retried
+    Failed(String),
+    /// failed after the backend took the mint; a transaction may be on chain,
+    /// so another attempt could mint the story twice
+    FailedAfterSubmit {
+        reason: String,
+        tx_hash: Option<String>,
+    },
+}
+
+impl MintStatus {
+    /// Game over screen line
+    pub(crate) fn label(&self) -> String {
+        match self {
+            MintStatus::Idle => String::new(),
+            MintStatus::Submitting => "MINTING YOUR STORY ON BLOCKCHAIN...".to_string(),
+            MintStatus::Pending { .. } => "WAITING FOR THE TRANSACTION TO CONFIRM...".to_string(),
+            MintStatus::Confirmed { nft_id, .. } => {
+                format!("NFT MINTED TO YOUR WALLET. ID: {}", nft_id)
+            }
+            MintStatus::MetadataFailed { nft_id, .. } => {
+                format!("NFT MINTED, ITS STORY WAS NOT SAVED. ID: {}", nft_id)
+            }
+            MintStatus::Failed(reason) | MintStatus::FailedAfterSubmit { reason, .. } => {
+                format!("MINTING FAILED: {}", reason)
+            }
+        }
+    }
+
+    /// Transaction hash and its explorer page, once the transaction is sent
+    pub(crate) fn transaction(&self) -> String {
+        match self {
+            MintStatus::Pending {
+                tx_hash: Some(tx_hash),
+                ..
+            }
+            | MintStatus::Confirmed {
+                tx_hash: Some(tx_hash),
+                ..
+            }
+            | MintStatus::MetadataFailed {
+                tx_hash: Some(tx_hash),
+                ..
+            }
+            | MintStatus::FailedAfterSubmit {
+                tx_hash: Some(tx_hash),
+                ..
+            } => format!("TX {}\n{}{}", tx_hash, EXPLORER_TX_URL, tx_hash),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Mint of the story that just finished, kept so a failed mint can be retried
+/// from the game over screen
+#[derive(Resource)]
+pub(crate) struct MintJob {
+    pub status: MintStatus,
+    /// counts submissions, answers for an earlier one are dropped
+    pub attempt: u32,
+    story: Option<(StoryExport, Vec<NftAttribute>)>,
+    /// a status request is in flight
+    polling: bool,
+    poll_errors: usize,
+    poll_timer: Timer,
+}
+
+impl Default for MintJob {
+    fn default() -> Self {
+        MintJob {
+            status: MintStatus::Idle,
+            attempt: 0,
+            story: None,
+            polling: false,
+            poll_errors: 0,
+            poll_timer: Timer::from_seconds(MINT_POLL_SECONDS, TimerMode::Repeating),
+        }
+    }
+}
+
+impl MintJob {
+    /// Starts a new attempt for the story
+    pub(crate) fn start(&mut self, story: StoryExport, attributes: Vec<NftAttribute>) {
+        self.attempt += 1;
+        self.status = MintStatus::Submitting;
+        self.story = Some((story, attributes));
+        self.polling = false;
+        self.poll_errors = 0;
+        self.poll_timer.reset();
+    }
+
+    /// Request minting the same story again, only after a failure that sent no
+    /// transaction
+    pub(crate) fn retry_request(&self) -> Option<EventPersistScenarioRequest> {
+        let MintStatus::Failed(_) = self.status else {
+            return None;
+        };
+
+        self.story
+            .as_ref()
+            .map(|(story, attributes)| EventPersistScenarioRequest {
+                story: story.clone(),
+                attributes: attributes.clone(),
+            })
+    }
+}
+
+/// New status of a mint attempt; `None` when the backend could not be asked
+#[derive(Event)]
+pub(crate) struct EventMintStatus {
+    pub attempt: u32,
+    pub status: Option<MintStatus>,
+}
+
+/// Body of `/nft/status/<job_id>`
+#[derive(Deserialize, Debug)]
+struct MintStatusResponse {
+    status: String,
+    tx_hash: Option<String>,
+    nft_id: Option<usize>,
+    error: Option<String>,
+    /// set by the backend when the job failed without sending a transaction
+    #[serde(default)]
+    retryable: bool,
+}
+
+impl MintStatusResponse {
+    fn into_status(self, job_id: String) -> MintStatus {
+        match (self.status.as_str(), self.nft_id) {
+            ("confirmed", Some(nft_id)) => MintStatus::Confirmed {
+                nft_id,
+                tx_hash: self.tx_hash,
+            },
+            ("metadata_failed", Some(nft_id)) => MintStatus::MetadataFailed {
+                nft_id,
+                tx_hash: self.tx_hash,
+            },
+            ("failed", _) => {
+                let reason = self.error.unwrap_or("TRANSACTION FAILED".to_string());
+                match self.retryable {
+                    true => MintStatus::Failed(reason),
+                    false => MintStatus::FailedAfterSubmit {
+                        reason,
+                        tx_hash: self.tx_hash,
+                    },
+                }
+            }
+            _ => MintStatus::Pending {
+                job_id,
+                tx_hash: self.tx_hash,
+            },
+        }
+    }
+}
+
+impl Plugin for MintPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EventMintStatus>()
+            .init_resource::<MintJob>()
+            .add_systems(
+                Update,
+                (
+                    handle_mint_status,
+                    poll_mint_status,
+                    show_mint_status.run_if(in_state(AppState::Game)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn handle_mint_status(
+    mut er_mint_status: EventReader<EventMintStatus>,
+    mut mint_job: ResMut<MintJob>,
+) {
+    for event in er_mint_status.read() {
+        if event.attempt != mint_job.attempt {
+            continue;
+        }
+        mint_job.polling = false;
+
+        match &event.status {
+            Some(status) => {
+                mint_job.poll_errors = 0;
+                mint_job.status = status.clone();
+            }
+            None => {
+                mint_job.poll_errors += 1;
+                if let MintStatus::Pending { tx_hash, .. } = &mint_job.status
+                    && mint_job.poll_errors >= MAX_POLL_ERRORS
+                {
+                    mint_job.status = MintStatus::FailedAfterSubmit {
+                        reason: "LOST TRACK OF THE MINT".to_string(),
+                        tx_hash: tx_hash.clone(),
+                    };
+                }
+            }
+        }
+    }
+}
+
+fn poll_mint_status(time: Res<Time>, tasks: Tasks, mut mint_job: ResMut<MintJob>) {
+    let job_id = match &mint_job.status {
+        MintStatus::Pending { job_id, .. } if !mint_job.polling => job_id.clone(),
+        _ => return,
+    };
+
+    if !mint_job.poll_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    mint_job.polling = true;
+    let attempt = mint_job.attempt;
+
+    spawn_task(
+        &tasks,
+        async move {
+            match api_mint_status(job_id.clone()).await {
+                Ok(response) => Some(response.into_status(job_id)),
+                Err(err) => {
+                    warn!("mint status: {:?}", err);
+                    None
+                }
+            }
+        },
+        move |world, status| {
+            world.send_event(EventMintStatus { attempt, status });
+        },
+    );
+}
+
+/// Runs every frame rather than on status changes, the game over template may
+/// still be loading when the first status arrives
+fn show_mint_status(
+    mint_job: Res<MintJob>,
+    mut q_text_labels: Query<(&mut Text, &Tags)>,
+    mut q_nodes: Query<(Entity, &mut Node, &Tags)>,
+    mut style: Query<&mut HtmlStyle>,
+) {
+    if mint_job.status == MintStatus::Idle {
+        return;
+    }
+
+    for (mut text, tags) in q_text_labels.iter_mut() {
+        let value = match tags.get("marker").map(|marker| marker.as_str()) {
+            Some("text_minting_status") => mint_job.status.label(),
+            Some("text_mint_transaction") => mint_job.status.transaction(),
+            _ => continue,
+        };
+
+        if text.0 != value {
+            *text = Text::new(value);
+        }
+    }
+
+    let retry_display = match mint_job.retry_request() {
+        Some(_) => Display::Flex,
+        None => Display::None,
+    };
+    for (entity, mut node, tags) in q_nodes.iter_mut() {
+        if let Some(marker) = tags.get("marker")
+            && marker == "button_mint_retry"
+            && node.display != retry_display
+        {
+            node.display = retry_display;
+
+            if let Ok(mut style) = style.get_mut(entity) {
+                style.computed.node.display = node.display;
+            }
+        }
+    }
+}
+
+async fn api_mint_status(job_id: String) -> Result<MintStatusResponse> {
+    let url = format!("{}/nft/status/{}", API_ENDPOINT, job_id);
+
+    let client = Client::new();
+    let response = client.get(url).send().await?;
+    let response_text = response.text().await?;
+    let response: MintStatusResponse = serde_json::from_str(&response_text)?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(json: &str) -> MintStatus {
+        serde_json::from_str::<MintStatusResponse>(json)
+            .unwrap()
+            .into_status("job".to_string())
+    }
+
+    #[test]
+    fn test_mint_status() {
+        let pending = status(r#"{"status":"pending","tx_hash":null,"nft_id":null,"error":null}"#);
+        assert_eq!(
+            pending,
+            MintStatus::Pending {
+                job_id: "job".to_string(),
+                tx_hash: None
+            }
+        );
+        assert_eq!(pending.transaction(), "");
+
+        let confirmed =
+            status(r#"{"status":"confirmed","tx_hash":"0xabc","nft_id":7,"error":null}"#);
+        assert_eq!(confirmed.label(), "NFT MINTED TO YOUR WALLET. ID: 7");
+        assert_eq!(
+            confirmed.transaction(),
+            "TX 0xabc\nhttps://soneium-minato.blockscout.com/tx/0xabc"
+        );
+
+        let reverted = status(
+            r#"{"status":"failed","tx_hash":"0xabc","nft_id":null,"error":"reverted","retryable":false}"#,
+        );
+        assert_eq!(reverted.label(), "MINTING FAILED: reverted");
+        assert_eq!(
+            reverted.transaction(),
+            "TX 0xabc\nhttps://soneium-minato.blockscout.com/tx/0xabc"
+        );
+
+        let failed = status(
+            r#"{"status":"failed","tx_hash":null,"nft_id":null,"error":"no funds","retryable":true}"#,
+        );
+        assert_eq!(failed, MintStatus::Failed("no funds".to_string()));
+
+        let metadata_failed = status(
+            r#"{"status":"metadata_failed","tx_hash":"0xabc","nft_id":7,"error":"llm","retryable":false}"#,
+        );
+        assert_eq!(
+            metadata_failed,
+            MintStatus::MetadataFailed {
+                nft_id: 7,
+                tx_hash: Some("0xabc".to_string())
+            }
+        );
+
+        let mut mint_job = MintJob::default();
+        mint_job.start(StoryExport::new(vec![], vec![], 0), vec![]);
+        assert!(mint_job.retry_request().is_none());
+
+        mint_job.status = reverted;
+        assert!(mint_job.retry_request().is_none());
+
+        mint_job.status = metadata_failed;
+        assert!(mint_job.retry_request().is_none());
+
+        mint_job.status = failed;
+        assert!(mint_job.retry_request().is_some());
+    }
+}
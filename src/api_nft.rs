@@ -1,20 +1,26 @@
 use bevy::prelude::*;
 
 use anyhow::Result;
-use bevy_novel::{events::EventStartScenario, NovelText};
+use bevy_hui::prelude::*;
 use bevy_wasm_tasks::*;
-use renpy_parser::{
-    parse_scenario_from_string,
-    parsers::{ASTVec, AST},
-};
+use renpy_parser::parse_scenario_from_string;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    api_text2img::EventDownloadImageRequest, menu_game::EventRefreshUI, AppState, GameState,
-    API_ENDPOINT,
+    api_mint::{EventMintStatus, MintJob, MintStatus},
+    api_signature::{mint_message, story_hash, verify_signature},
+    api_tasks::spawn_task,
+    api_wallet::Wallet,
+    story_attributes::{attribute_lines, NftAttribute},
+    story_export::{EventReplayStory, StoryExport},
+    story_preload::EventPreloadScenario,
+    AppState, API_ENDPOINT,
 };
 
+/// Attribute lines on the replay title screen
+const MAX_TITLE_ATTRIBUTES: usize = 12;
+
 #[derive(Default)]
 pub struct NFTPlugin;
 
@@ -22,12 +28,8 @@ pub struct NFTPlugin;
 
 #[derive(Event)]
 pub struct EventPersistScenarioRequest {
-    pub scenario: Vec<AST>,
-}
-
-#[derive(Event)]
-pub struct EventPersistScenarioResponse {
-    pub nft_id: usize,
+    pub story: StoryExport,
+    pub attributes: Vec<NftAttribute>,
 }
 
 // Load NFT
@@ -42,216 +44,383 @@ pub struct EventLoadNFTResponse {
     pub nft: StoryNFT,
 }
 
+/// The story could not be downloaded or read, `error` is shown to the player
+#[derive(Event)]
+pub struct EventLoadNFTFailed {
+    pub error: String,
+}
+
+// List NFTs
+
+#[derive(Event)]
+pub struct EventListNFTRequest {
+    pub owner: String,
+}
+
+/// `nfts` is `None` when the listing could not be loaded
+#[derive(Event)]
+pub struct EventListNFTResponse {
+    pub owner: String,
+    pub nfts: Option<Vec<OwnedNFT>>,
+}
+
+/// Loaded story shown behind the replay title screen until the player starts it
+#[derive(Resource, Default)]
+struct PendingReplay(Option<StoryExport>);
+
+#[derive(Component)]
+pub struct ReplayTitleScreen {}
+
+#[derive(Component)]
+pub struct LoadErrorScreen {}
+
 impl Plugin for NFTPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<EventPersistScenarioRequest>()
-            .add_event::<EventPersistScenarioResponse>()
             .add_event::<EventLoadNFTRequest>()
             .add_event::<EventLoadNFTResponse>()
+            .add_event::<EventLoadNFTFailed>()
+            .add_event::<EventListNFTRequest>()
+            .add_event::<EventListNFTResponse>()
+            .init_resource::<PendingReplay>()
             .add_systems(
                 Update,
                 (
                     handle_persist_nft_request,
-                    handle_persist_nft_response,
                     handle_load_nft_request,
                     handle_load_nft_response,
+                    handle_list_nft_request,
                 ),
+            )
+            .add_systems(
+                Update,
+                show_load_nft_failed.run_if(in_state(AppState::Loading3)),
             );
-    }
-}
 
-fn handle_persist_nft_response(
-    mut er_llm_response: EventReader<EventPersistScenarioResponse>,
-    mut ew_refresh_ui: EventWriter<EventRefreshUI>,
-) {
-    for event in er_llm_response.read() {
-        ew_refresh_ui.write(EventRefreshUI::GameOver(event.nft_id));
+        #[cfg(target_arch = "wasm32")]
+        app.init_resource::<PendingSignatures>()
+            .add_systems(Update, handle_wallet_signature);
     }
 }
 
 fn handle_persist_nft_request(
     mut er_llm_request: EventReader<EventPersistScenarioRequest>,
     tasks: Tasks,
-    game_state: Res<GameState>,
+    wallet: Res<Wallet>,
+    mut mint_job: ResMut<MintJob>,
+    #[cfg(target_arch = "wasm32")] mut pending_signatures: ResMut<PendingSignatures>,
 ) {
     for er in er_llm_request.read() {
-        let scenario_string = format!("{}", ASTVec(&er.scenario));
-
-        // hack! serializing is currently broken: a duplicated scenario gets
-        // after first "game_mechanic "game over""
-
-        let parts = scenario_string
-            .split("game_mechanic \"game over\"")
-            .collect::<Vec<&str>>();
-
-        let scenario_string = parts[0].to_string();
-
-        let owner = game_state.wallet.address.clone();
+        mint_job.start(er.story.clone(), er.attributes.clone());
+
+        let Some(owner) = wallet.address.clone() else {
+            mint_job.status = MintStatus::Failed("CONNECT A WALLET FIRST".to_string());
+            continue;
+        };
+
+        let scenario = match er.story.to_json() {
+            Ok(scenario) => scenario,
+            Err(err) => {
+                error!("story export: {}", err);
+                mint_job.status = MintStatus::Failed("THE STORY COULD NOT BE SAVED".to_string());
+                continue;
+            }
+        };
+
+        let story_hash = story_hash(&scenario);
+        let message = mint_message(&story_hash, &owner);
+        let request = NFTPersistRequest {
+            scenario,
+            owner,
+            story_hash,
+            signature: String::new(),
+            attributes: er.attributes.clone(),
+        };
 
-        // TODO: DEDUP
         #[cfg(not(target_arch = "wasm32"))]
-        tasks.spawn_tokio(move |ctx| async move {
-            let llm_request = NFTPersistRequest {
-                scenario: scenario_string,
-                owner,
+        {
+            let Some(signer) = &wallet.signer else {
+                warn!(
+                    "{} cannot sign mint requests, set WALLET_PRIVATE_KEY to mint",
+                    request.owner
+                );
+                mint_job.status = MintStatus::Failed("THIS WALLET CANNOT SIGN".to_string());
+                continue;
             };
 
-            let llm_response = api_persist_story(llm_request).await;
-
-            if llm_response.is_ok() {
-                let nft_id = llm_response.unwrap();
-
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventPersistScenarioResponse { nft_id };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            } else {
-                panic!("error: {:?}", llm_response.err());
+            match signer.sign_message(&message) {
+                Ok(signature) => submit_signed_request(
+                    &tasks,
+                    &mut mint_job,
+                    &message,
+                    NFTPersistRequest {
+                        signature,
+                        ..request
+                    },
+                ),
+                Err(err) => {
+                    error!("signing mint request: {}", err);
+                    mint_job.status = MintStatus::Failed("SIGNING FAILED".to_string());
+                }
             }
-        });
+        }
+
+        // answered by `handle_wallet_signature`
         #[cfg(target_arch = "wasm32")]
-        tasks.spawn_wasm(move |ctx| async move {
-            let llm_request = NFTPersistRequest {
-                scenario: scenario_string,
-                owner,
-            };
+        {
+            crate::wasm::wallet_sign(&message, &request.owner);
+            pending_signatures.0.push_back((message, request));
+        }
+    }
+}
 
-            let llm_response = api_persist_story(llm_request).await;
+/// Mint requests waiting for the browser wallet, in the order it was asked to
+/// sign them
+#[cfg(target_arch = "wasm32")]
+#[derive(Resource, Default)]
+struct PendingSignatures(std::collections::VecDeque<(String, NFTPersistRequest)>);
 
-            if llm_response.is_ok() {
-                let nft_id = llm_response.unwrap();
+#[cfg(target_arch = "wasm32")]
+fn handle_wallet_signature(
+    tasks: Tasks,
+    mut pending_signatures: ResMut<PendingSignatures>,
+    mut mint_job: ResMut<MintJob>,
+) {
+    while let Some(signature) = crate::wasm::wallet_poll_signature() {
+        let Some((message, request)) = pending_signatures.0.pop_front() else {
+            continue;
+        };
+
+        if signature.is_empty() {
+            mint_job.status = MintStatus::Failed("SIGNATURE REJECTED".to_string());
+            continue;
+        }
+
+        submit_signed_request(
+            &tasks,
+            &mut mint_job,
+            &message,
+            NFTPersistRequest {
+                signature,
+                ..request
+            },
+        );
+    }
+}
+
+/// Checked here too so a wallet that signed with another account fails before
+/// the backend is asked
+fn submit_signed_request(
+    tasks: &Tasks,
+    mint_job: &mut MintJob,
+    message: &str,
+    request: NFTPersistRequest,
+) {
+    if !verify_signature(message, &request.signature, &request.owner) {
+        error!("mint request not signed by {}", request.owner);
+        mint_job.status = MintStatus::Failed("SIGNED BY ANOTHER ACCOUNT".to_string());
+        return;
+    }
 
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventPersistScenarioResponse { nft_id };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            } else {
-                panic!("error: {:?}", llm_response.err());
+    let attempt = mint_job.attempt;
+
+    spawn_task(tasks, api_persist_story(request), move |world, job_id| {
+        let status = match job_id {
+            Ok(job_id) => MintStatus::Pending {
+                job_id,
+                tx_hash: None,
+            },
+            Err(err) => {
+                error!("mint request: {:?}", err);
+                MintStatus::Failed("THE SERVER DID NOT ACCEPT THE MINT".to_string())
             }
+        };
+        world.send_event(EventMintStatus {
+            attempt,
+            status: Some(status),
         });
-    }
+    });
 }
 
 fn handle_load_nft_request(
-    mut er_llm_request: EventReader<EventLoadNFTRequest>,
+    mut er_load_nft_request: EventReader<EventLoadNFTRequest>,
     tasks: Tasks,
-    // game_state: Res<GameState>,
 ) {
-    for er in er_llm_request.read() {
+    for er in er_load_nft_request.read() {
         let url = er.url.clone();
 
-        // TODO: DEDUP
-        #[cfg(not(target_arch = "wasm32"))]
-        tasks.spawn_tokio(move |ctx| async move {
-            let nft_response = api_load_nft(url).await;
-
-            if nft_response.is_ok() {
-                let nft = nft_response.unwrap();
-
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventLoadNFTResponse { nft };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            } else {
-                panic!("error: {:?}", nft_response.err());
-            }
-        });
-        #[cfg(target_arch = "wasm32")]
-        tasks.spawn_wasm(move |ctx| async move {
-            let nft_response = api_load_nft(url).await;
-
-            if nft_response.is_ok() {
-                let nft = nft_response.unwrap();
-
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventLoadNFTResponse { nft };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            } else {
-                panic!("error: {:?}", nft_response.err());
-            }
-        });
+        spawn_task(
+            &tasks,
+            async move {
+                let nft = api_load_nft(url.clone()).await;
+                (url, nft)
+            },
+            |world, (url, nft)| match nft {
+                Ok(nft) => {
+                    world.send_event(EventLoadNFTResponse { nft });
+                }
+                Err(err) => {
+                    error!("loading nft {}: {:?}", url, err);
+                    world.send_event(EventLoadNFTFailed {
+                        error: "THE STORY COULD NOT BE DOWNLOADED".to_string(),
+                    });
+                }
+            },
+        );
+    }
+}
+
+fn handle_list_nft_request(
+    mut er_list_nft_request: EventReader<EventListNFTRequest>,
+    tasks: Tasks,
+) {
+    for er in er_list_nft_request.read() {
+        let owner = er.owner.clone();
+
+        spawn_task(
+            &tasks,
+            async move {
+                let nfts = api_list_nfts(&owner).await;
+                (owner, nfts)
+            },
+            |world, (owner, nfts)| {
+                let nfts = match nfts {
+                    Ok(nfts) => Some(nfts),
+                    Err(err) => {
+                        error!("listing nfts of {}: {:?}", owner, err);
+                        None
+                    }
+                };
+                world.send_event(EventListNFTResponse { owner, nfts });
+            },
+        );
     }
 }
 
 fn handle_load_nft_response(
+    mut commands: Commands,
+    mut html_funcs: HtmlFunctions,
     mut app_state: ResMut<NextState<AppState>>,
-    mut ew_start_scenario: EventWriter<EventStartScenario>,
     mut er_load_nft_response: EventReader<EventLoadNFTResponse>,
-    mut ew_download_image: EventWriter<EventDownloadImageRequest>,
-    mut q_novel_text: Query<(Entity, &mut Node, &NovelText)>,
+    mut ew_preload_scenario: EventWriter<EventPreloadScenario>,
+    mut ew_load_nft_failed: EventWriter<EventLoadNFTFailed>,
+    mut pending_replay: ResMut<PendingReplay>,
+    asset_server: Res<AssetServer>,
 ) {
     for event in er_load_nft_response.read() {
-        // quickfix due to fauly serialization
-        let scenario_string = event.nft.scenario.clone().replace(" \"...\"", "");
-
-        println!("scenario_string: {}", scenario_string);
-
-        let result = parse_scenario_from_string(&scenario_string, "_");
-        if let Ok((scenario, _errors)) = result {
-            for node in scenario.iter() {
-                match node {
-                    AST::Label(_, _, asts, _) => {
-                        for child_node in asts.iter() {
-                            if let AST::Scene(_, filename, _) = child_node {
-                                if let Some(filename) = filename {
-                                    if filename.ends_with(".jpeg") {
-                                        ew_download_image.write(EventDownloadImageRequest {
-                                            filename: filename.clone(),
-                                        });
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    AST::Scene(_, filename, _) => {
-                        if let Some(filename) = filename {
-                            if filename.ends_with(".jpeg") {
-                                ew_download_image.write(EventDownloadImageRequest {
-                                    filename: filename.clone(),
-                                });
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            }
-
+        if let Ok(story) = StoryExport::from_json(&event.nft.scenario) {
             app_state.set(AppState::NovelPlayer);
-            ew_start_scenario.write(EventStartScenario {
-                ast: scenario.clone(),
-            });
+            pending_replay.0 = Some(story);
+            show_replay_title(&mut commands, &mut html_funcs, &event.nft, &asset_server);
+            continue;
+        }
 
-            for (_, mut node, _) in q_novel_text.iter_mut() {
-                node.left = Val::Percent(20.0);
-                node.margin = UiRect::new(Val::Px(20.0), Val::Px(0.0), Val::Px(0.0), Val::Px(0.0));
+        // stories minted before the export format are Ren'Py text
+        match parse_scenario_from_string(&event.nft.scenario, "_") {
+            Ok((scenario, _errors)) => {
+                app_state.set(AppState::NovelPlayer);
+                ew_preload_scenario.write(EventPreloadScenario { ast: scenario });
+            }
+            Err(err) => {
+                error!("nft scenario: {:?}", err);
+                ew_load_nft_failed.write(EventLoadNFTFailed {
+                    error: "THE STORY IS IN A FORMAT THIS GAME CANNOT READ".to_string(),
+                });
             }
-        } else {
-            panic!("could not load scenario {:?}", result);
         }
     }
 }
 
+/// Player mode has nothing to fall back to, the error stays on screen
+fn show_load_nft_failed(
+    mut commands: Commands,
+    mut er_load_nft_failed: EventReader<EventLoadNFTFailed>,
+    q_error: Query<(Entity, &LoadErrorScreen)>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in er_load_nft_failed.read() {
+        for (entity, _) in q_error.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        commands.spawn((
+            HtmlNode(asset_server.load("menu/load_error.html")),
+            TemplateProperties::default().with("error", &event.error),
+            LoadErrorScreen {},
+            Name::new("load error screen"),
+        ));
+    }
+}
+
+/// Title screen of a replay: the story name and the attributes it was minted
+/// with
+fn show_replay_title(
+    commands: &mut Commands,
+    html_funcs: &mut HtmlFunctions,
+    nft: &StoryNFT,
+    asset_server: &AssetServer,
+) {
+    let lines = attribute_lines(&nft.attributes);
+    let mut properties = TemplateProperties::default()
+        .with("title", &nft.name.to_uppercase())
+        .with("description", &nft.description);
+    for i in 0..MAX_TITLE_ATTRIBUTES {
+        properties = properties
+            .with(
+                &format!("attribute_{}", i),
+                lines.get(i).map(|line| line.as_str()).unwrap_or_default(),
+            )
+            .with(
+                &format!("attribute_{}_display", i),
+                if i < lines.len() { "flex" } else { "none" },
+            );
+    }
+
+    commands.spawn((
+        HtmlNode(asset_server.load("menu/replay_title.html")),
+        properties,
+        ReplayTitleScreen {},
+        Name::new("replay title screen"),
+    ));
+
+    html_funcs.register(
+        "replay_start",
+        |In(_),
+         mut commands: Commands,
+         mut pending_replay: ResMut<PendingReplay>,
+         mut ew_replay_story: EventWriter<EventReplayStory>,
+         q_title: Query<(Entity, &ReplayTitleScreen)>| {
+            for (entity, _) in q_title.iter() {
+                commands.entity(entity).despawn();
+            }
+
+            if let Some(story) = pending_replay.0.take() {
+                ew_replay_story.write(EventReplayStory { story });
+            }
+        },
+    );
+}
+
+/// `signature` is the owner's EIP-191 signature of `mint_message`, so the
+/// backend can check the owner asked for the mint
 #[derive(Serialize)]
 struct NFTPersistRequest {
     pub scenario: String,
     pub owner: String,
+    /// Keccak-256 of `scenario`
+    pub story_hash: String,
+    pub signature: String,
+    /// ERC-721 metadata attributes
+    pub attributes: Vec<NftAttribute>,
 }
 
 #[derive(Deserialize, Debug)]
 struct NFTPersistResponse {
-    pub nft_id: usize,
+    /// polled on `/nft/status` until the transaction confirms
+    pub job_id: String,
 }
 
-async fn api_persist_story(prompt: NFTPersistRequest) -> Result<usize> {
+async fn api_persist_story(prompt: NFTPersistRequest) -> Result<String> {
     let url = format!("{}/nft/create", API_ENDPOINT);
     let payload_json = serde_json::to_string(&prompt)?;
 
@@ -265,22 +434,61 @@ async fn api_persist_story(prompt: NFTPersistRequest) -> Result<usize> {
     let response_text = response.text().await?;
     let response: NFTPersistResponse = serde_json::from_str(&response_text)?;
 
-    Ok(response.nft_id)
+    Ok(response.job_id)
 }
 
 #[derive(Deserialize, Debug)]
 pub struct StoryNFT {
     pub description: String,
-    pub image: String,
     pub name: String,
-    pub poster: String,
     pub scenario: String,
+    /// missing on stories minted before attributes were sent
+    #[serde(default)]
+    pub attributes: Vec<NftAttribute>,
 }
 
-async fn api_load_nft(url: String) -> Result<StoryNFT> {
+/// Story NFT in the listing of an owner
+#[derive(Deserialize, Clone, Debug)]
+pub struct OwnedNFT {
+    pub nft_id: usize,
+    pub name: String,
+    /// poster URL
+    pub image: String,
+    /// metadata URL, loaded with `EventLoadNFTRequest`
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct NFTListResponse {
+    pub nfts: Vec<OwnedNFT>,
+}
+
+/// `NFT_LIST_ENDPOINT` points the listing at a local stub on native, see
+/// `contrib/backend/gallery_stub.py`
+fn nft_list_endpoint() -> String {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Ok(endpoint) = std::env::var("NFT_LIST_ENDPOINT") {
+        return endpoint;
+    }
+
+    format!("{}/nft/owner", API_ENDPOINT)
+}
+
+async fn api_list_nfts(owner: &str) -> Result<Vec<OwnedNFT>> {
+    let url = format!("{}/{}", nft_list_endpoint(), owner);
+
     let client = Client::new();
     let response = client.get(url).send().await?;
     let response_text = response.text().await?;
+    let response: NFTListResponse = serde_json::from_str(&response_text)?;
+
+    Ok(response.nfts)
+}
+
+async fn api_load_nft(url: String) -> Result<StoryNFT> {
+    let client = Client::new();
+    let response = client.get(url).send().await?.error_for_status()?;
+    let response_text = response.text().await?;
     let nft: StoryNFT = serde_json::from_str(&response_text)?;
 
     Ok(nft)
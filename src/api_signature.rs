@@ -0,0 +1,153 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use sha3::{Digest, Keccak256};
+
+pub(crate) fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+/// Keccak-256 of the story JSON sent to `/nft/create`, `0x`-prefixed hex
+pub(crate) fn story_hash(scenario: &str) -> String {
+    format!("0x{}", hex::encode(keccak256(scenario.as_bytes())))
+}
+
+/// Template of the text signed to mint a story, the backend reads the same file
+const MINT_MESSAGE: &str = include_str!("../contrib/backend/mint_message.txt");
+
+/// Text the wallet signs to mint a story, binding the story to its owner so
+/// the backend can refuse to mint to an address that did not sign
+pub(crate) fn mint_message(story_hash: &str, owner: &str) -> String {
+    MINT_MESSAGE
+        .trim_end()
+        .replace("{story_hash}", story_hash)
+        .replace("{owner}", &owner.to_lowercase())
+}
+
+/// EIP-191 `personal_sign` digest, what wallets sign for a text message
+fn eip191_hash(message: &str) -> [u8; 32] {
+    let prefix = format!("\x19Ethereum Signed Message:\n{}", message.len());
+    keccak256(&[prefix.as_bytes(), message.as_bytes()].concat())
+}
+
+/// Lowercase `0x` address: the last 20 bytes of the Keccak-256 of the
+/// uncompressed public key
+fn address_of(key: &VerifyingKey) -> String {
+    let point = key.to_encoded_point(false);
+    format!(
+        "0x{}",
+        hex::encode(&keccak256(&point.as_bytes()[1..])[12..])
+    )
+}
+
+/// Address that produced a 65 byte `r || s || v` signature of `message`
+pub(crate) fn recover_address(message: &str, signature: &str) -> Result<String> {
+    let bytes = hex::decode(signature.trim_start_matches("0x"))?;
+    if bytes.len() != 65 {
+        return Err(anyhow!("signature is {} bytes, expected 65", bytes.len()));
+    }
+
+    // wallets report `v` as 27 or 28, some as the raw recovery id
+    let v = bytes[64];
+    let recovery_id = RecoveryId::from_byte(if v >= 27 { v - 27 } else { v })
+        .ok_or_else(|| anyhow!("invalid recovery id {}", v))?;
+    let signature = Signature::from_slice(&bytes[..64])?;
+
+    let key = VerifyingKey::recover_from_prehash(&eip191_hash(message), &signature, recovery_id)?;
+    Ok(address_of(&key))
+}
+
+/// Whether `address` signed `message`, addresses compared ignoring their
+/// EIP-55 checksum case
+pub(crate) fn verify_signature(message: &str, signature: &str, address: &str) -> bool {
+    recover_address(message, signature)
+        .map(|signer| signer.eq_ignore_ascii_case(address))
+        .unwrap_or(false)
+}
+
+/// Wallet key held by the game itself, to mint without a browser wallet when
+/// testing
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) struct LocalSigner {
+    key: SigningKey,
+}
+
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+impl LocalSigner {
+    pub(crate) fn from_hex(private_key: &str) -> Result<Self> {
+        let bytes = hex::decode(private_key.trim().trim_start_matches("0x"))?;
+        Ok(LocalSigner {
+            key: SigningKey::from_slice(&bytes)?,
+        })
+    }
+
+    pub(crate) fn address(&self) -> String {
+        address_of(self.key.verifying_key())
+    }
+
+    /// `personal_sign` of `message`, as a browser wallet would return it
+    pub(crate) fn sign_message(&self, message: &str) -> Result<String> {
+        let (signature, recovery_id) = self.key.sign_prehash_recoverable(&eip191_hash(message))?;
+
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(27 + recovery_id.to_byte());
+        Ok(format!("0x{}", hex::encode(bytes)))
+    }
+}
+
+/// Keeps the private key out of logs
+impl fmt::Debug for LocalSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalSigner")
+            .field("address", &self.address())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// first account of the Hardhat and Anvil test mnemonic, never use it on
+    /// a real network
+    const TEST_KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const TEST_ADDRESS: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    #[test]
+    fn test_sign_mint_request() {
+        let signer = LocalSigner::from_hex(TEST_KEY).unwrap();
+        assert!(signer.address().eq_ignore_ascii_case(TEST_ADDRESS));
+
+        let hash = story_hash("label start:");
+        assert_eq!(
+            hash,
+            "0x4ccbb2b4d5a88906f4584f5608159c279352651e3bef7e02f599139d0d592fd9"
+        );
+
+        let message = mint_message(&hash, TEST_ADDRESS);
+        assert_eq!(
+            message,
+            "Mint story 0x4ccbb2b4d5a88906f4584f5608159c279352651e3bef7e02f599139d0d592fd9 to 0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266"
+        );
+
+        let signature = signer.sign_message(&message).unwrap();
+        assert_eq!(
+            signature,
+            "0x7073d02e76528bc6a906aac3f2edcbeb8cb4900ce9813cf5b23635c43c1a061048e1cf213d8fe74498617a958c1c119c410fa6def1703fc9e58350cad1bd7ef01c"
+        );
+
+        assert!(verify_signature(&message, &signature, TEST_ADDRESS));
+        assert!(!verify_signature(
+            &mint_message(&story_hash("label end:"), TEST_ADDRESS),
+            &signature,
+            TEST_ADDRESS
+        ));
+        assert!(!verify_signature(
+            &message,
+            &signature,
+            "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+        ));
+        assert!(recover_address(&message, "0x1234").is_err());
+    }
+}
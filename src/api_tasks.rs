@@ -0,0 +1,37 @@
+use std::future::Future;
+
+use bevy::prelude::*;
+use bevy_wasm_tasks::*;
+
+/// `Send` natively, where tasks run on tokio; browser futures aren't `Send`
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// Runs `task` in the background, on tokio natively and on the browser's
+/// executor on the web, then hands its output to `finish` on the main thread
+pub(crate) fn spawn_task<T, Fut, F>(tasks: &Tasks, task: Fut, finish: F)
+where
+    T: Send + 'static,
+    Fut: Future<Output = T> + MaybeSend + 'static,
+    F: FnOnce(&mut World, T) + Send + 'static,
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    tasks.spawn_tokio(move |ctx| async move {
+        let output = task.await;
+        ctx.run_on_main_thread(move |ctx| finish(ctx.world, output))
+            .await;
+    });
+    #[cfg(target_arch = "wasm32")]
+    tasks.spawn_wasm(move |ctx| async move {
+        let output = task.await;
+        ctx.run_on_main_thread(move |ctx| finish(ctx.world, output))
+            .await;
+    });
+}
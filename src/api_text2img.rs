@@ -1,21 +1,43 @@
 use anyhow::anyhow;
 use anyhow::Result;
+use bevy::asset::io::{
+    memory::{Dir, MemoryAssetReader, Value},
+    AssetSource, AssetSourceBuilder,
+};
 use bevy::prelude::*;
 use bevy_wasm_tasks::*;
-use image::{self, DynamicImage};
+use image::{self, DynamicImage, ImageFormat};
 use reqwest::Client;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::io::Cursor;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use url::{form_urlencoded, Url};
 
-use crate::API_ENDPOINT;
+use crate::{api_tasks::spawn_task, API_ENDPOINT};
+
+/// Asset source images made at runtime are served from, `generated://...`
+pub const GENERATED_ASSET_SOURCE: &str = "generated";
 
 #[derive(Default)]
 pub struct Text2ImagePlugin;
 
+/// What a generated image is for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Text2ImageRequestType {
+    /// chapter background
+    Scene,
+    /// portrait of a player-made character, by name
+    Portrait(String),
+    /// art of a card forged in the shop
+    CardArt,
+}
+
 #[derive(Event)]
 pub struct EventText2ImageRequest {
     pub prompt: String,
+    pub request_type: Text2ImageRequestType,
 }
 
 #[derive(Event)]
@@ -29,10 +51,67 @@ pub struct EventDownloadImageResponse {
     pub filename: String,
 }
 
+/// Sent instead of a response when the image could not be downloaded
+#[derive(Event)]
+pub struct EventDownloadImageFailed {
+    pub filename: String,
+}
+
 #[derive(Event)]
 pub struct EventText2ImageResponse {
     pub image: DynamicImage,
     pub filename: String,
+    pub request_type: Text2ImageRequestType,
+}
+
+/// Sent instead of a response when the image could not be generated
+#[derive(Event)]
+pub struct EventText2ImageFailed {
+    pub request_type: Text2ImageRequestType,
+}
+
+/// In-memory directory behind the `generated://` asset source, so generated
+/// images can be loaded by path like any file asset
+#[derive(Resource, Clone, Default)]
+pub struct GeneratedAssets {
+    dir: Dir,
+    /// the same bytes by path, `Dir` doesn't hand them back
+    bytes: Arc<RwLock<HashMap<String, Arc<Vec<u8>>>>>,
+}
+
+impl GeneratedAssets {
+    /// Must be registered before `AssetPlugin` is added
+    pub fn source(&self) -> AssetSourceBuilder {
+        let dir = self.dir.clone();
+        AssetSource::build().with_reader(move || Box::new(MemoryAssetReader { root: dir.clone() }))
+    }
+
+    /// Stores the image as PNG and returns its asset path
+    pub fn insert_image(&self, path: &str, image: &DynamicImage) -> Result<String> {
+        let mut bytes: Vec<u8> = vec![];
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+
+        Ok(self.insert_png(path, bytes))
+    }
+
+    /// Stores already encoded PNG bytes and returns their asset path
+    pub fn insert_png(&self, path: &str, bytes: Vec<u8>) -> String {
+        let bytes = Arc::new(bytes);
+        self.dir
+            .insert_asset(Path::new(path), Value::Vec(bytes.clone()));
+        if let Ok(mut stored) = self.bytes.write() {
+            stored.insert(path.to_string(), bytes);
+        }
+
+        format!("{}://{}", GENERATED_ASSET_SOURCE, path)
+    }
+
+    /// Bytes stored at `path`, without the source prefix
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn read(&self, path: &str) -> Option<Vec<u8>> {
+        let stored = self.bytes.read().ok()?;
+        stored.get(path).map(|bytes| bytes.to_vec())
+    }
 }
 
 #[derive(Deserialize)]
@@ -44,8 +123,10 @@ impl Plugin for Text2ImagePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<EventText2ImageRequest>()
             .add_event::<EventText2ImageResponse>()
+            .add_event::<EventText2ImageFailed>()
             .add_event::<EventDownloadImageRequest>()
             .add_event::<EventDownloadImageResponse>()
+            .add_event::<EventDownloadImageFailed>()
             .add_systems(Update, (handle_text_2_image_request, handle_download_image));
     }
 }
@@ -61,39 +142,19 @@ fn handle_download_image(
         );
         let filename = er.filename.clone();
 
-        // TODO: DEDUP
-        #[cfg(not(target_arch = "wasm32"))]
-        tasks.spawn_tokio(|ctx| async move {
-            let image = download_and_load_image(url.as_ref()).await;
-
-            if image.is_ok() {
-                ctx.run_on_main_thread(move |ctx| {
-                    let world: &mut World = ctx.world;
-
-                    world.send_event(EventDownloadImageResponse {
-                        image: image.unwrap(),
-                        filename: filename.clone(),
-                    });
-                })
-                .await;
-            }
-        });
-        #[cfg(target_arch = "wasm32")]
-        tasks.spawn_wasm(|ctx| async move {
-            let image = download_and_load_image(url.as_ref()).await;
-
-            if image.is_ok() {
-                ctx.run_on_main_thread(move |ctx| {
-                    let world: &mut World = ctx.world;
-
-                    world.send_event(EventDownloadImageResponse {
-                        image: image.unwrap(),
-                        filename: filename.clone(),
-                    });
-                })
-                .await;
-            }
-        });
+        spawn_task(
+            &tasks,
+            async move { download_and_load_image(url.as_ref()).await },
+            move |world, image| match image {
+                Ok(image) => {
+                    world.send_event(EventDownloadImageResponse { image, filename });
+                }
+                Err(err) => {
+                    warn!("downloading {}: {:?}", filename, err);
+                    world.send_event(EventDownloadImageFailed { filename });
+                }
+            },
+        );
     }
 }
 
@@ -103,69 +164,42 @@ fn handle_text_2_image_request(
 ) {
     for er in er_text_2_image_request.read() {
         let prompt = er.prompt.clone();
-
-        // TODO: DEDUP
-        #[cfg(not(target_arch = "wasm32"))]
-        tasks.spawn_tokio(|ctx| async move {
-            let url = format!("{}/image/v2", API_ENDPOINT);
-            let mut url = Url::parse(&url).unwrap();
-
-            let encoded_prompt =
-                form_urlencoded::byte_serialize(prompt.as_bytes()).collect::<String>();
-            url.query_pairs_mut().append_pair("prompt", &encoded_prompt);
-
-            let image_hash = generate_image(url.as_ref()).await;
-            if image_hash.is_err() {
-                return;
-            }
-            let filename = image_hash.unwrap();
-            let url = format!("{}/image/v2/{}", API_ENDPOINT, filename);
-            let image = download_and_load_image(url.as_ref()).await;
-
-            if image.is_ok() {
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventText2ImageResponse {
-                        image: image.unwrap(),
-                        filename,
-                    };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            }
-        });
-        #[cfg(target_arch = "wasm32")]
-        tasks.spawn_wasm(|ctx| async move {
-            let url = format!("{}/image/v2", API_ENDPOINT);
-            let mut url = Url::parse(&url).unwrap();
-
-            let encoded_prompt =
-                form_urlencoded::byte_serialize(prompt.as_bytes()).collect::<String>();
-            url.query_pairs_mut().append_pair("prompt", &encoded_prompt);
-
-            let image_hash = generate_image(url.as_ref()).await;
-            if image_hash.is_err() {
-                return;
-            }
-            let filename = image_hash.unwrap();
-            let url = format!("{}/image/v2/{}", API_ENDPOINT, filename);
-            let image = download_and_load_image(url.as_ref()).await;
-
-            if image.is_ok() {
-                ctx.run_on_main_thread(move |ctx| {
-                    let event_response = EventText2ImageResponse {
-                        image: image.unwrap(),
+        let request_type = er.request_type.clone();
+
+        spawn_task(
+            &tasks,
+            async move { generate_and_load_image(&prompt).await },
+            move |world, generated| match generated {
+                Ok((filename, image)) => {
+                    world.send_event(EventText2ImageResponse {
+                        image,
                         filename,
-                    };
-                    let world: &mut World = ctx.world;
-                    world.send_event(event_response);
-                })
-                .await;
-            }
-        });
+                        request_type,
+                    });
+                }
+                Err(err) => {
+                    warn!("text to image: {:?}", err);
+                    world.send_event(EventText2ImageFailed { request_type });
+                }
+            },
+        );
     }
 }
 
+/// Generated image and the hash it is served under
+async fn generate_and_load_image(prompt: &str) -> Result<(String, DynamicImage)> {
+    let mut url = Url::parse(&format!("{}/image/v2", API_ENDPOINT))?;
+
+    let encoded_prompt = form_urlencoded::byte_serialize(prompt.as_bytes()).collect::<String>();
+    url.query_pairs_mut().append_pair("prompt", &encoded_prompt);
+
+    let filename = generate_image(url.as_ref()).await?;
+    let url = format!("{}/image/v2/{}", API_ENDPOINT, filename);
+    let image = download_and_load_image(url.as_ref()).await?;
+
+    Ok((filename, image))
+}
+
 async fn generate_image(url: &str) -> Result<String> {
     let client = Client::new();
     let response = client.get(url).send().await?;
@@ -175,12 +209,12 @@ async fn generate_image(url: &str) -> Result<String> {
     Ok(response.hash)
 }
 
-async fn download_and_load_image(url: &str) -> Result<DynamicImage> {
+pub(crate) async fn download_and_load_image(url: &str) -> Result<DynamicImage> {
     let response = reqwest::get(url).await?;
 
     if response.status().is_success() {
         let image_bytes = response.bytes().await?;
-        let img: DynamicImage = image::load(Cursor::new(image_bytes), image::ImageFormat::Jpeg)?;
+        let img: DynamicImage = image::load_from_memory(&image_bytes)?;
         Ok(img)
     } else {
         Err(anyhow!(format!(
@@ -0,0 +1,258 @@
+use bevy::prelude::*;
+use bevy_hui::prelude::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::api_signature::LocalSigner;
+
+/// Reads the player's wallet: an EIP-1193 provider (`window.ethereum`) on the
+/// web, the `WALLET_PRIVATE_KEY` or `WALLET_ADDRESS` environment variables on
+/// native
+pub struct WalletPlugin;
+
+/// Account stories are minted to
+#[derive(Resource, Default, Debug)]
+pub(crate) struct Wallet {
+    /// `0x` followed by 40 hex digits
+    pub address: Option<String>,
+    /// waiting for the player to approve the connection
+    pub connecting: bool,
+    /// signs mint requests on native, a `WALLET_ADDRESS` alone cannot mint
+    #[cfg(not(target_arch = "wasm32"))]
+    pub signer: Option<LocalSigner>,
+}
+
+impl Wallet {
+    /// Line shown by the wallet widget on the main menu
+    pub(crate) fn status(&self) -> String {
+        match (&self.address, self.connecting) {
+            (Some(address), _) => format!("WALLET {}", short_address(address)),
+            (None, true) => "CONNECTING WALLET...".to_string(),
+            (None, false) => "NO WALLET CONNECTED".to_string(),
+        }
+    }
+
+    pub(crate) fn button_label(&self) -> &'static str {
+        match self.address {
+            Some(_) => "DISCONNECT WALLET",
+            None => "CONNECT WALLET",
+        }
+    }
+}
+
+pub(crate) fn is_valid_address(address: &str) -> bool {
+    address.len() == 42
+        && address.starts_with("0x")
+        && address[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn short_address(address: &str) -> String {
+    match address.len() > 10 {
+        true => format!("{}...{}", &address[..6], &address[address.len() - 4..]),
+        false => address.to_string(),
+    }
+}
+
+#[derive(Event)]
+pub(crate) struct EventWalletConnect {}
+
+#[derive(Event)]
+pub(crate) struct EventWalletDisconnect {}
+
+/// Account the wallet reports, `None` once it is disconnected
+#[derive(Event)]
+pub(crate) struct EventWalletChanged {
+    pub address: Option<String>,
+}
+
+impl Plugin for WalletPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EventWalletConnect>()
+            .add_event::<EventWalletDisconnect>()
+            .add_event::<EventWalletChanged>()
+            .init_resource::<Wallet>()
+            .add_systems(Startup, start_wallet)
+            .add_systems(
+                Update,
+                (
+                    handle_wallet_connect,
+                    handle_wallet_disconnect,
+                    poll_wallet,
+                    handle_wallet_changed,
+                    refresh_wallet_widget.run_if(resource_changed::<Wallet>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn handle_wallet_changed(
+    mut er_wallet_changed: EventReader<EventWalletChanged>,
+    mut wallet: ResMut<Wallet>,
+) {
+    for event in er_wallet_changed.read() {
+        wallet.address = event
+            .address
+            .clone()
+            .filter(|address| is_valid_address(address));
+        wallet.connecting = false;
+
+        match &wallet.address {
+            Some(address) => info!("wallet connected: {}", address),
+            None => info!("wallet disconnected"),
+        }
+    }
+}
+
+/// Main menu texts tagged `text_wallet_status` and `text_wallet_button`
+fn refresh_wallet_widget(mut q_text_labels: Query<(&mut Text, &Tags)>, wallet: Res<Wallet>) {
+    for (mut text, tags) in q_text_labels.iter_mut() {
+        match tags.get("marker").map(|marker| marker.as_str()) {
+            Some("text_wallet_status") => *text = Text::new(wallet.status()),
+            Some("text_wallet_button") => *text = Text::new(wallet.button_label()),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn configured_address() -> Option<String> {
+    let address = std::env::var("WALLET_ADDRESS").ok()?;
+    if !is_valid_address(&address) {
+        warn!("WALLET_ADDRESS is not a wallet address: {}", address);
+        return None;
+    }
+
+    Some(address)
+}
+
+/// Local signer for testing, its address wins over `WALLET_ADDRESS`
+#[cfg(not(target_arch = "wasm32"))]
+fn configured_signer() -> Option<LocalSigner> {
+    let private_key = std::env::var("WALLET_PRIVATE_KEY").ok()?;
+    match LocalSigner::from_hex(&private_key) {
+        Ok(signer) => Some(signer),
+        Err(err) => {
+            warn!("WALLET_PRIVATE_KEY is not a private key: {}", err);
+            None
+        }
+    }
+}
+
+/// Reads the environment again, returns the address to connect
+#[cfg(not(target_arch = "wasm32"))]
+fn configure_wallet(wallet: &mut Wallet) -> Option<String> {
+    wallet.signer = configured_signer();
+    wallet
+        .signer
+        .as_ref()
+        .map(LocalSigner::address)
+        .or_else(configured_address)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn start_wallet(
+    mut ew_wallet_changed: EventWriter<EventWalletChanged>,
+    mut wallet: ResMut<Wallet>,
+) {
+    if let Some(address) = configure_wallet(&mut wallet) {
+        ew_wallet_changed.write(EventWalletChanged {
+            address: Some(address),
+        });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn handle_wallet_connect(
+    mut er_wallet_connect: EventReader<EventWalletConnect>,
+    mut ew_wallet_changed: EventWriter<EventWalletChanged>,
+    mut wallet: ResMut<Wallet>,
+) {
+    for _ in er_wallet_connect.read() {
+        let address = configure_wallet(&mut wallet);
+        if address.is_none() {
+            warn!("set WALLET_PRIVATE_KEY or WALLET_ADDRESS to connect a wallet");
+        }
+        ew_wallet_changed.write(EventWalletChanged { address });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn handle_wallet_disconnect(
+    mut er_wallet_disconnect: EventReader<EventWalletDisconnect>,
+    mut ew_wallet_changed: EventWriter<EventWalletChanged>,
+    mut wallet: ResMut<Wallet>,
+) {
+    for _ in er_wallet_disconnect.read() {
+        wallet.signer = None;
+        ew_wallet_changed.write(EventWalletChanged { address: None });
+    }
+}
+
+/// Native wallets only change through connect and disconnect
+#[cfg(not(target_arch = "wasm32"))]
+fn poll_wallet() {}
+
+#[cfg(target_arch = "wasm32")]
+fn start_wallet(mut ew_wallet_changed: EventWriter<EventWalletChanged>) {
+    crate::wasm::wallet_listen();
+
+    // the page hosting the game may have connected already
+    let address = crate::wasm::user_connected_wallet();
+    if is_valid_address(&address) {
+        ew_wallet_changed.write(EventWalletChanged {
+            address: Some(address),
+        });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn handle_wallet_connect(
+    mut er_wallet_connect: EventReader<EventWalletConnect>,
+    mut wallet: ResMut<Wallet>,
+) {
+    for _ in er_wallet_connect.read() {
+        wallet.connecting = true;
+        crate::wasm::wallet_connect();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn handle_wallet_disconnect(mut er_wallet_disconnect: EventReader<EventWalletDisconnect>) {
+    for _ in er_wallet_disconnect.read() {
+        crate::wasm::wallet_disconnect();
+    }
+}
+
+/// Accounts reported by the provider since the last frame; an empty one means
+/// the wallet was disconnected or the request rejected
+#[cfg(target_arch = "wasm32")]
+fn poll_wallet(mut ew_wallet_changed: EventWriter<EventWalletChanged>) {
+    while let Some(account) = crate::wasm::wallet_poll() {
+        ew_wallet_changed.write(EventWalletChanged {
+            address: Some(account).filter(|account| !account.is_empty()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wallet_status() {
+        let address = "0x52908400098527886E0F7030069857D2E4169EE7";
+        assert!(is_valid_address(address));
+        assert!(!is_valid_address(&address[..41]));
+        assert!(!is_valid_address(&address.replace('E', "Z")));
+
+        let mut wallet = Wallet::default();
+        assert_eq!(wallet.status(), "NO WALLET CONNECTED");
+
+        wallet.connecting = true;
+        assert_eq!(wallet.status(), "CONNECTING WALLET...");
+
+        wallet.address = Some(address.to_string());
+        assert_eq!(wallet.status(), "WALLET 0x5290...9EE7");
+        assert_eq!(wallet.button_label(), "DISCONNECT WALLET");
+    }
+}
@@ -0,0 +1,329 @@
+//! Checks scenarios before they reach players:
+//!
+//! ```sh
+//! cargo run --bin rpy-lint                  # every .rpy under assets/plot
+//! cargo run --bin rpy-lint -- assets/plot/act2.rpy --mechanic "card play dice"
+//! ```
+//!
+//! Exits with status 1 when a scenario has problems, so it can run in CI.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{anyhow, Result};
+use renpy_parser::{parse_scenario_from_string, parsers::AST};
+
+use kakuseinosekainokokujoninarudaikinonisemono::{
+    story_mechanics::MechanicTable,
+    story_script::{find_label_index, ScriptCommand},
+    story_template::{PromptVariable, TemplateContext, TemplateValue},
+};
+
+const SCENARIO_DIR: &str = "assets/plot";
+
+const SCENE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+struct Lint {
+    mechanics: MechanicTable,
+    context: TemplateContext,
+}
+
+impl Lint {
+    /// Mechanics are the ones `main.rs` registers plus the `extra` ones mods
+    /// add with `--mechanic`
+    fn new(extra: &[String], fragments: HashMap<String, String>) -> Self {
+        let mechanics = MechanicTable::with_builtins(extra);
+
+        let mut context = TemplateContext::with_fragments(fragments);
+        for variable in PromptVariable::ALL {
+            let name = variable.name();
+            match variable.is_list() {
+                true => context.set(name, TemplateValue::list(vec![name.to_string()], " ")),
+                false => context.set_text(name, name),
+            }
+        }
+
+        Lint { mechanics, context }
+    }
+
+    fn check_mechanic(&self, statement: &str) -> Result<()> {
+        self.mechanics.parse(statement).map(|_| ())
+    }
+
+    /// Problems found in one scenario, each prefixed with the label it is in
+    fn check_scenario(&self, path: &Path) -> Result<Vec<String>> {
+        let source = fs::read_to_string(path)?;
+        let (ast, errors) = parse_scenario_from_string(&source, &path.to_string_lossy())
+            .map_err(|err| anyhow!("{:?}", err))?;
+
+        let mut problems = errors
+            .iter()
+            .map(|error| format!("parse error: {:?}", error))
+            .collect::<Vec<String>>();
+
+        let scene_dir = path.parent().unwrap_or(Path::new("."));
+        let labels = flatten_labels(&ast);
+
+        for label in labels.iter() {
+            for node in label.nodes.iter() {
+                let problem = match node {
+                    AST::GameMechanic(_, statement) => match ScriptCommand::parse(statement) {
+                        Some(Ok(ScriptCommand::Jump(_, target))) => {
+                            match find_label_index(&ast, &target) {
+                                Some(_) => Ok(()),
+                                None => Err(anyhow!("jump to unknown label `{}`", target)),
+                            }
+                        }
+                        Some(Ok(ScriptCommand::Set(..))) => Ok(()),
+                        Some(Err(err)) => Err(err),
+                        None => self.check_mechanic(statement),
+                    },
+                    AST::LLMGenerate(_, _, Some(prompt)) => self
+                        .context
+                        .render(prompt)
+                        .map(|_| ())
+                        .map_err(|err| anyhow!("llm_generate prompt: {}", err)),
+                    AST::Scene(_, Some(image), _) => match scene_exists(scene_dir, image) {
+                        true => Ok(()),
+                        false => Err(anyhow!("scene image `{}` not found", image)),
+                    },
+                    AST::Jump(_, target, _) => match find_label_index(&ast, target) {
+                        Some(_) => Ok(()),
+                        None => Err(anyhow!("jump to unknown label `{}`", target)),
+                    },
+                    _ => Ok(()),
+                };
+
+                if let Err(err) = problem {
+                    problems.push(format!("label {}: {}", label.name, err));
+                }
+            }
+        }
+
+        for name in unreachable_labels(&labels) {
+            problems.push(format!("label {}: unreachable", name));
+        }
+
+        Ok(problems)
+    }
+}
+
+/// Label with the statements directly inside it, nested labels come after
+/// their parent in scenario order
+struct LabelBlock<'a> {
+    name: &'a str,
+    nodes: Vec<&'a AST>,
+}
+
+fn flatten_labels(ast: &[AST]) -> Vec<LabelBlock<'_>> {
+    let mut labels = vec![];
+    for node in ast {
+        if let AST::Label(_, name, children, _) = node {
+            labels.push(LabelBlock {
+                name,
+                nodes: children
+                    .iter()
+                    .filter(|child| !matches!(child, AST::Label(..)))
+                    .collect(),
+            });
+            labels.extend(flatten_labels(children));
+        }
+    }
+    labels
+}
+
+impl LabelBlock<'_> {
+    /// Labels the block can continue to; a block that doesn't end with an
+    /// unconditional jump falls through to the next label
+    fn exits(&self) -> (Vec<String>, bool) {
+        let mut targets = vec![];
+        let mut falls_through = true;
+
+        for node in self.nodes.iter() {
+            match node {
+                AST::Jump(_, target, _) => {
+                    targets.push(target.clone());
+                    falls_through = false;
+                }
+                AST::GameMechanic(_, statement) => {
+                    if let Some(Ok(ScriptCommand::Jump(condition, target))) =
+                        ScriptCommand::parse(statement)
+                    {
+                        falls_through &= condition.is_some();
+                        targets.push(target);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        (targets, falls_through)
+    }
+}
+
+/// The scenario starts at `start`, or at its first label
+fn unreachable_labels<'a>(labels: &[LabelBlock<'a>]) -> Vec<&'a str> {
+    let Some(entry) = labels
+        .iter()
+        .position(|label| label.name == "start")
+        .or((!labels.is_empty()).then_some(0))
+    else {
+        return vec![];
+    };
+
+    let mut reached = BTreeSet::new();
+    let mut queue = vec![entry];
+    while let Some(i) = queue.pop() {
+        if !reached.insert(i) {
+            continue;
+        }
+
+        let (targets, falls_through) = labels[i].exits();
+        queue.extend(
+            targets
+                .iter()
+                .filter_map(|target| labels.iter().position(|label| label.name == target)),
+        );
+        if falls_through && i + 1 < labels.len() {
+            queue.push(i + 1);
+        }
+    }
+
+    labels
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !reached.contains(i))
+        .map(|(_, label)| label.name)
+        .collect()
+}
+
+/// Scene names may leave out the image extension
+fn scene_exists(dir: &Path, image: &str) -> bool {
+    if image.contains("://") {
+        return true;
+    }
+
+    dir.join(image).is_file()
+        || SCENE_EXTENSIONS
+            .iter()
+            .any(|extension| dir.join(format!("{}.{}", image, extension)).is_file())
+}
+
+fn scenarios(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            paths.extend(scenarios(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "rpy") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn run() -> Result<bool> {
+    let mut mechanics = vec![];
+    let mut paths = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mechanic" => mechanics.push(
+                args.next()
+                    .ok_or_else(|| anyhow!("--mechanic needs a name"))?,
+            ),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        paths = scenarios(Path::new(SCENARIO_DIR))?;
+    }
+
+    let fragments = fs::read_to_string(Path::new(SCENARIO_DIR).join("fragments.json"))?;
+    let lint = Lint::new(&mechanics, serde_json::from_str(&fragments)?);
+
+    let mut clean = true;
+    for path in paths.iter() {
+        let problems = lint
+            .check_scenario(path)
+            .unwrap_or_else(|err| vec![err.to_string()]);
+
+        for problem in problems.iter() {
+            eprintln!("{}: {}", path.display(), problem);
+        }
+        clean &= problems.is_empty();
+    }
+
+    println!("checked {} scenarios", paths.len());
+    Ok(clean)
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("rpy-lint: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENARIO: &str = r#"label start:
+    jump chapter1
+
+label orphan:
+    "Nobody jumps here."
+
+label chapter1:
+    game_mechanic "if score < 50 jump bad_end"
+    game_mechanic "card play narrative characters 2"
+    game_mechanic "game over"
+
+label bad_end:
+    "The end."
+"#;
+
+    #[test]
+    fn test_unreachable_labels() {
+        let (ast, _) = parse_scenario_from_string(SCENARIO, "test.rpy").unwrap();
+        let labels = flatten_labels(&ast);
+
+        assert_eq!(unreachable_labels(&labels), vec!["orphan"]);
+    }
+
+    #[test]
+    fn test_shipped_scenarios_are_clean() {
+        let fragments = fs::read_to_string(Path::new(SCENARIO_DIR).join("fragments.json")).unwrap();
+        let lint = Lint::new(&[], serde_json::from_str(&fragments).unwrap());
+
+        for path in scenarios(Path::new(SCENARIO_DIR)).unwrap() {
+            let problems = lint.check_scenario(&path).unwrap();
+            assert!(problems.is_empty(), "{}: {:?}", path.display(), problems);
+        }
+    }
+
+    #[test]
+    fn test_check_mechanic() {
+        let lint = Lint::new(&["card play dice".to_string()], HashMap::new());
+
+        assert!(lint
+            .check_mechanic("card play narrative characters\" 2")
+            .is_ok());
+        assert!(lint.check_mechanic("card play dice 3").is_ok());
+        assert!(lint.check_mechanic("card play narative setting").is_err());
+        assert!(lint.context.render("{PROMPT} {PLOT TWIST}").is_ok());
+        assert!(lint.context.render("{SETTNG}").is_err());
+    }
+}
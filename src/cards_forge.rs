@@ -0,0 +1,429 @@
+use ab_glyph::{FontRef, PxScale};
+use anyhow::{anyhow, bail, Result};
+use bevy::prelude::*;
+use image::{imageops, imageops::FilterType, DynamicImage, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_circle_mut, draw_text_mut, text_size};
+
+use crate::{
+    api_llm::{EventLLMFailed, EventLLMRequest, EventLLMResponse, LLMRequestType},
+    api_text2img::{
+        EventText2ImageFailed, EventText2ImageRequest, EventText2ImageResponse, GeneratedAssets,
+        Text2ImageRequestType,
+    },
+    cards_game::{NarrativeCard, VNCard, VNCardMetadata},
+    menu_game::{EventRefreshUI, EventRenderUI},
+    story_format::parse_json_reply,
+    GameState,
+};
+
+/// Owned cards listed in the forge menu
+pub(crate) const MAX_FORGE_SLOTS: usize = 8;
+
+/// Blank narrative card, the generated art goes where the picture is on
+/// cards made by `contrib/cards`
+const CARD_FRAME: &[u8] = include_bytes!("../assets/narrative-cards/frame.png");
+const ART_X: i64 = 2;
+const ART_Y: i64 = 2;
+const ART_WIDTH: u32 = 460;
+const ART_HEIGHT: u32 = 360;
+
+/// Card text is written below the art, laid out like `contrib/cards`
+const CARD_FONT: &[u8] = include_bytes!("../assets/font.ttf");
+const TEXT_X: i32 = 20;
+const TEXT_WIDTH: u32 = 424;
+const TEXT_BOTTOM: i32 = 660;
+const PRICE_CENTER: (i32, i32) = (50, 320);
+const PRICE_RADIUS: i32 = 30;
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const GRAY: Rgba<u8> = Rgba([108, 117, 125, 255]);
+const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+const CARD_TYPES: [&str; 3] = ["setting", "plot twist", "conflict"];
+
+const FORGE_PROMPT: &str = r#"
+You are designer of a narrative card game.
+Invent one new narrative card that combines the two cards below.
+Do not include any instructions or explanations.
+Respond only with JSON in this format:
+{"name": "card name", "card_type": "setting, plot twist or conflict", "genre": "genre", "effect": "what the card does to the story", "flavor_text": "short quote", "price": 50}
+"#;
+
+/// Two owned narrative cards picked for forging and the card being made of them
+#[derive(Resource, Default)]
+pub(crate) struct CardForge {
+    /// positions in `GameState::collected_deck`
+    pub selected: Vec<usize>,
+    /// card written by the LLM, waiting for its art
+    pending: Option<NarrativeCard>,
+    forging: bool,
+}
+
+impl CardForge {
+    fn finish(&mut self) {
+        self.pending = None;
+        self.forging = false;
+    }
+
+    pub(crate) fn toggle(&mut self, position: usize) {
+        match self.selected.iter().position(|p| *p == position) {
+            Some(i) => {
+                self.selected.remove(i);
+            }
+            None if self.selected.len() < 2 => self.selected.push(position),
+            None => (),
+        }
+    }
+}
+
+/// Owned narrative cards that can go into the forge, with their deck positions
+pub(crate) fn forge_candidates(game_state: &GameState) -> Vec<(usize, String)> {
+    game_state
+        .collected_deck
+        .iter()
+        .enumerate()
+        .filter(|(_, card)| card.metadata.is_narrative())
+        .filter_map(|(position, card)| card.metadata.name().map(|name| (position, name)))
+        .take(MAX_FORGE_SLOTS)
+        .collect()
+}
+
+pub(crate) fn forge_menu(game_state: &GameState, card_forge: &CardForge) -> EventRenderUI {
+    EventRenderUI::Forge(
+        forge_candidates(game_state)
+            .into_iter()
+            .map(|(position, name)| (name, card_forge.selected.contains(&position)))
+            .collect(),
+    )
+}
+
+/// Asks the LLM for a card made of the two selected ones
+pub(crate) fn start_forging(
+    game_state: &GameState,
+    card_forge: &mut CardForge,
+    ew_llm_request: &mut EventWriter<EventLLMRequest>,
+) -> bool {
+    if card_forge.forging || card_forge.selected.len() != 2 {
+        return false;
+    }
+
+    let cards = card_forge
+        .selected
+        .iter()
+        .filter_map(|position| game_state.collected_deck.get(*position))
+        .map(|card| {
+            format!(
+                "{} ({}): {}",
+                card.metadata.name().unwrap_or_default(),
+                card.metadata.card_type().unwrap_or_default(),
+                card.metadata.effect().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<String>>();
+
+    ew_llm_request.write(EventLLMRequest {
+        prompt: format!("{}Cards: ```{}```.", FORGE_PROMPT, cards.join(" ")),
+        request_type: LLMRequestType::ForgeCard,
+    });
+    card_forge.forging = true;
+
+    true
+}
+
+/// Reads the invented card; type must be one the narrative game deals and the
+/// price is kept within what the shop sells
+pub(crate) fn parse_forged_card(response: &str) -> Result<NarrativeCard> {
+    let mut card: NarrativeCard = parse_json_reply(response)?;
+
+    card.name = card.name.trim().to_string();
+    if card.name.is_empty() {
+        bail!("forged card has no name");
+    }
+
+    card.card_type = card.card_type.trim().to_lowercase();
+    if !CARD_TYPES.contains(&card.card_type.as_str()) {
+        bail!("forged card has unknown type `{}`", card.card_type);
+    }
+
+    card.price = (card.price.clamp(10, 200) / 5) * 5;
+
+    Ok(card)
+}
+
+/// Puts the art into the picture slot of a blank card and writes the card
+/// text under it
+pub(crate) fn composite_card(card: &NarrativeCard, art: &DynamicImage) -> Result<DynamicImage> {
+    let font = FontRef::try_from_slice(CARD_FONT).map_err(|err| anyhow!("card font: {}", err))?;
+
+    let mut frame = image::load_from_memory(CARD_FRAME)?.to_rgba8();
+    let art = imageops::resize(&art.to_rgba8(), ART_WIDTH, ART_HEIGHT, FilterType::Triangle);
+    imageops::overlay(&mut frame, &art, ART_X, ART_Y);
+
+    let price = card.price.to_string();
+    let price_scale = PxScale::from(26.0);
+    let (price_width, price_height) = text_size(price_scale, &font, &price);
+    draw_filled_circle_mut(&mut frame, PRICE_CENTER, PRICE_RADIUS, RED);
+    draw_text_mut(
+        &mut frame,
+        WHITE,
+        PRICE_CENTER.0 - price_width as i32 / 2,
+        PRICE_CENTER.1 - price_height as i32 / 2,
+        price_scale,
+        &font,
+        &price,
+    );
+
+    let y = ART_Y as i32 + ART_HEIGHT as i32 + 12;
+    let y = draw_paragraph(&mut frame, &font, WHITE, 30.0, &card.name, y);
+    let y = draw_paragraph(
+        &mut frame,
+        &font,
+        GRAY,
+        22.0,
+        &format!("{} | {}", card.card_type, card.genre),
+        y,
+    );
+    let y = draw_paragraph(&mut frame, &font, WHITE, 22.0, &card.effect, y + 10);
+    draw_paragraph(&mut frame, &font, GRAY, 20.0, &card.flavor_text, y + 10);
+
+    Ok(DynamicImage::ImageRgba8(frame))
+}
+
+/// Writes `text` wrapped to the card width, returns where the next paragraph
+/// starts; lines that would run off the card are left out
+fn draw_paragraph(
+    frame: &mut RgbaImage,
+    font: &FontRef,
+    color: Rgba<u8>,
+    size: f32,
+    text: &str,
+    mut y: i32,
+) -> i32 {
+    let scale = PxScale::from(size);
+    let line_height = (size * 1.2) as i32;
+
+    for line in wrap_text(font, scale, text, TEXT_WIDTH) {
+        if y + line_height > TEXT_BOTTOM {
+            break;
+        }
+        draw_text_mut(frame, color, TEXT_X, y, scale, font, &line);
+        y += line_height;
+    }
+
+    y
+}
+
+/// Splits `text` into lines no wider than `max_width`, a single word longer
+/// than that gets a line of its own
+fn wrap_text(font: &FontRef, scale: PxScale, text: &str, max_width: u32) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if text_size(scale, font, &format!("{} {}", line, word)).0 <= max_width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+
+    lines
+}
+
+pub(crate) fn handle_forge_llm_response(
+    mut er_llm_response: EventReader<EventLLMResponse>,
+    mut ew_text_2_image_request: EventWriter<EventText2ImageRequest>,
+    mut ew_refresh_ui: EventWriter<EventRefreshUI>,
+    mut card_forge: ResMut<CardForge>,
+) {
+    for event in er_llm_response.read() {
+        if !matches!(event.request_type, LLMRequestType::ForgeCard) {
+            continue;
+        }
+
+        match parse_forged_card(&event.response) {
+            Ok(card) => {
+                ew_text_2_image_request.write(EventText2ImageRequest {
+                    prompt: format!(
+                        "{}. {} Image style: {}, realistic.",
+                        card.name, card.effect, card.genre
+                    ),
+                    request_type: Text2ImageRequestType::CardArt,
+                });
+                ew_refresh_ui.write(EventRefreshUI::ForgeStatus(format!(
+                    "painting {}",
+                    card.name
+                )));
+                card_forge.pending = Some(card);
+            }
+            Err(err) => {
+                warn!("forged card rejected: {}", err);
+                card_forge.finish();
+                ew_refresh_ui.write(EventRefreshUI::ForgeStatus("the forge failed".to_string()));
+            }
+        }
+    }
+}
+
+/// The two forged cards leave the player's cards and the new card takes their
+/// place
+pub(crate) fn handle_forge_art_response(
+    mut er_text_2_image_response: EventReader<EventText2ImageResponse>,
+    mut ew_refresh_ui: EventWriter<EventRefreshUI>,
+    mut card_forge: ResMut<CardForge>,
+    mut game_state: ResMut<GameState>,
+    generated_assets: Res<GeneratedAssets>,
+) {
+    for event in er_text_2_image_response.read() {
+        if event.request_type != Text2ImageRequestType::CardArt {
+            continue;
+        }
+
+        let Some(card) = card_forge.pending.take() else {
+            continue;
+        };
+        card_forge.finish();
+
+        let filename = composite_card(&card, &event.image).and_then(|image| {
+            generated_assets.insert_image(&format!("cards/{}.png", event.filename), &image)
+        });
+
+        let filename = match filename {
+            Ok(filename) => filename,
+            Err(err) => {
+                error!("forged card art: {}", err);
+                ew_refresh_ui.write(EventRefreshUI::ForgeStatus("the forge failed".to_string()));
+                continue;
+            }
+        };
+
+        let mut selected = std::mem::take(&mut card_forge.selected);
+        selected.sort_unstable();
+        for position in selected.into_iter().rev() {
+            if position < game_state.collected_deck.len() {
+                game_state.collected_deck.remove(position);
+            }
+        }
+
+        let index = game_state
+            .game_deck
+            .iter()
+            .chain(game_state.collected_deck.iter())
+            .filter(|card| card.metadata.is_narrative())
+            .filter_map(|card| card.metadata.index())
+            .max()
+            .unwrap_or_default()
+            + 1;
+
+        let forged_card = VNCard {
+            filename,
+            metadata: VNCardMetadata::Narrative(
+                index,
+                card.card_type,
+                card.genre,
+                card.name.clone(),
+                card.effect,
+                card.price,
+            ),
+        };
+
+        game_state.collected_deck.push(forged_card);
+
+        ew_refresh_ui.write(EventRefreshUI::ForgeStatus(format!("forged {}", card.name)));
+    }
+}
+
+/// The selected cards stay with the player, they can be forged again
+pub(crate) fn handle_forge_llm_failed(
+    mut er_llm_failed: EventReader<EventLLMFailed>,
+    mut ew_refresh_ui: EventWriter<EventRefreshUI>,
+    mut card_forge: ResMut<CardForge>,
+) {
+    for event in er_llm_failed.read() {
+        if !matches!(event.request_type, LLMRequestType::ForgeCard) || !card_forge.forging {
+            continue;
+        }
+
+        card_forge.finish();
+        ew_refresh_ui.write(EventRefreshUI::ForgeStatus("the forge failed".to_string()));
+    }
+}
+
+/// The selected cards stay with the player, they can be forged again
+pub(crate) fn handle_forge_art_failed(
+    mut er_text_2_image_failed: EventReader<EventText2ImageFailed>,
+    mut ew_refresh_ui: EventWriter<EventRefreshUI>,
+    mut card_forge: ResMut<CardForge>,
+) {
+    for event in er_text_2_image_failed.read() {
+        if event.request_type != Text2ImageRequestType::CardArt || card_forge.pending.is_none() {
+            continue;
+        }
+
+        card_forge.finish();
+        ew_refresh_ui.write(EventRefreshUI::ForgeStatus("the forge failed".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_forged_card() {
+        let response = r#"Here is your card:
+{"name": " Storm Over Kherson ", "card_type": "Setting", "genre": "fantasy",
+"effect": "The city drowns.", "flavor_text": "Rain.", "price": 333}"#;
+
+        let card = parse_forged_card(response).unwrap();
+
+        assert_eq!(card.name, "Storm Over Kherson");
+        assert_eq!(card.card_type, "setting");
+        assert_eq!(card.price, 200);
+
+        assert!(parse_forged_card(
+            r#"{"name": "X", "card_type": "filler", "genre": "", "effect": "", "flavor_text": "", "price": 10}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_forge_selects_two_cards() {
+        let mut card_forge = CardForge::default();
+        card_forge.toggle(3);
+        card_forge.toggle(5);
+        card_forge.toggle(7);
+        assert_eq!(card_forge.selected, vec![3, 5]);
+
+        card_forge.toggle(3);
+        assert_eq!(card_forge.selected, vec![5]);
+    }
+
+    #[test]
+    fn test_composite_card_keeps_frame_size() {
+        let card = parse_forged_card(
+            r#"{"name": "Storm Over Kherson", "card_type": "setting", "genre": "fantasy",
+            "effect": "The city drowns.", "flavor_text": "Rain.", "price": 50}"#,
+        )
+        .unwrap();
+        let art = DynamicImage::new_rgb8(1024, 1024);
+        let forged = composite_card(&card, &art).unwrap();
+
+        assert_eq!((forged.width(), forged.height()), (464, 676));
+    }
+
+    #[test]
+    fn test_wrap_text_fits_card_width() {
+        let font = FontRef::try_from_slice(CARD_FONT).unwrap();
+        let scale = PxScale::from(22.0);
+        let text = "Change the time period of the story, altering its context and challenges.";
+
+        let lines = wrap_text(&font, scale, text, TEXT_WIDTH);
+
+        assert!(lines.len() > 1);
+        assert_eq!(lines.join(" "), text);
+        for line in lines.iter() {
+            assert!(text_size(scale, &font, line).0 <= TEXT_WIDTH);
+        }
+    }
+}
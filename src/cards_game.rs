@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt;
 
 use anyhow::Result;
@@ -30,15 +31,17 @@ pub struct NarrativeCard {
 pub struct PsychosisCard {
     pub name: String,
     pub description: String,
+    pub debuff: PsychosisDebuff,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct CharacterCard {
     pub name: String,
     pub description: String,
-    pub flavor_text: String,
     pub price: u16,
-    pub filename: String,
+    /// emotion -> sprite in `character-cards/`
+    #[serde(default)]
+    pub expressions: BTreeMap<String, String>,
 }
 
 #[derive(Deserialize, Asset, TypePath, Deref, DerefMut)]
@@ -58,7 +61,8 @@ pub(crate) enum VNCardMetadata {
     Narrative(usize, String, String, String, String, u16),
     // index, name, description, price
     Character(usize, String, String, u16),
-    Psychosis(usize, String, String),
+    // index, name, description, debuff
+    Psychosis(usize, String, String, PsychosisDebuff),
 }
 
 impl Default for VNCardMetadata {
@@ -90,7 +94,7 @@ impl VNCardMetadata {
         if let VNCardMetadata::Character(_, _, _, _) = self {
             return Some("character".to_string());
         }
-        if let VNCardMetadata::Psychosis(_, _, _) = self {
+        if let VNCardMetadata::Psychosis(_, _, _, _) = self {
             return Some("psychosis".to_string());
         }
         None
@@ -107,6 +111,9 @@ impl VNCardMetadata {
         if let VNCardMetadata::Narrative(_index, _card_type, _genre, _name, effect, _price) = self {
             return Some(effect.clone());
         }
+        if let VNCardMetadata::Psychosis(_index, _name, description, _debuff) = self {
+            return Some(description.clone());
+        }
         None
     }
 
@@ -117,6 +124,9 @@ impl VNCardMetadata {
         if let VNCardMetadata::Character(_, name, _description, _price) = self {
             return Some(name.clone());
         }
+        if let VNCardMetadata::Psychosis(_index, name, _description, _debuff) = self {
+            return Some(name.clone());
+        }
         None
     }
 
@@ -124,9 +134,21 @@ impl VNCardMetadata {
         if let VNCardMetadata::Character(_, _name, description, _price) = self {
             return Some(description.clone());
         }
+        if let VNCardMetadata::Psychosis(_index, _name, description, _debuff) = self {
+            return Some(description.clone());
+        }
         None
     }
 
+    pub(crate) fn index(&self) -> Option<usize> {
+        match self {
+            VNCardMetadata::Poker(_, _) => None,
+            VNCardMetadata::Narrative(index, _, _, _, _, _) => Some(*index),
+            VNCardMetadata::Character(index, _, _, _) => Some(*index),
+            VNCardMetadata::Psychosis(index, _, _, _) => Some(*index),
+        }
+    }
+
     pub(crate) fn price(&self) -> Option<u16> {
         if let VNCardMetadata::Narrative(_index, _card_type, _genre, _name, _effect, price) = self {
             return Some(*price);
@@ -143,7 +165,7 @@ impl VNCardMetadata {
     }
 
     pub(crate) fn is_psychosis(&self) -> bool {
-        if let VNCardMetadata::Psychosis(_index, _name, _description) = self {
+        if let VNCardMetadata::Psychosis(_index, _name, _description, _debuff) = self {
             return true;
         }
         false
@@ -315,10 +337,6 @@ pub fn filter_narrative_plot_twist_deck(deck: Vec<VNCard>) -> Result<Vec<VNCard>
     filter_narrative_cards_by_type(deck, "plot twist".to_string())
 }
 
-pub fn filter_narrative_psychosis_deck(deck: Vec<VNCard>) -> Result<Vec<VNCard>> {
-    filter_narrative_cards_by_type(deck, "psychosis".to_string())
-}
-
 pub fn filter_narrative_conflict_deck(deck: Vec<VNCard>) -> Result<Vec<VNCard>> {
     filter_narrative_cards_by_type(deck, "conflict".to_string())
 }
@@ -343,11 +361,75 @@ impl CardMetadata for VNCard {
             VNCardMetadata::Poker(_, _) => "poker-cards/Back_1.png".into(),
             VNCardMetadata::Narrative(_, _, _, _, _, _) => "poker-cards/Back_2.png".into(),
             VNCardMetadata::Character(_, _, _, _) => "poker-cards/Back_3.png".into(),
-            VNCardMetadata::Psychosis(_, _, _) => "poker-cards/Back_1.png".into(),
+            VNCardMetadata::Psychosis(_, _, _, _) => "poker-cards/Back_1.png".into(),
+        }
+    }
+}
+
+// ----------------
+// Psychosis Debuff
+// ----------------
+
+/// Lasting effect of a played psychosis card on every later poker round,
+/// declared per card in `psychosis-cards/cards.json`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PsychosisDebuff {
+    /// table cells that can't hold a card
+    HiddenCells(usize),
+    /// cards dealt with their face turned away
+    BlurredFaces(usize),
+}
+
+impl PsychosisDebuff {
+    pub(crate) fn from_card(card: &VNCard) -> Option<Self> {
+        match &card.metadata {
+            VNCardMetadata::Psychosis(_, _, _, debuff) => Some(*debuff),
+            _ => None,
         }
     }
 }
 
+pub(crate) fn count_hidden_cells(debuffs: &[PsychosisDebuff]) -> usize {
+    debuffs
+        .iter()
+        .map(|debuff| match debuff {
+            PsychosisDebuff::HiddenCells(n) => *n,
+            PsychosisDebuff::BlurredFaces(_) => 0,
+        })
+        .sum()
+}
+
+pub(crate) fn count_blurred_faces(debuffs: &[PsychosisDebuff]) -> usize {
+    debuffs
+        .iter()
+        .map(|debuff| match debuff {
+            PsychosisDebuff::HiddenCells(_) => 0,
+            PsychosisDebuff::BlurredFaces(n) => *n,
+        })
+        .sum()
+}
+
+/// Turns `n` random cards of the deck face down, keeping their value
+pub(crate) fn blur_poker_deck(deck: Vec<VNCard>, n: usize) -> Vec<VNCard> {
+    let mut rng = rand::thread_rng();
+    let blurred = rand::seq::index::sample(&mut rng, deck.len(), n.min(deck.len())).into_vec();
+
+    deck.into_iter()
+        .enumerate()
+        .map(|(i, card)| {
+            if blurred.contains(&i) {
+                VNCard {
+                    filename: "poker-cards/Back_4.png".to_string(),
+                    ..card
+                }
+            } else {
+                card
+            }
+        })
+        .collect()
+}
+
 // -----------------------
 // Poker Combination Tests
 // -----------------------
@@ -687,8 +769,8 @@ mod tests {
 
         assert!(is_royal_flush(&royal_flush_set));
 
-        let mut rng = thread_rng();
-        royal_flush_set.shuffle(&mut rng);
+        // out of order, a shuffle can land back on the sorted hand
+        royal_flush_set.swap(0, 1);
 
         assert!(!is_royal_flush(&royal_flush_set));
     }
@@ -714,8 +796,8 @@ mod tests {
 
         assert!(is_straight_flush(&straight_flush_set));
 
-        let mut rng = thread_rng();
-        straight_flush_set.shuffle(&mut rng);
+        // out of order, a shuffle can land back on the sorted hand
+        straight_flush_set.swap(0, 1);
 
         assert!(!is_straight_flush(&straight_flush_set));
     }
@@ -809,8 +891,8 @@ mod tests {
 
         assert!(is_flush(&flush_set));
 
-        let mut rng = thread_rng();
-        flush_set.shuffle(&mut rng);
+        // out of order, a shuffle can land back on the sorted hand
+        flush_set.swap(0, 1);
 
         assert!(!is_flush(&flush_set));
     }
@@ -838,8 +920,8 @@ mod tests {
 
         assert!(straight(&straight_flush_set));
 
-        let mut rng = thread_rng();
-        straight_flush_set.shuffle(&mut rng);
+        // out of order, a shuffle can land back on the sorted hand
+        straight_flush_set.swap(0, 1);
 
         assert!(!straight(&straight_flush_set));
     }
@@ -965,4 +1047,25 @@ mod tests {
         assert!(is_one_pair);
         assert_eq!(score, 6);
     }
+
+    #[test]
+    fn test_psychosis_cards_declare_debuffs() {
+        let cards: Vec<PsychosisCard> =
+            serde_json::from_str(include_str!("../assets/psychosis-cards/cards.json")).unwrap();
+        assert!(!cards.is_empty());
+
+        let card = VNCard {
+            filename: String::new(),
+            metadata: VNCardMetadata::Psychosis(
+                6,
+                cards[5].name.clone(),
+                cards[5].description.clone(),
+                cards[5].debuff,
+            ),
+        };
+        assert_eq!(
+            PsychosisDebuff::from_card(&card),
+            Some(PsychosisDebuff::BlurredFaces(5))
+        );
+    }
 }
@@ -6,7 +6,7 @@ use bevy_defer::AsyncWorld;
 use bevy_la_mesa::events::*;
 use bevy_la_mesa::*;
 use bevy_novel::events::EventSwitchNextNode;
-use bevy_tweening::lens::TransformPositionLens;
+use bevy_tweening::lens::{TransformPositionLens, TransformRotationLens};
 use bevy_tweening::Animator;
 use bevy_tweening::Tween;
 
@@ -15,12 +15,17 @@ use crate::menu_game::EventRefreshUI;
 use crate::menu_game::EventRenderUI;
 use crate::menu_game::NarrativeMenuSettings;
 use crate::menu_game::PokerMenuSettings;
+use crate::story_export::StoryBeat;
+use crate::story_mechanics::MechanicArgs;
 use crate::EventCardPositionHover;
 use crate::EventCardPositionOut;
 use crate::EventCardPositionPress;
 use crate::GameState;
 use crate::GameType;
 
+/// Narrative table has room for this many picked cards
+pub(crate) const MAX_NARRATIVE_PICKS: usize = 5;
+
 // ------
 // Events
 // ------
@@ -31,13 +36,26 @@ pub(crate) struct EventStartPokerGame {}
 #[derive(Event)]
 pub(crate) struct EventStartNarrativeCardShop {}
 
+/// Starts a narrative card play; the payload is how many cards may be picked
 #[derive(Event)]
 pub(crate) enum EventStartNarrativeGame {
-    Setting,
-    PlotTwist,
-    Conflict,
-    Characters,
-    Psychosis,
+    Setting(usize),
+    PlotTwist(usize),
+    Conflict(usize),
+    Characters(usize),
+    Psychosis(usize),
+}
+
+impl EventStartNarrativeGame {
+    pub(crate) fn n_cards(&self) -> usize {
+        match self {
+            EventStartNarrativeGame::Setting(n)
+            | EventStartNarrativeGame::PlotTwist(n)
+            | EventStartNarrativeGame::Conflict(n)
+            | EventStartNarrativeGame::Characters(n)
+            | EventStartNarrativeGame::Psychosis(n) => *n,
+        }
+    }
 }
 
 #[derive(Event)]
@@ -47,7 +65,72 @@ pub(crate) struct EventPlayHand {}
 pub(crate) struct EventEndCardGame {}
 
 #[derive(Event)]
-pub(crate) struct EventGameOver {}
+pub(crate) struct EventGameOver {
+    /// the last act of the campaign was passed
+    pub won: bool,
+}
+
+// --------------
+// Game mechanics
+// --------------
+
+pub(crate) fn mechanic_play_poker(
+    In(_): In<MechanicArgs>,
+    mut ew_start_poker_game: EventWriter<EventStartPokerGame>,
+    mut game_state: ResMut<GameState>,
+) {
+    ew_start_poker_game.write(EventStartPokerGame {});
+    game_state.current_menu_type = EventRenderUI::Poker(PokerMenuSettings { ..default() });
+}
+
+pub(crate) fn mechanic_play_narrative_setting(
+    In(args): In<MechanicArgs>,
+    mut ew_start_narrative_game: EventWriter<EventStartNarrativeGame>,
+) {
+    ew_start_narrative_game.write(EventStartNarrativeGame::Setting(args.count.unwrap_or(1)));
+}
+
+pub(crate) fn mechanic_play_narrative_characters(
+    In(args): In<MechanicArgs>,
+    mut ew_start_narrative_game: EventWriter<EventStartNarrativeGame>,
+) {
+    ew_start_narrative_game.write(EventStartNarrativeGame::Characters(args.count.unwrap_or(1)));
+}
+
+pub(crate) fn mechanic_play_narrative_conflict(
+    In(args): In<MechanicArgs>,
+    mut ew_start_narrative_game: EventWriter<EventStartNarrativeGame>,
+) {
+    ew_start_narrative_game.write(EventStartNarrativeGame::Conflict(args.count.unwrap_or(1)));
+}
+
+pub(crate) fn mechanic_play_narrative_psychosis(
+    In(args): In<MechanicArgs>,
+    mut ew_start_narrative_game: EventWriter<EventStartNarrativeGame>,
+) {
+    ew_start_narrative_game.write(EventStartNarrativeGame::Psychosis(args.count.unwrap_or(1)));
+}
+
+pub(crate) fn mechanic_play_narrative_plot_twist(
+    In(args): In<MechanicArgs>,
+    mut ew_start_narrative_game: EventWriter<EventStartNarrativeGame>,
+) {
+    ew_start_narrative_game.write(EventStartNarrativeGame::PlotTwist(args.count.unwrap_or(1)));
+}
+
+pub(crate) fn mechanic_card_shop(
+    In(_): In<MechanicArgs>,
+    mut ew_start_narrative_card_shop: EventWriter<EventStartNarrativeCardShop>,
+) {
+    ew_start_narrative_card_shop.write(EventStartNarrativeCardShop {});
+}
+
+pub(crate) fn mechanic_game_over(
+    In(_): In<MechanicArgs>,
+    mut ew_game_over: EventWriter<EventGameOver>,
+) {
+    ew_game_over.write(EventGameOver { won: false });
+}
 
 // ----------
 // Components
@@ -61,66 +144,130 @@ pub(crate) struct CardShowcase {}
 // --------------
 
 pub fn handle_card_press_cardplay(
+    mut commands: Commands,
     mut game_state: ResMut<GameState>,
     mut card_press: EventReader<CardPress>,
     mut ew_place_card_on_table: EventWriter<PlaceCardOnTable>,
     mut er_refresh_ui: EventWriter<EventRefreshUI>,
+    q_hand_areas: Query<(&Transform, &HandArea)>,
     mut q_cards: ParamSet<(
-        Query<(Entity, &Card<VNCard>, &CardOnTable)>,
+        Query<(Entity, &Card<VNCard>, &CardOnTable, &Transform)>,
         Query<(Entity, &Card<VNCard>, &Hand)>,
     )>,
 ) {
     for event in card_press.read() {
+        if game_state.game_type != GameType::Narrative {
+            continue;
+        }
+
         let p0 = q_cards.p0();
-        let n_cards_on_table = p0.iter().len();
-        if p0.get(event.entity).is_ok() {
+        let occupied_markers: Vec<usize> = p0
+            .iter()
+            .map(|(_, _, card_on_table, _)| card_on_table.marker)
+            .collect();
+
+        // un-pick: the card slides back to where it was in the hand
+        if let Ok((_, card, _, transform)) = p0.get(event.entity) {
+            let Some(hand_transform) = q_hand_areas
+                .iter()
+                .find(|(_, hand_area)| hand_area.player == 1)
+                .map(|(transform, _)| transform)
+            else {
+                continue;
+            };
+            let Some(hand_slot) = card.transform else {
+                continue;
+            };
+
+            let rotate = Tween::new(
+                EaseFunction::QuadraticIn,
+                Duration::from_millis(75),
+                TransformRotationLens {
+                    start: transform.rotation,
+                    end: hand_transform.rotation,
+                },
+            );
+            let slide = Tween::new(
+                EaseFunction::QuadraticIn,
+                Duration::from_millis(75),
+                TransformPositionLens {
+                    start: transform.translation,
+                    end: hand_slot.translation,
+                },
+            );
+
+            commands
+                .entity(event.entity)
+                .remove::<CardOnTable>()
+                .insert(Hand { player: 1 })
+                .insert(Animator::new(rotate.then(slide)));
+
+            let n_picked = occupied_markers.len() - 1;
+            er_refresh_ui.write(EventRefreshUI::Narrative(NarrativeMenuSettings {
+                show_advance_button: n_picked > 0,
+            }));
+
+            game_state.n_turns = n_picked;
             continue;
         }
 
-        let p1 = q_cards.p1();
+        if occupied_markers.len() >= game_state.n_narrative_picks
+            || q_cards.p1().get(event.entity).is_err()
+        {
+            continue;
+        }
 
-        if game_state.game_type == GameType::Narrative && n_cards_on_table < 1 {
-            let card = p1.get(event.entity).unwrap().1;
-            let card_type = card.data.metadata.card_type().unwrap_or_default();
-            let effect = card.data.metadata.effect().unwrap_or_default();
-            let name = card.data.metadata.name().unwrap_or_default();
-            let description = card.data.metadata.description().unwrap_or_default();
+        let marker = (1..=game_state.n_narrative_picks)
+            .find(|marker| !occupied_markers.contains(marker))
+            .unwrap();
 
-            match card_type.as_str() {
-                "setting" => {
-                    game_state.narrative_settings.push(effect);
-                }
-                "plot twist" => {
-                    game_state.narrative_plot_twists.push(effect);
-                }
-                "conflict" => {
-                    game_state.narrative_conflicts.push(effect);
-                }
-                "psychosis" => {
-                    game_state.psychosis.push(effect);
-                }
-                "character" => {
-                    game_state.characters.push(format!(
-                        "Character name: {}; Character description: {}",
-                        name, description
-                    ));
-                }
-                _ => {}
-            }
+        ew_place_card_on_table.write(PlaceCardOnTable {
+            card_entity: event.entity,
+            player: 1,
+            marker,
+        });
 
-            ew_place_card_on_table.write(PlaceCardOnTable {
-                card_entity: event.entity,
-                player: 1,
-                marker: n_cards_on_table + 1,
-            });
+        er_refresh_ui.write(EventRefreshUI::Narrative(NarrativeMenuSettings {
+            show_advance_button: true,
+        }));
 
-            er_refresh_ui.write(EventRefreshUI::Narrative(NarrativeMenuSettings {
-                show_advance_button: true,
-            }));
+        game_state.n_turns = occupied_markers.len() + 1;
+    }
+}
+
+/// Writes a picked narrative card into the story state
+fn commit_narrative_card(game_state: &mut GameState, card: &VNCard) {
+    let card_type = card.metadata.card_type().unwrap_or_default();
+    let effect = card.metadata.effect().unwrap_or_default();
+    let name = card.metadata.name().unwrap_or_default();
+    let description = card.metadata.description().unwrap_or_default();
 
-            game_state.n_turns = n_cards_on_table + 1;
+    match card_type.as_str() {
+        "setting" => {
+            game_state.narrative_settings.push(effect);
+        }
+        "plot twist" => {
+            game_state.narrative_plot_twists.push(effect);
+        }
+        "conflict" => {
+            game_state.narrative_conflicts.push(effect);
+        }
+        "psychosis" => {
+            game_state.psychosis.push(effect);
+            if let Some(debuff) = PsychosisDebuff::from_card(card) {
+                game_state.psychosis_debuffs.push(debuff);
+            }
         }
+        "character" => {
+            game_state.characters.add(name.clone(), description);
+        }
+        _ => {}
     }
+
+    game_state
+        .story_beats
+        .push(StoryBeat::Card { name: name.clone() });
+    game_state.played_cards.push(name);
 }
 
 pub fn cardshop_handle_card_press(
@@ -192,7 +339,7 @@ pub(crate) fn handle_play_hand(
         let graveyard_deck_entity = q_decks.iter().find(|(_, deck)| deck.marker == 1).unwrap().0;
 
         if game_state.game_type == GameType::Poker
-            && game_state.n_draws == game_state.max_n_poker_draws
+            && game_state.n_draws == game_state.poker_draw_limit()
         {
             let poker_cards_on_table = q_cards
                 .p0()
@@ -218,16 +365,23 @@ pub(crate) fn handle_play_hand(
                     .map(|(card, _, col)| (card.clone(), *col))
                     .collect::<Vec<(VNCard, usize)>>();
                 // sort by position
-                row_cards.sort_by(|a, b| a.1.cmp(&b.1));
+                row_cards.sort_by_key(|row| row.1);
                 let row_cards = row_cards
                     .iter()
                     .map(|row| row.0.clone())
                     .collect::<Vec<VNCard>>();
 
-                let (_combination, score) = check_poker_hand(row_cards);
+                let (combination, score) = check_poker_hand(row_cards);
 
                 // total_score += score;
                 game_state.score += score as isize;
+                if game_state
+                    .best_poker_combination
+                    .as_ref()
+                    .is_none_or(|best| combination > *best)
+                {
+                    game_state.best_poker_combination = Some(combination);
+                }
             }
 
             for (entity, _, _) in q_cards.p1().iter() {
@@ -239,6 +393,17 @@ pub(crate) fn handle_play_hand(
         }
 
         if game_state.game_type == GameType::Narrative {
+            let mut picked_cards = q_cards
+                .p0()
+                .iter()
+                .map(|(_, card, card_on_table)| (card.data.clone(), card_on_table.marker))
+                .collect::<Vec<(VNCard, usize)>>();
+            picked_cards.sort_by_key(|picked| picked.1);
+
+            for (card, _) in picked_cards.iter() {
+                commit_narrative_card(&mut game_state, card);
+            }
+
             for (entity, _, _) in q_cards.p1().iter() {
                 ew_discard_card_to_deck.write(DiscardCardToDeck {
                     card_entity: entity,
@@ -345,6 +510,12 @@ pub(crate) fn handle_start_poker_game(
         game_state.game_type = GameType::Poker;
         game_state.n_turns = 0;
 
+        // psychosis debuffs: some cells can't be used, some cards are dealt face down
+        let n_hidden_cells = count_hidden_cells(&game_state.psychosis_debuffs).min(10);
+        let hidden_cells =
+            rand::seq::index::sample(&mut rand::thread_rng(), 25, n_hidden_cells).into_vec();
+        game_state.n_hidden_poker_cells = n_hidden_cells;
+
         // Deck
         let deck_play_cards = commands
             .spawn((
@@ -382,7 +553,10 @@ pub(crate) fn handle_start_poker_game(
                             0.0,
                             6.0 - 3.6 * (j as f32),
                         )),
-                        Visibility::Visible,
+                        match hidden_cells.contains(&(i * 5 + j)) {
+                            true => Visibility::Hidden,
+                            false => Visibility::Visible,
+                        },
                         PlayArea {
                             marker: i * 5 + j,
                             player: 1,
@@ -397,7 +571,10 @@ pub(crate) fn handle_start_poker_game(
 
         ew_render_deck.write(RenderDeck::<VNCard> {
             deck_entity: deck_play_cards,
-            deck: load_poker_deck(),
+            deck: blur_poker_deck(
+                load_poker_deck(),
+                count_blurred_faces(&game_state.psychosis_debuffs),
+            ),
         });
 
         ew_render_ui.write(EventRenderUI::Poker(PokerMenuSettings {
@@ -548,7 +725,7 @@ pub(crate) fn handle_start_narrative_game(
             Transform::from_translation(Vec3::new(4.0, 0.0, 0.0))
                 .with_rotation(Quat::from_rotation_y(std::f32::consts::PI / 2.0)),
             Visibility::Hidden,
-            DeckArea { marker: 1 },
+            DeckArea { marker: 2 },
             Name::new("Deck 2 -- Play Cards"),
         ));
 
@@ -560,85 +737,43 @@ pub(crate) fn handle_start_narrative_game(
             HandArea { player: 1 },
         ));
 
-        // Play Area
+        // Play Area -- one slot per card that may be picked
         let face_material = materials.add(Color::srgb_u8(124, 144, 255));
+        let n_cards = event.n_cards().clamp(1, MAX_NARRATIVE_PICKS);
+        game_state.n_narrative_picks = n_cards;
 
-        commands.spawn((
-            Mesh3d(meshes.add(Plane3d::default().mesh().size(2.5, 3.5).subdivisions(10))),
-            MeshMaterial3d(face_material.clone()),
-            Transform::from_translation(Vec3::new(-7.6 + 2.0, 0.0, 7.0)),
-            Visibility::Hidden,
-            PlayArea {
-                marker: 1,
-                player: 1,
-            },
-            Name::new("Play Area 1"),
-        ));
-
-        commands.spawn((
-            Mesh3d(meshes.add(Plane3d::default().mesh().size(2.5, 3.5).subdivisions(10))),
-            MeshMaterial3d(face_material.clone()),
-            Transform::from_translation(Vec3::new(-7.6 + 2.0 + 3.05, 0.0, 7.0)),
-            Visibility::Hidden,
-            PlayArea {
-                marker: 2,
-                player: 1,
-            },
-            Name::new("Play Area 2"),
-        ));
-
-        commands.spawn((
-            Mesh3d(meshes.add(Plane3d::default().mesh().size(2.5, 3.5).subdivisions(10))),
-            MeshMaterial3d(face_material.clone()),
-            Transform::from_translation(Vec3::new(-7.6 + 2.0 + 3.05 * 2.0, 0.0, 7.0)),
-            Visibility::Hidden,
-            PlayArea {
-                marker: 3,
-                player: 1,
-            },
-            Name::new("Play Area 3"),
-        ));
+        for i in 0..n_cards {
+            let offset = i as f32 - (n_cards - 1) as f32 / 2.0;
 
-        commands.spawn((
-            Mesh3d(meshes.add(Plane3d::default().mesh().size(2.5, 3.5).subdivisions(10))),
-            MeshMaterial3d(face_material.clone()),
-            Transform::from_translation(Vec3::new(-7.6 + 2.0 + 3.05 * 3.0, 0.0, 7.0)),
-            Visibility::Hidden,
-            PlayArea {
-                marker: 4,
-                player: 1,
-            },
-            Name::new("Play Area 4"),
-        ));
-
-        commands.spawn((
-            Mesh3d(meshes.add(Plane3d::default().mesh().size(2.5, 3.5).subdivisions(10))),
-            MeshMaterial3d(face_material.clone()),
-            Transform::from_translation(Vec3::new(-7.6 + 1.0 + 3.05 * 4.0, 0.0, 7.0)),
-            Visibility::Hidden,
-            PlayArea {
-                marker: 5,
-                player: 1,
-            },
-            Name::new("Play Area 5"),
-        ));
+            commands.spawn((
+                Mesh3d(meshes.add(Plane3d::default().mesh().size(2.5, 3.5).subdivisions(10))),
+                MeshMaterial3d(face_material.clone()),
+                Transform::from_translation(Vec3::new(0.5 + 3.05 * offset, 0.0, 7.0)),
+                Visibility::Hidden,
+                PlayArea {
+                    marker: i + 1,
+                    player: 1,
+                },
+                Name::new(format!("Play Area {}", i + 1)),
+            ));
+        }
 
         ew_render_deck.write(RenderDeck::<VNCard> {
             deck_entity: deck_play_cards,
             deck: match event {
-                EventStartNarrativeGame::Setting => {
+                EventStartNarrativeGame::Setting(_) => {
                     filer_narrative_setting_deck(game_state.collected_deck.clone()).unwrap()
                 }
-                EventStartNarrativeGame::PlotTwist => {
+                EventStartNarrativeGame::PlotTwist(_) => {
                     filter_narrative_plot_twist_deck(game_state.collected_deck.clone()).unwrap()
                 }
-                EventStartNarrativeGame::Conflict => {
+                EventStartNarrativeGame::Conflict(_) => {
                     filter_narrative_conflict_deck(game_state.collected_deck.clone()).unwrap()
                 }
-                EventStartNarrativeGame::Characters => {
+                EventStartNarrativeGame::Characters(_) => {
                     filter_character_deck(game_state.collected_deck.clone()).unwrap()
                 }
-                EventStartNarrativeGame::Psychosis => {
+                EventStartNarrativeGame::Psychosis(_) => {
                     filter_psychosis_cards(game_state.collected_deck.clone()).unwrap()
                 }
             },
@@ -663,36 +798,36 @@ pub(crate) fn handle_card_on_table_hover(
     )>,
 ) {
     hover.read().for_each(|hover| {
-        if game_state.game_type == GameType::CardShop {
-            if let Ok((_, card, hand, _transform)) = cards_in_on_table.get_mut(hover.entity) {
-                if card.pickable && card.transform.is_some() {
-                    let start_translation = card.transform.unwrap().translation;
-                    let tween = Tween::new(
-                        EaseFunction::QuadraticIn,
-                        Duration::from_millis(100),
-                        TransformPositionLens {
-                            start: start_translation,
-                            end: start_translation
-                                + match hand.player {
-                                    1 => Vec3::new(0., 0.7, 0.7),
-                                    _ => Vec3::new(0., 0.7, 0.0),
-                                },
+        if game_state.game_type == GameType::CardShop
+            && let Ok((_, card, hand, _transform)) = cards_in_on_table.get_mut(hover.entity)
+            && card.pickable
+            && let Some(resting) = card.transform
+        {
+            let start_translation = resting.translation;
+            let tween = Tween::new(
+                EaseFunction::QuadraticIn,
+                Duration::from_millis(100),
+                TransformPositionLens {
+                    start: start_translation,
+                    end: start_translation
+                        + match hand.player {
+                            1 => Vec3::new(0., 0.7, 0.7),
+                            _ => Vec3::new(0., 0.7, 0.0),
                         },
-                    );
-
-                    for (_, mut visibility, _, mut material) in q_card_showcase.iter_mut() {
-                        let face_texture = asset_server.load(card.data.front_image_filename());
-                        let face_material = materials.add(StandardMaterial {
-                            base_color_texture: Some(face_texture.clone()),
-                            ..Default::default()
-                        });
-                        *material = MeshMaterial3d(face_material);
-                        visibility.set_if_neq(Visibility::Visible);
-                    }
-
-                    commands.entity(hover.entity).insert(Animator::new(tween));
-                }
+                },
+            );
+
+            for (_, mut visibility, _, mut material) in q_card_showcase.iter_mut() {
+                let face_texture = asset_server.load(card.data.front_image_filename());
+                let face_material = materials.add(StandardMaterial {
+                    base_color_texture: Some(face_texture.clone()),
+                    ..Default::default()
+                });
+                *material = MeshMaterial3d(face_material);
+                visibility.set_if_neq(Visibility::Visible);
             }
+
+            commands.entity(hover.entity).insert(Animator::new(tween));
         }
     });
 }
@@ -705,25 +840,25 @@ pub(crate) fn handle_card_on_table_out(
     mut q_card_showcase: Query<(Entity, &mut Visibility, &CardShowcase)>,
 ) {
     out.read().for_each(|hover| {
-        if game_state.game_type == GameType::CardShop {
-            if let Ok((_, card, _, transform)) = cards_in_on_table.get_mut(hover.entity) {
-                if card.pickable && card.transform.is_some() {
-                    let tween = Tween::new(
-                        EaseFunction::QuadraticIn,
-                        Duration::from_millis(100),
-                        TransformPositionLens {
-                            start: transform.translation,
-                            end: card.transform.unwrap().translation,
-                        },
-                    );
-
-                    for (_, mut visibility, _) in q_card_showcase.iter_mut() {
-                        visibility.set_if_neq(Visibility::Hidden);
-                    }
-
-                    commands.entity(hover.entity).insert(Animator::new(tween));
-                }
+        if game_state.game_type == GameType::CardShop
+            && let Ok((_, card, _, transform)) = cards_in_on_table.get_mut(hover.entity)
+            && card.pickable
+            && let Some(resting) = card.transform
+        {
+            let tween = Tween::new(
+                EaseFunction::QuadraticIn,
+                Duration::from_millis(100),
+                TransformPositionLens {
+                    start: transform.translation,
+                    end: resting.translation,
+                },
+            );
+
+            for (_, mut visibility, _) in q_card_showcase.iter_mut() {
+                visibility.set_if_neq(Visibility::Hidden);
             }
+
+            commands.entity(hover.entity).insert(Animator::new(tween));
         }
     });
 }
@@ -83,7 +83,7 @@ pub fn handle_card_position_press(
             });
             game_state.n_turns += 1;
 
-            if game_state.n_draws < game_state.max_n_poker_draws {
+            if game_state.n_draws < game_state.poker_draw_limit() {
                 commands.spawn_task(move || async move {
                     AsyncWorld.sleep(0.5).await;
                     AsyncWorld.send_event(DrawToHand {
@@ -131,7 +131,7 @@ pub fn poker_handle_place_card_on_table(
                     .map(|(card, col, _row)| (card.clone(), *col))
                     .collect::<Vec<(VNCard, usize)>>();
                 // sort by position
-                row_cards.sort_by(|a, b| a.1.cmp(&b.1));
+                row_cards.sort_by_key(|row| row.1);
 
                 let row_cards = row_cards
                     .iter()
@@ -145,7 +145,7 @@ pub fn poker_handle_place_card_on_table(
             }
 
             ew_refresh_ui.write(EventRefreshUI::PokerMenu(PokerMenuSettings {
-                show_advance_button: game_state.n_draws == game_state.max_n_poker_draws,
+                show_advance_button: game_state.n_draws == game_state.poker_draw_limit(),
                 show_score: true,
                 score: total_score,
             }));
@@ -0,0 +1,6 @@
+//! Scenario scripting shared by the game and `rpy-lint`: `game_mechanic`
+//! statements, the mechanics the game ships with and prompt templates
+
+pub mod story_mechanics;
+pub mod story_script;
+pub mod story_template;
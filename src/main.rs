@@ -1,17 +1,41 @@
-#![feature(let_chains)]
+// Bevy systems take their resources as arguments and queries as tuples
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use std::collections::BTreeMap;
 
 mod api_llm;
+mod api_mint;
 mod api_nft;
+mod api_signature;
+mod api_tasks;
 mod api_text2img;
+mod api_wallet;
+mod cards_forge;
 mod cards_game;
 mod cards_scene;
 mod cards_solitaire;
+mod menu_character;
+mod menu_gallery;
 mod menu_game;
+mod menu_library;
 mod menu_main;
 mod splashscreen;
+mod story_attributes;
+mod story_book;
+mod story_campaign;
+mod story_characters;
+mod story_export;
+mod story_format;
+mod story_library;
+mod story_preload;
+mod story_renpy;
+mod story_stage;
+mod story_summary;
 mod visual_novel;
 mod wasm;
 
+use kakuseinosekainokokujoninarudaikinonisemono::{story_mechanics, story_script, story_template};
+
 use api_nft::EventLoadNFTRequest;
 use bevy::asset::AssetMetaCheck;
 use bevy::color::palettes::css::WHITE;
@@ -28,29 +52,64 @@ use bevy_la_mesa::*;
 use bevy_modern_pixel_camera::prelude::*;
 use bevy_novel::*;
 
+use api_mint::MintPlugin;
 use api_nft::NFTPlugin;
+use api_text2img::GeneratedAssets;
 use api_text2img::Text2ImagePlugin;
+use api_text2img::GENERATED_ASSET_SOURCE;
+use api_wallet::WalletPlugin;
 use cards_game::CharacterCards;
 use cards_game::NarrativeCards;
 use cards_game::PokerCombination;
 use cards_game::PsychosisCards;
+use cards_game::PsychosisDebuff;
 use cards_game::VNCard;
 use cards_game::VNCardMetadata;
+use cards_game::{
+    filter_initial_character_cards, filter_initial_narrative_cards, filter_psychosis_cards,
+};
 use menu_game::EventRenderUI;
-use rpy_asset_loader::Rpy;
 use splashscreen::SplashscreenPlugin;
+use story_book::StorybookPlugin;
+use story_campaign::handle_end_act;
+use story_campaign::mechanic_act_end;
+use story_campaign::Campaign;
+use story_campaign::CampaignProgress;
+use story_campaign::EventEndAct;
+use story_characters::CharacterRoster;
+use story_characters::CharacterSprites;
+use story_export::handle_replay_story;
+use story_export::EventReplayStory;
+use story_export::StoryBeat;
+use story_mechanics::BuiltinMechanic;
+use story_mechanics::GameMechanicAppExt;
+use story_preload::handle_preload_downloads;
+use story_preload::handle_preload_scenario;
+use story_preload::start_preloaded_scenario;
+use story_preload::ScenePreloadPlugin;
+use story_renpy::RenpyExportPlugin;
+use story_summary::StorySoFar;
+use story_template::PromptFragments;
 
 use crate::api_llm::*;
+use crate::cards_forge::*;
 use crate::cards_scene::*;
 use crate::cards_solitaire::*;
+use crate::menu_character::CharacterCreatorPlugin;
+use crate::menu_gallery::GalleryPlugin;
 use crate::menu_game::GameMenuPlugin;
+use crate::menu_library::LibraryPlugin;
 use crate::menu_main::*;
 use crate::visual_novel::*;
 
 pub const API_ENDPOINT: &str = "https://kakuseinosekainokokujoninarudaikinonisemono.space/api";
 
 fn main() {
+    let generated_assets = GeneratedAssets::default();
+
     App::new()
+        .register_asset_source(GENERATED_ASSET_SOURCE, generated_assets.source())
+        .insert_resource(generated_assets)
         .add_plugins((
             DefaultPlugins
                 .set(ImagePlugin::default_nearest())
@@ -67,13 +126,18 @@ fn main() {
                     ..default()
                 }),
             AsyncPlugin::default_settings(),
-            JsonAssetPlugin::<NarrativeCards>::new(&["json"]),
-            JsonAssetPlugin::<CharacterCards>::new(&["json"]),
-            JsonAssetPlugin::<PsychosisCards>::new(&["json"]),
+            // a plugin tuple holds at most 15
+            (
+                JsonAssetPlugin::<NarrativeCards>::new(&["json"]),
+                JsonAssetPlugin::<CharacterCards>::new(&["json"]),
+                JsonAssetPlugin::<PsychosisCards>::new(&["json"]),
+                JsonAssetPlugin::<PromptFragments>::new(&["json"]),
+                JsonAssetPlugin::<Campaign>::new(&["json"]),
+            ),
             LaMesaPlugin::<cards_game::VNCard>::default(),
             MeshPickingPlugin,
             NovelPlugin {},
-            TasksPlugin::default(),
+            TasksPlugin,
             AudioPlugin,
             HuiPlugin,
             EguiPlugin {
@@ -86,9 +150,17 @@ fn main() {
             SplashscreenPlugin,
             LLMPlugin,
             NFTPlugin,
+            MintPlugin,
             Text2ImagePlugin,
             MainMenuPlugin,
             GameMenuPlugin,
+            CharacterCreatorPlugin,
+            LibraryPlugin,
+            GalleryPlugin,
+            RenpyExportPlugin,
+            StorybookPlugin,
+            WalletPlugin,
+            ScenePreloadPlugin,
         ))
         .add_systems(Startup, (setup_camera_and_light, load_resources))
         .add_systems(Update, (load_cards,).run_if(in_state(AppState::Loading2)))
@@ -104,7 +176,9 @@ fn main() {
                 handle_draw_to_hand,
                 handle_draw_to_table,
                 handle_llm_response,
+                handle_llm_failed,
                 handle_new_vn_node,
+                handle_end_act,
                 handle_event_game_over,
                 handle_play_hand,
                 handle_text_2_image_response,
@@ -116,8 +190,16 @@ fn main() {
         )
         .add_systems(
             Update,
-            ((handle_new_vn_node, handle_download_image_response).chain())
-                .run_if(in_state(AppState::NovelPlayer)),
+            ((
+                handle_replay_story,
+                handle_preload_scenario,
+                handle_new_vn_node,
+                handle_download_image_response,
+                handle_preload_downloads,
+                start_preloaded_scenario,
+            )
+                .chain())
+            .run_if(in_state(AppState::NovelPlayer)),
         )
         .add_systems(
             Update,
@@ -141,6 +223,10 @@ fn main() {
                 handle_card_on_table_hover,
                 handle_card_on_table_out,
                 cardshop_handle_card_press,
+                handle_forge_llm_response,
+                handle_forge_llm_failed,
+                handle_forge_art_response,
+                handle_forge_art_failed,
             )
                 .run_if(in_state(AppState::Game)),
         )
@@ -149,6 +235,12 @@ fn main() {
             assets_path: "plot".to_string(),
             pause_handle_switch_node: false,
         })
+        .insert_resource(StorySettings {
+            structured_output: structured_output(),
+            story_token_budget: 1500,
+            summary_threshold: 2000,
+            summary_keep_recent: 500,
+        })
         // Events
         .add_event::<EventCardPositionHover>()
         .add_event::<EventCardPositionOut>()
@@ -159,7 +251,13 @@ fn main() {
         .add_event::<EventStartPokerGame>()
         .add_event::<EventStartNarrativeCardShop>()
         .add_event::<EventGameOver>()
+        .add_event::<EventEndAct>()
+        .add_event::<EventReplayStory>()
+        // Game mechanics, `game_mechanic "name"` in scenarios
+        .add_plugins(register_builtin_mechanics)
         // Resources
+        .init_resource::<CardForge>()
+        .init_resource::<CampaignProgress>()
         .insert_resource(GameState {
             max_n_poker_draws: 25,
             score: 0,
@@ -174,6 +272,41 @@ fn main() {
         .run();
 }
 
+/// Gives every built-in mechanic its handler, a new `BuiltinMechanic` doesn't
+/// build until it has one
+fn register_builtin_mechanics(app: &mut App) {
+    for mechanic in BuiltinMechanic::ALL {
+        let (name, parse) = (mechanic.name(), mechanic.parser());
+        match mechanic {
+            BuiltinMechanic::PlayPoker => {
+                app.register_game_mechanic(name, parse, mechanic_play_poker)
+            }
+            BuiltinMechanic::PlayNarrativeSetting => {
+                app.register_game_mechanic(name, parse, mechanic_play_narrative_setting)
+            }
+            BuiltinMechanic::PlayNarrativeCharacters => {
+                app.register_game_mechanic(name, parse, mechanic_play_narrative_characters)
+            }
+            BuiltinMechanic::PlayNarrativeConflict => {
+                app.register_game_mechanic(name, parse, mechanic_play_narrative_conflict)
+            }
+            BuiltinMechanic::PlayNarrativePsychosis => {
+                app.register_game_mechanic(name, parse, mechanic_play_narrative_psychosis)
+            }
+            BuiltinMechanic::PlayNarrativePlotTwist => {
+                app.register_game_mechanic(name, parse, mechanic_play_narrative_plot_twist)
+            }
+            BuiltinMechanic::CardShop => {
+                app.register_game_mechanic(name, parse, mechanic_card_shop)
+            }
+            BuiltinMechanic::GameOver => {
+                app.register_game_mechanic(name, parse, mechanic_game_over)
+            }
+            BuiltinMechanic::ActEnd => app.register_game_mechanic(name, parse, mechanic_act_end),
+        };
+    }
+}
+
 fn setup_camera_and_light(mut commands: Commands) {
     commands.spawn((
         Name::new("Camera 2d"),
@@ -216,6 +349,9 @@ enum AppState {
     Game,
     NovelPlayer,
     MainMenu,
+    CharacterCreator,
+    Library,
+    Gallery,
 }
 
 // ---------
@@ -232,11 +368,6 @@ pub(crate) enum GameType {
     VisualNovelPlayer,
 }
 
-#[derive(Default)]
-pub(crate) struct CryptoWallet {
-    pub address: String,
-}
-
 #[derive(Resource, Default)]
 pub(crate) struct GameState {
     pub game_deck: Vec<VNCard>,
@@ -245,23 +376,100 @@ pub(crate) struct GameState {
     pub max_n_poker_draws: usize,
     pub n_draws: usize,
     pub n_turns: usize,
+    /// chapters generated this run, `chapters` in scenario conditions
+    pub n_chapters: usize,
+    pub n_narrative_picks: usize,
     pub n_vn_node_scene_request: usize,
     pub n_vn_node: usize,
     pub narrative_conflicts: Vec<String>,
     pub narrative_plot_twists: Vec<String>,
     pub narrative_settings: Vec<String>,
-    pub characters: Vec<String>,
+    pub characters: CharacterRoster,
+    pub character_sprites: CharacterSprites,
     pub psychosis: Vec<String>,
-    pub narrative_story_so_far: Vec<String>,
+    pub psychosis_debuffs: Vec<PsychosisDebuff>,
+    pub n_hidden_poker_cells: usize,
+    pub narrative_story_so_far: StorySoFar,
+    /// options offered at the end of the current chapter
+    pub story_choices: Vec<String>,
+    /// option the player picked, `{CHOICE}` in the next prompt only
+    pub story_choice: Option<String>,
+    /// set by `game_mechanic "set ..."`, read by `if ... jump`
+    pub script_variables: BTreeMap<String, i64>,
+    /// names of cards committed to the story, read by `if played ...`
+    pub played_cards: Vec<String>,
+    /// what the player has seen so far, persisted at game over
+    pub story_beats: Vec<StoryBeat>,
+    /// PNG of the latest generated background, shown in the story library
+    pub story_thumbnail: Vec<u8>,
     pub poker_combinations: Vec<PokerCombination>,
+    /// highest hand scored in the run, minted as an NFT attribute
+    pub best_poker_combination: Option<PokerCombination>,
+    /// random number identifying the run, minted as an NFT attribute
+    pub seed: u64,
     pub score: isize,
     pub current_menu_type: EventRenderUI,
-    pub wallet: CryptoWallet,
     pub player_nft_url: Option<String>,
 }
 
-#[derive(Resource, Deref, DerefMut)]
-struct ScenarioHandle(Handle<Rpy>);
+impl GameState {
+    /// Cards dealt in a poker round, one per table cell still in play
+    pub fn poker_draw_limit(&self) -> usize {
+        self.max_n_poker_draws
+            .saturating_sub(self.n_hidden_poker_cells)
+    }
+
+    /// Starts a new run: what the last one played, told and scored is dropped
+    /// and the player holds the initial cards again. Only the loaded cards,
+    /// settings and UI state are kept
+    pub fn reset_run(&mut self) {
+        let game_deck = std::mem::take(&mut self.game_deck);
+        let collected_deck = [
+            filter_initial_narrative_cards(game_deck.clone()),
+            filter_initial_character_cards(game_deck.clone()),
+            filter_psychosis_cards(game_deck.clone()).unwrap(),
+        ]
+        .concat();
+
+        *self = GameState {
+            game_deck,
+            collected_deck,
+            game_type: std::mem::take(&mut self.game_type),
+            max_n_poker_draws: self.max_n_poker_draws,
+            character_sprites: std::mem::take(&mut self.character_sprites),
+            current_menu_type: std::mem::take(&mut self.current_menu_type),
+            player_nft_url: self.player_nft_url.take(),
+            seed: rand::random(),
+            ..default()
+        };
+    }
+}
+
+/// How chapters are requested from the LLM
+#[derive(Resource, Default)]
+pub(crate) struct StorySettings {
+    /// ask for JSON beats, falling back to the line based format if the reply
+    /// doesn't validate; off unless `STRUCTURED_OUTPUT` is set
+    pub structured_output: bool,
+    /// most recent part of the story that fits is pasted into `{STORY}`
+    pub story_token_budget: usize,
+    /// once the verbatim story grows past this many tokens older chapters are
+    /// summarised into a synopsis
+    pub summary_threshold: usize,
+    /// tokens of the most recent story kept verbatim when summarising
+    pub summary_keep_recent: usize,
+}
+
+/// `STRUCTURED_OUTPUT=1` asks the LLM for JSON chapters on native, the line
+/// based format stays the default
+fn structured_output() -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Ok(value) = std::env::var("STRUCTURED_OUTPUT") {
+        return value == "1" || value.eq_ignore_ascii_case("true");
+    }
+
+    false
+}
 
 #[derive(Resource, Deref, DerefMut)]
 struct NarrativeCardsHandle(Handle<NarrativeCards>);
@@ -272,13 +480,19 @@ struct CharacterCardsHandle(Handle<CharacterCards>);
 #[derive(Resource, Deref, DerefMut)]
 struct PsychosisCardsHandle(Handle<PsychosisCards>);
 
+#[derive(Resource, Deref, DerefMut)]
+struct PromptFragmentsHandle(Handle<PromptFragments>);
+
+#[derive(Resource, Deref, DerefMut)]
+struct CampaignHandle(Handle<Campaign>);
+
 fn load_resources(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut game_state: ResMut<GameState>,
+    #[cfg(target_arch = "wasm32")] mut game_state: ResMut<GameState>,
 ) {
-    let scenario_handle = ScenarioHandle(asset_server.load("plot/intro.rpy"));
-    commands.insert_resource(scenario_handle);
+    let campaign_handle = CampaignHandle(asset_server.load("plot/campaign.json"));
+    commands.insert_resource(campaign_handle);
 
     let character_cards_handle =
         CharacterCardsHandle(asset_server.load("character-cards/cards.json"));
@@ -292,17 +506,15 @@ fn load_resources(
         PsychosisCardsHandle(asset_server.load("psychosis-cards/cards.json"));
     commands.insert_resource(psychosis_cards_handle);
 
-    game_state.wallet.address = "0x971C6CDa7EDE9db62732D896995c9ee3A3196e40".to_string();
+    let prompt_fragments_handle = PromptFragmentsHandle(asset_server.load("plot/fragments.json"));
+    commands.insert_resource(prompt_fragments_handle);
 
     // load app settings from wasm container
     #[cfg(target_arch = "wasm32")]
     {
-        let user_connected_wallet = wasm::user_connected_wallet();
         let game_mode = wasm::mode();
         let nft_link = wasm::nft_link();
 
-        game_state.wallet.address = user_connected_wallet;
-
         if game_mode == "player" {
             game_state.game_type = GameType::VisualNovelPlayer;
             game_state.player_nft_url = Some(nft_link);
@@ -317,6 +529,12 @@ fn load_cards(
     character_cards_assets: Res<Assets<CharacterCards>>,
     psychosis_cards_handle: Res<PsychosisCardsHandle>,
     psychosis_cards_assets: Res<Assets<PsychosisCards>>,
+    prompt_fragments_handle: Res<PromptFragmentsHandle>,
+    prompt_fragments_assets: Res<Assets<PromptFragments>>,
+    campaign_handle: Res<CampaignHandle>,
+    campaign_assets: Res<Assets<Campaign>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
     mut game_state: ResMut<GameState>,
     mut app_state: ResMut<NextState<AppState>>,
     mut ew_load_nft: EventWriter<EventLoadNFTRequest>,
@@ -324,7 +542,22 @@ fn load_cards(
     if let Some(narrative_cards) = narrative_cards_assets.get(narrative_cards_handle.id())
         && let Some(character_cards) = character_cards_assets.get(character_cards_handle.id())
         && let Some(psychosis_cards) = psychosis_cards_assets.get(psychosis_cards_handle.id())
+        && let Some(prompt_fragments) = prompt_fragments_assets.get(prompt_fragments_handle.id())
+        && let Some(campaign) = campaign_assets.get(campaign_handle.id())
     {
+        commands.insert_resource(prompt_fragments.clone());
+
+        // every act is loaded up front, the next one starts right from the map
+        commands.insert_resource(CampaignProgress {
+            act: 0,
+            scripts: campaign
+                .acts
+                .iter()
+                .map(|act| asset_server.load(&act.script))
+                .collect(),
+        });
+        commands.insert_resource(campaign.clone());
+
         let mut deck: Vec<VNCard> = vec![];
         for (i, narrative_card) in narrative_cards.iter().enumerate() {
             deck.push(VNCard {
@@ -341,6 +574,11 @@ fn load_cards(
         }
 
         for (i, narrative_card) in character_cards.iter().enumerate() {
+            game_state.character_sprites.insert(
+                narrative_card.name.clone(),
+                narrative_card.expressions.clone(),
+            );
+
             deck.push(VNCard {
                 filename: format!("character-cards/card-{}.png", i + 1),
                 metadata: VNCardMetadata::Character(
@@ -359,6 +597,7 @@ fn load_cards(
                     i + 1,
                     psychosis_card.name.clone(),
                     psychosis_card.description.clone(),
+                    psychosis_card.debuff,
                 ),
             });
         }
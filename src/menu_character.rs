@@ -0,0 +1,315 @@
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+use bevy_hui::prelude::*;
+
+use crate::{
+    api_text2img::{
+        EventText2ImageFailed, EventText2ImageRequest, EventText2ImageResponse, GeneratedAssets,
+        Text2ImageRequestType,
+    },
+    cards_game::{VNCard, VNCardMetadata},
+    AppState, GameState,
+};
+
+const MAX_NAME_LENGTH: usize = 24;
+const MAX_DESCRIPTION_LENGTH: usize = 200;
+
+/// Shown until the portrait is generated
+const PORTRAIT_PLACEHOLDER: &str = "poker-cards/Back_3.png";
+
+pub struct CharacterCreatorPlugin;
+
+impl Plugin for CharacterCreatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CharacterDraft>()
+            .add_systems(OnEnter(AppState::CharacterCreator), show_menu)
+            .add_systems(OnExit(AppState::CharacterCreator), despawn_menu)
+            .add_systems(
+                Update,
+                (
+                    handle_typing,
+                    handle_portrait_response,
+                    handle_portrait_failed,
+                    refresh_draft,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::CharacterCreator)),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct CharacterCreatorMenu {}
+
+#[derive(Default, PartialEq, Eq, Clone, Copy)]
+enum DraftField {
+    #[default]
+    Name,
+    Description,
+}
+
+/// Character being written in the creator
+#[derive(Resource, Default)]
+pub(crate) struct CharacterDraft {
+    name: String,
+    description: String,
+    field: DraftField,
+    /// asset path of the generated portrait
+    portrait: Option<String>,
+    generating: bool,
+    /// the last portrait could not be generated, generating again retries
+    failed: bool,
+}
+
+impl CharacterDraft {
+    fn field_mut(&mut self) -> (&mut String, usize) {
+        match self.field {
+            DraftField::Name => (&mut self.name, MAX_NAME_LENGTH),
+            DraftField::Description => (&mut self.description, MAX_DESCRIPTION_LENGTH),
+        }
+    }
+
+    fn status(&self) -> &str {
+        if self.generating {
+            "GENERATING PORTRAIT"
+        } else if self.name.trim().is_empty() || self.description.trim().is_empty() {
+            "ENTER NAME AND DESCRIPTION, TAB SWITCHES FIELD"
+        } else if self.failed {
+            "PORTRAIT FAILED, GENERATE AGAIN"
+        } else if self.portrait.is_none() {
+            "GENERATE A PORTRAIT"
+        } else {
+            "READY"
+        }
+    }
+}
+
+fn show_menu(
+    mut commands: Commands,
+    mut html_funcs: HtmlFunctions,
+    mut draft: ResMut<CharacterDraft>,
+    asset_server: Res<AssetServer>,
+) {
+    *draft = CharacterDraft::default();
+
+    commands.spawn((
+        HtmlNode(asset_server.load("menu/character_creator.html")),
+        TemplateProperties::default().with("portrait", PORTRAIT_PLACEHOLDER),
+        CharacterCreatorMenu {},
+        Name::new("character creator menu"),
+    ));
+
+    html_funcs.register(
+        "creator_focus_name",
+        |In(_), mut draft: ResMut<CharacterDraft>| {
+            draft.field = DraftField::Name;
+        },
+    );
+
+    html_funcs.register(
+        "creator_focus_description",
+        |In(_), mut draft: ResMut<CharacterDraft>| {
+            draft.field = DraftField::Description;
+        },
+    );
+
+    html_funcs.register(
+        "creator_generate_portrait",
+        |In(_),
+         mut draft: ResMut<CharacterDraft>,
+         mut ew_text_2_image_request: EventWriter<EventText2ImageRequest>| {
+            let name = draft.name.trim().to_string();
+            let description = draft.description.trim().to_string();
+            if draft.generating || name.is_empty() || description.is_empty() {
+                return;
+            }
+
+            ew_text_2_image_request.write(EventText2ImageRequest {
+                prompt: format!(
+                    "Portrait of {}, {}. Visual novel character sprite. Image style: realistic.",
+                    name, description
+                ),
+                request_type: Text2ImageRequestType::Portrait(name),
+            });
+            draft.generating = true;
+            draft.failed = false;
+        },
+    );
+
+    html_funcs.register(
+        "creator_save",
+        |In(_),
+         mut draft: ResMut<CharacterDraft>,
+         mut game_state: ResMut<GameState>,
+         mut app_state: ResMut<NextState<AppState>>| {
+            let Some(portrait) = draft.portrait.clone() else {
+                return;
+            };
+            let name = draft.name.trim().to_string();
+            let description = draft.description.trim().to_string();
+            if draft.generating || name.is_empty() || description.is_empty() {
+                return;
+            }
+
+            add_custom_character(&mut game_state, name, description, portrait);
+
+            *draft = CharacterDraft::default();
+            app_state.set(AppState::MainMenu);
+        },
+    );
+
+    html_funcs.register(
+        "creator_back",
+        |In(_), mut app_state: ResMut<NextState<AppState>>| {
+            app_state.set(AppState::MainMenu);
+        },
+    );
+}
+
+fn despawn_menu(mut commands: Commands, q_menu: Query<(Entity, &CharacterCreatorMenu)>) {
+    for (entity, _) in q_menu.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Player characters are free cards, so they are dealt into the starting deck
+/// of every new game
+fn add_custom_character(
+    game_state: &mut GameState,
+    name: String,
+    description: String,
+    portrait: String,
+) {
+    let index = game_state
+        .game_deck
+        .iter()
+        .filter(|card| card.metadata.is_character())
+        .count()
+        + 1;
+
+    let card = VNCard {
+        filename: portrait.clone(),
+        metadata: VNCardMetadata::Character(index, name.clone(), description, 0),
+    };
+
+    game_state.game_deck.push(card.clone());
+    game_state.collected_deck.push(card);
+    game_state.character_sprites.insert(
+        name,
+        [("neutral".to_string(), portrait)].into_iter().collect(),
+    );
+}
+
+fn handle_typing(mut er_keyboard: EventReader<KeyboardInput>, mut draft: ResMut<CharacterDraft>) {
+    for event in er_keyboard.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Tab => {
+                draft.field = match draft.field {
+                    DraftField::Name => DraftField::Description,
+                    DraftField::Description => DraftField::Name,
+                };
+            }
+            Key::Backspace => {
+                draft.field_mut().0.pop();
+            }
+            Key::Space => {
+                let (text, max_length) = draft.field_mut();
+                if text.chars().count() < max_length {
+                    text.push(' ');
+                }
+            }
+            Key::Character(characters) => {
+                let (text, max_length) = draft.field_mut();
+                for character in characters.chars().filter(|c| !c.is_control()) {
+                    if text.chars().count() < max_length {
+                        text.push(character);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn handle_portrait_response(
+    mut er_text_2_image_response: EventReader<EventText2ImageResponse>,
+    mut draft: ResMut<CharacterDraft>,
+    mut q_images: Query<(&mut ImageNode, &Tags)>,
+    generated_assets: Res<GeneratedAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in er_text_2_image_response.read() {
+        let Text2ImageRequestType::Portrait(name) = &event.request_type else {
+            continue;
+        };
+
+        draft.generating = false;
+
+        let path = format!("characters/{}.png", event.filename);
+        match generated_assets.insert_image(&path, &event.image) {
+            Ok(portrait) => {
+                for (mut image, tags) in q_images.iter_mut() {
+                    if let Some(marker) = tags.get("marker")
+                        && marker == "image_portrait"
+                    {
+                        image.image = asset_server.load(&portrait);
+                    }
+                }
+
+                draft.portrait = Some(portrait);
+            }
+            Err(err) => {
+                error!("portrait of {}: {}", name, err);
+                draft.failed = true;
+            }
+        }
+    }
+}
+
+fn handle_portrait_failed(
+    mut er_text_2_image_failed: EventReader<EventText2ImageFailed>,
+    mut draft: ResMut<CharacterDraft>,
+) {
+    for event in er_text_2_image_failed.read() {
+        if let Text2ImageRequestType::Portrait(_) = event.request_type {
+            draft.generating = false;
+            draft.failed = true;
+        }
+    }
+}
+
+fn refresh_draft(draft: Res<CharacterDraft>, mut q_text_labels: Query<(&mut Text, &Tags)>) {
+    if !draft.is_changed() {
+        return;
+    }
+
+    let cursor = |field: DraftField| if draft.field == field { "_" } else { "" };
+
+    for (mut text, tags) in q_text_labels.iter_mut() {
+        match tags.get("marker").map(|marker| marker.as_str()) {
+            Some("text_name") => {
+                *text = Text::new(format!("{}{}", draft.name, cursor(DraftField::Name)));
+            }
+            Some("text_description") => {
+                *text = Text::new(format!(
+                    "{}{}",
+                    draft.description,
+                    cursor(DraftField::Description)
+                ));
+            }
+            Some("text_status") => {
+                *text = Text::new(draft.status());
+            }
+            _ => (),
+        }
+    }
+}
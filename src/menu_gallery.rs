@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_hui::prelude::*;
+use bevy_wasm_tasks::*;
+use image::DynamicImage;
+
+use crate::{
+    api_nft::{
+        EventListNFTRequest, EventListNFTResponse, EventLoadNFTFailed, EventLoadNFTRequest,
+        OwnedNFT,
+    },
+    api_tasks::spawn_task,
+    api_text2img::{download_and_load_image, GeneratedAssets},
+    api_wallet::Wallet,
+    AppState,
+};
+
+/// Stories listed on one page of the gallery
+const GALLERY_PAGE_SIZE: usize = 4;
+
+/// Shown until a poster is downloaded, or if it cannot be
+const POSTER_PLACEHOLDER: &str = "poker-cards/Back_3.png";
+
+/// Story NFTs owned by the connected wallet, replayed in the novel player
+pub struct GalleryPlugin;
+
+impl Plugin for GalleryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NftGallery>()
+            .init_resource::<GalleryPage>()
+            .add_event::<EventGalleryPoster>()
+            .add_systems(OnEnter(AppState::Gallery), show_menu)
+            .add_systems(OnExit(AppState::Gallery), despawn_menu)
+            .add_systems(
+                Update,
+                (
+                    handle_list_nft_response,
+                    handle_gallery_poster,
+                    handle_load_nft_failed.run_if(in_state(AppState::Gallery)),
+                    refresh_menu
+                        .run_if(resource_changed::<GalleryPage>.or(resource_changed::<NftGallery>))
+                        .run_if(in_state(AppState::Gallery)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct GalleryMenu {}
+
+#[derive(Resource, Default)]
+struct GalleryPage(usize);
+
+/// Listing of the wallet the gallery was last opened with
+#[derive(Resource, Default)]
+struct NftGallery {
+    owner: Option<String>,
+    nfts: Vec<OwnedNFT>,
+    /// line shown above the stories: what is loading or what went wrong
+    status: String,
+    /// asset paths of the downloaded posters, by token id
+    posters: HashMap<usize, String>,
+}
+
+#[derive(Event)]
+struct EventGalleryPoster {
+    nft_id: usize,
+    image: DynamicImage,
+}
+
+/// The menu itself is spawned by `refresh_menu` once the listing is requested
+fn show_menu(
+    mut html_funcs: HtmlFunctions,
+    mut page: ResMut<GalleryPage>,
+    mut gallery: ResMut<NftGallery>,
+    mut ew_list_nft: EventWriter<EventListNFTRequest>,
+    wallet: Res<Wallet>,
+) {
+    page.0 = 0;
+    gallery.nfts.clear();
+    gallery.owner = wallet.address.clone();
+
+    match &wallet.address {
+        Some(owner) => {
+            gallery.status = "LOADING YOUR STORIES...".to_string();
+            ew_list_nft.write(EventListNFTRequest {
+                owner: owner.clone(),
+            });
+        }
+        None => {
+            gallery.status = "CONNECT A WALLET ON THE MAIN MENU TO SEE YOUR STORIES".to_string();
+        }
+    }
+
+    html_funcs.register(
+        "gallery_play",
+        |In(entity): In<Entity>,
+         q_tags: Query<&Tags>,
+         page: Res<GalleryPage>,
+         mut gallery: ResMut<NftGallery>,
+         mut ew_load_nft: EventWriter<EventLoadNFTRequest>| {
+            let Some(slot) = q_tags
+                .get(entity)
+                .ok()
+                .and_then(|tags| tags.get("slot"))
+                .and_then(|slot| slot.parse::<usize>().ok())
+            else {
+                return;
+            };
+            let Some(nft) = gallery.nfts.get(page.0 * GALLERY_PAGE_SIZE + slot).cloned() else {
+                return;
+            };
+
+            // the novel player takes over once the metadata is loaded
+            ew_load_nft.write(EventLoadNFTRequest { url: nft.url });
+            gallery.status = format!("LOADING {}...", nft.name.to_uppercase());
+        },
+    );
+
+    html_funcs.register(
+        "gallery_previous",
+        |In(_), mut page: ResMut<GalleryPage>| {
+            page.0 = page.0.saturating_sub(1);
+        },
+    );
+
+    html_funcs.register(
+        "gallery_next",
+        |In(_), mut page: ResMut<GalleryPage>, gallery: Res<NftGallery>| {
+            if (page.0 + 1) * GALLERY_PAGE_SIZE < gallery.nfts.len() {
+                page.0 += 1;
+            }
+        },
+    );
+
+    html_funcs.register(
+        "gallery_back",
+        |In(_), mut app_state: ResMut<NextState<AppState>>| {
+            app_state.set(AppState::MainMenu);
+        },
+    );
+}
+
+fn handle_list_nft_response(
+    mut er_list_nft_response: EventReader<EventListNFTResponse>,
+    mut gallery: ResMut<NftGallery>,
+    tasks: Tasks,
+) {
+    for event in er_list_nft_response.read() {
+        if gallery.owner.as_ref() != Some(&event.owner) {
+            continue;
+        }
+
+        let Some(nfts) = &event.nfts else {
+            gallery.status = "YOUR STORIES COULD NOT BE LOADED".to_string();
+            continue;
+        };
+
+        gallery.status = match nfts.is_empty() {
+            true => "NO STORIES MINTED YET, FINISH A RUN TO MINT ONE".to_string(),
+            false => String::new(),
+        };
+        gallery.nfts = nfts.clone();
+
+        for nft in nfts.iter() {
+            if !gallery.posters.contains_key(&nft.nft_id) {
+                download_poster(&tasks, nft);
+            }
+        }
+    }
+}
+
+/// The player stays in the gallery and can pick another story
+fn handle_load_nft_failed(
+    mut er_load_nft_failed: EventReader<EventLoadNFTFailed>,
+    mut gallery: ResMut<NftGallery>,
+) {
+    for event in er_load_nft_failed.read() {
+        gallery.status = event.error.clone();
+    }
+}
+
+fn download_poster(tasks: &Tasks, nft: &OwnedNFT) {
+    let nft_id = nft.nft_id;
+    let url = nft.image.clone();
+
+    spawn_task(
+        tasks,
+        async move {
+            let image = download_and_load_image(&url).await;
+            (url, image)
+        },
+        move |world, (url, image)| match image {
+            Ok(image) => {
+                world.send_event(EventGalleryPoster { nft_id, image });
+            }
+            Err(err) => warn!("poster {}: {:?}", url, err),
+        },
+    );
+}
+
+fn handle_gallery_poster(
+    mut er_gallery_poster: EventReader<EventGalleryPoster>,
+    mut gallery: ResMut<NftGallery>,
+    generated_assets: Res<GeneratedAssets>,
+) {
+    for event in er_gallery_poster.read() {
+        match generated_assets.insert_image(&format!("gallery/{}.png", event.nft_id), &event.image)
+        {
+            Ok(path) => {
+                gallery.posters.insert(event.nft_id, path);
+            }
+            Err(err) => warn!("poster {}: {}", event.nft_id, err),
+        }
+    }
+}
+
+fn refresh_menu(
+    mut commands: Commands,
+    q_menu: Query<(Entity, &GalleryMenu)>,
+    page: Res<GalleryPage>,
+    gallery: Res<NftGallery>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, _) in q_menu.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let page = page.0;
+    let first = page * GALLERY_PAGE_SIZE;
+    let n_pages = gallery.nfts.len().div_ceil(GALLERY_PAGE_SIZE).max(1);
+
+    let mut properties = TemplateProperties::default()
+        .with("status", &gallery.status)
+        .with(
+            "status_display",
+            if gallery.status.is_empty() {
+                "none"
+            } else {
+                "flex"
+            },
+        )
+        .with("page", &format!("PAGE {}/{}", page + 1, n_pages));
+
+    for slot in 0..GALLERY_PAGE_SIZE {
+        let nft = gallery.nfts.get(first + slot);
+        properties = properties
+            .with(
+                &format!("story_{}", slot),
+                &nft.map(|nft| nft.name.clone()).unwrap_or_default(),
+            )
+            .with(
+                &format!("poster_{}", slot),
+                nft.and_then(|nft| gallery.posters.get(&nft.nft_id))
+                    .map(|path| path.as_str())
+                    .unwrap_or(POSTER_PLACEHOLDER),
+            )
+            .with(
+                &format!("story_{}_display", slot),
+                if nft.is_some() { "flex" } else { "none" },
+            );
+    }
+
+    commands.spawn((
+        HtmlNode(asset_server.load("menu/gallery_menu.html")),
+        properties,
+        GalleryMenu {},
+        Name::new("gallery menu"),
+    ));
+}
+
+fn despawn_menu(mut commands: Commands, q_menu: Query<(Entity, &GalleryMenu)>) {
+    for (entity, _) in q_menu.iter() {
+        commands.entity(entity).despawn();
+    }
+}
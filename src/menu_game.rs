@@ -2,9 +2,23 @@ use bevy::prelude::*;
 
 use bevy_hui::prelude::*;
 use bevy_kira_audio::*;
-use bevy_novel::events::EventSwitchNextNode;
+use bevy_novel::{
+    events::{EventStartScenario, EventSwitchNextNode},
+    rpy_asset_loader::Rpy,
+    NovelSettings,
+};
 
-use crate::{AppState, EventEndCardGame, EventPlayHand, GameState, GameType};
+use crate::{
+    api_llm::EventLLMRequest,
+    api_mint::MintJob,
+    api_nft::EventPersistScenarioRequest,
+    api_wallet::Wallet,
+    cards_forge::{forge_candidates, forge_menu, start_forging, CardForge, MAX_FORGE_SLOTS},
+    story_campaign::{CampaignProgress, MAX_ACTS},
+    story_export::StoryBeat,
+    story_format::MAX_CHOICES,
+    AppState, EventEndCardGame, EventPlayHand, GameState, GameType,
+};
 
 pub struct GameMenuPlugin;
 
@@ -44,9 +58,9 @@ pub enum EventRefreshUI {
     PokerMenu(PokerMenuSettings),
     NovelMenu(String),
     ShopMenu,
+    ForgeStatus(String),
     LoadingMenu,
     Narrative(NarrativeMenuSettings),
-    GameOver(usize),
 }
 
 /// Despawn previous menu template and render a new one
@@ -59,6 +73,14 @@ pub enum EventRenderUI {
     Loading,
     Narrative,
     GameOver,
+    /// game over menu after the last act of the campaign is passed
+    CampaignWon,
+    /// options the player picks from at the end of a generated chapter
+    Choices(Vec<String>),
+    /// owned narrative cards, and whether each is picked for forging
+    Forge(Vec<(String, bool)>),
+    /// acts of the campaign between two acts, one line each
+    CampaignMap(Vec<String>),
 }
 
 #[derive(Event, PartialEq, Eq, Default, Debug)]
@@ -108,6 +130,124 @@ pub fn show_menu(
             ew_switch_next_node.write(EventSwitchNextNode {});
         },
     );
+
+    // the picked option becomes part of the story and the chapter goes on
+    html_funcs.register(
+        "choose",
+        |In(entity): In<Entity>,
+         q_tags: Query<&Tags>,
+         mut ew_switch_next_node: EventWriter<EventSwitchNextNode>,
+         mut novel_settings: ResMut<NovelSettings>,
+         mut game_state: ResMut<GameState>| {
+            let Some(choice) = q_tags
+                .get(entity)
+                .ok()
+                .and_then(|tags| tags.get("choice"))
+                .and_then(|choice| choice.parse::<usize>().ok())
+                .and_then(|choice| game_state.story_choices.get(choice).cloned())
+            else {
+                return;
+            };
+
+            game_state
+                .narrative_story_so_far
+                .push(format!("Main character chose to: {}", choice));
+            game_state.story_beats.push(StoryBeat::choice(&choice));
+            game_state.story_choice = Some(choice);
+            game_state.story_choices.clear();
+
+            novel_settings.pause_handle_switch_node = false;
+            ew_switch_next_node.write(EventSwitchNextNode {});
+        },
+    );
+
+    // card forge, opened from the shop
+    html_funcs.register(
+        "forge",
+        |In(_),
+         mut ew_render_ui: EventWriter<EventRenderUI>,
+         game_state: Res<GameState>,
+         card_forge: Res<CardForge>| {
+            ew_render_ui.write(forge_menu(&game_state, &card_forge));
+        },
+    );
+
+    html_funcs.register(
+        "forge_pick",
+        |In(entity): In<Entity>,
+         q_tags: Query<&Tags>,
+         mut ew_render_ui: EventWriter<EventRenderUI>,
+         game_state: Res<GameState>,
+         mut card_forge: ResMut<CardForge>| {
+            let Some(slot) = q_tags
+                .get(entity)
+                .ok()
+                .and_then(|tags| tags.get("slot"))
+                .and_then(|slot| slot.parse::<usize>().ok())
+            else {
+                return;
+            };
+
+            if let Some((position, _)) = forge_candidates(&game_state).get(slot) {
+                card_forge.toggle(*position);
+            }
+
+            ew_render_ui.write(forge_menu(&game_state, &card_forge));
+        },
+    );
+
+    html_funcs.register(
+        "forge_cards",
+        |In(_),
+         mut ew_render_ui: EventWriter<EventRenderUI>,
+         mut ew_refresh_ui: EventWriter<EventRefreshUI>,
+         mut ew_llm_request: EventWriter<EventLLMRequest>,
+         game_state: Res<GameState>,
+         mut card_forge: ResMut<CardForge>| {
+            if start_forging(&game_state, &mut card_forge, &mut ew_llm_request) {
+                ew_render_ui.write(EventRenderUI::Shop);
+                ew_refresh_ui.write(EventRefreshUI::ForgeStatus("forging...".to_string()));
+            }
+        },
+    );
+
+    html_funcs.register(
+        "forge_back",
+        |In(_), mut ew_render_ui: EventWriter<EventRenderUI>| {
+            ew_render_ui.write(EventRenderUI::Shop);
+        },
+    );
+
+    // campaign map, shown between acts
+    html_funcs.register(
+        "next_act",
+        |In(_),
+         mut ew_start_scenario: EventWriter<EventStartScenario>,
+         mut ew_render_ui: EventWriter<EventRenderUI>,
+         mut novel_settings: ResMut<NovelSettings>,
+         mut campaign_progress: ResMut<CampaignProgress>,
+         rpy_assets: Res<Assets<Rpy>>| {
+            let Some(ast) = campaign_progress.next_act(&rpy_assets) else {
+                return;
+            };
+
+            ew_start_scenario.write(EventStartScenario { ast });
+            ew_render_ui.write(EventRenderUI::Novel);
+            novel_settings.pause_handle_switch_node = false;
+        },
+    );
+
+    // game over, mints the finished story again without replaying the run
+    html_funcs.register(
+        "mint_retry",
+        |In(_),
+         mint_job: Res<MintJob>,
+         mut ew_persist_scenario: EventWriter<EventPersistScenarioRequest>| {
+            if let Some(request) = mint_job.retry_request() {
+                ew_persist_scenario.write(request);
+            }
+        },
+    );
 }
 
 fn despawn_menu(
@@ -128,6 +268,7 @@ fn render_ui(
     q_game_menu: Query<(Entity, &GameMenu)>,
     asset_server: Res<AssetServer>,
     game_state: Res<GameState>,
+    wallet: Res<Wallet>,
 ) {
     for event in er_refresh_ui.read() {
         for (entity, _) in q_game_menu.iter() {
@@ -183,14 +324,94 @@ fn render_ui(
                     Name::new("narative menu"),
                 ));
             }
-            EventRenderUI::GameOver => {
+            EventRenderUI::GameOver | EventRenderUI::CampaignWon => {
                 commands.spawn((
                     HtmlNode(asset_server.load("menu/game_over.html")),
-                    TemplateProperties::default(),
+                    TemplateProperties::default()
+                        .with(
+                            "title",
+                            match event {
+                                EventRenderUI::CampaignWon => "CAMPAIGN WON",
+                                _ => "GAME OVER",
+                            },
+                        )
+                        .with(
+                            "minting_status",
+                            match wallet.address {
+                                Some(_) => "MINTING YOUR STORY ON BLOCKCHAIN...",
+                                None => {
+                                    "CONNECT A WALLET TO MINT YOUR STORY, IT IS KEPT IN THE LIBRARY"
+                                }
+                            },
+                        ),
                     GameMenu {},
                     Name::new("game over menu"),
                 ));
             }
+            EventRenderUI::Choices(choices) => {
+                let mut properties = TemplateProperties::default();
+                for i in 0..MAX_CHOICES {
+                    properties = properties
+                        .with(
+                            &format!("choice_{}", i),
+                            choices.get(i).map(|c| c.as_str()).unwrap_or_default(),
+                        )
+                        .with(
+                            &format!("choice_{}_display", i),
+                            if i < choices.len() { "flex" } else { "none" },
+                        );
+                }
+
+                commands.spawn((
+                    HtmlNode(asset_server.load("menu/choices_menu.html")),
+                    properties,
+                    GameMenu {},
+                    Name::new("choices menu"),
+                ));
+            }
+            EventRenderUI::Forge(cards) => {
+                let mut properties = TemplateProperties::default();
+                for i in 0..MAX_FORGE_SLOTS {
+                    let label = match cards.get(i) {
+                        Some((name, true)) => format!("> {}", name),
+                        Some((name, false)) => name.clone(),
+                        None => String::new(),
+                    };
+                    properties = properties.with(&format!("card_{}", i), &label).with(
+                        &format!("card_{}_display", i),
+                        if i < cards.len() { "flex" } else { "none" },
+                    );
+                }
+
+                commands.spawn((
+                    HtmlNode(asset_server.load("menu/forge_menu.html")),
+                    properties,
+                    GameMenu {},
+                    Name::new("forge menu"),
+                ));
+            }
+            EventRenderUI::CampaignMap(acts) => {
+                let mut properties =
+                    TemplateProperties::default().with("score", &format!("{}", game_state.score));
+                for i in 0..MAX_ACTS {
+                    properties = properties
+                        .with(
+                            &format!("act_{}", i),
+                            acts.get(i).map(|a| a.as_str()).unwrap_or_default(),
+                        )
+                        .with(
+                            &format!("act_{}_display", i),
+                            if i < acts.len() { "flex" } else { "none" },
+                        );
+                }
+
+                commands.spawn((
+                    HtmlNode(asset_server.load("menu/campaign_map.html")),
+                    properties,
+                    GameMenu {},
+                    Name::new("campaign map"),
+                ));
+            }
         }
     }
 }
@@ -237,6 +458,15 @@ fn refresh_ui(
                     }
                 }
             }
+            EventRefreshUI::ForgeStatus(status) => {
+                for (_, mut text, tags) in q_text_labels.iter_mut() {
+                    if let Some(marker) = tags.get("marker")
+                        && marker == "text_forge_status"
+                    {
+                        *text = Text::new(status);
+                    }
+                }
+            }
             EventRefreshUI::NovelMenu(title) => {
                 for (_, mut text, tags) in q_text_labels.iter_mut() {
                     if let Some(marker) = tags.get("marker")
@@ -275,15 +505,6 @@ fn refresh_ui(
                     }
                 }
             }
-            EventRefreshUI::GameOver(nft_id) => {
-                for (_, mut text, tags) in q_text_labels.iter_mut() {
-                    if let Some(marker) = tags.get("marker")
-                        && marker == "text_minting_status"
-                    {
-                        *text = Text::new(format!("nft minted to your wallet. id: {}", *nft_id));
-                    }
-                }
-            }
         }
     }
 }
@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use bevy_hui::prelude::*;
+
+use crate::{
+    api_text2img::GeneratedAssets,
+    story_book::EventExportStorybook,
+    story_export::EventReplayStory,
+    story_library::{last_scene, LibraryEntry, StoryLibrary},
+    story_renpy::EventExportRenpyProject,
+    AppState,
+};
+
+/// Stories listed on one page of the library screen
+const LIBRARY_PAGE_SIZE: usize = 5;
+
+/// Shown for stories saved without a background
+const THUMBNAIL_PLACEHOLDER: &str = "poker-cards/Back_3.png";
+
+pub struct LibraryPlugin;
+
+impl Plugin for LibraryPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(StoryLibrary::load())
+            .init_resource::<LibraryPage>()
+            .add_systems(OnEnter(AppState::Library), show_menu)
+            .add_systems(OnExit(AppState::Library), despawn_menu)
+            .add_systems(
+                Update,
+                refresh_menu
+                    .run_if(resource_changed::<LibraryPage>)
+                    .run_if(in_state(AppState::Library)),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct LibraryMenu {}
+
+#[derive(Resource, Default)]
+struct LibraryPage(usize);
+
+/// The menu itself is spawned by `refresh_menu` once the page is reset
+fn show_menu(mut html_funcs: HtmlFunctions, mut page: ResMut<LibraryPage>) {
+    page.0 = 0;
+
+    html_funcs.register(
+        "library_play",
+        |In(entity): In<Entity>,
+         q_tags: Query<&Tags>,
+         page: Res<LibraryPage>,
+         library: Res<StoryLibrary>,
+         mut app_state: ResMut<NextState<AppState>>,
+         mut ew_replay_story: EventWriter<EventReplayStory>| {
+            let Some(entry) = slot_entry(entity, &q_tags, &page, &library) else {
+                return;
+            };
+
+            app_state.set(AppState::NovelPlayer);
+            ew_replay_story.write(EventReplayStory {
+                story: entry.story.clone(),
+            });
+        },
+    );
+
+    html_funcs.register(
+        "library_export",
+        |In(entity): In<Entity>,
+         q_tags: Query<&Tags>,
+         page: Res<LibraryPage>,
+         library: Res<StoryLibrary>,
+         mut ew_export_renpy_project: EventWriter<EventExportRenpyProject>| {
+            let Some(entry) = slot_entry(entity, &q_tags, &page, &library) else {
+                return;
+            };
+
+            ew_export_renpy_project.write(EventExportRenpyProject {
+                name: format!("story-{}", entry.saved_at),
+                story: entry.story.clone(),
+            });
+        },
+    );
+
+    html_funcs.register(
+        "library_storybook",
+        |In(entity): In<Entity>,
+         q_tags: Query<&Tags>,
+         page: Res<LibraryPage>,
+         library: Res<StoryLibrary>,
+         mut ew_export_storybook: EventWriter<EventExportStorybook>| {
+            let Some(entry) = slot_entry(entity, &q_tags, &page, &library) else {
+                return;
+            };
+
+            ew_export_storybook.write(EventExportStorybook {
+                name: format!("story-{}", entry.saved_at),
+                story: entry.story.clone(),
+            });
+        },
+    );
+
+    html_funcs.register(
+        "library_previous",
+        |In(_), mut page: ResMut<LibraryPage>| {
+            page.0 = page.0.saturating_sub(1);
+        },
+    );
+
+    html_funcs.register(
+        "library_next",
+        |In(_), mut page: ResMut<LibraryPage>, library: Res<StoryLibrary>| {
+            if (page.0 + 1) * LIBRARY_PAGE_SIZE < library.entries.len() {
+                page.0 += 1;
+            }
+        },
+    );
+
+    html_funcs.register(
+        "library_back",
+        |In(_), mut app_state: ResMut<NextState<AppState>>| {
+            app_state.set(AppState::MainMenu);
+        },
+    );
+}
+
+/// Library entry behind a button tagged with its slot on the page
+fn slot_entry<'a>(
+    entity: Entity,
+    q_tags: &Query<&Tags>,
+    page: &LibraryPage,
+    library: &'a StoryLibrary,
+) -> Option<&'a LibraryEntry> {
+    let slot = q_tags
+        .get(entity)
+        .ok()?
+        .get("slot")?
+        .parse::<usize>()
+        .ok()?;
+    library.entries.get(page.0 * LIBRARY_PAGE_SIZE + slot)
+}
+
+fn refresh_menu(
+    mut commands: Commands,
+    q_menu: Query<(Entity, &LibraryMenu)>,
+    page: Res<LibraryPage>,
+    library: Res<StoryLibrary>,
+    generated_assets: Res<GeneratedAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, _) in q_menu.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let page = page.0;
+    let first = page * LIBRARY_PAGE_SIZE;
+    let n_pages = library.entries.len().div_ceil(LIBRARY_PAGE_SIZE).max(1);
+
+    let mut properties = TemplateProperties::default()
+        .with(
+            "empty_display",
+            if library.entries.is_empty() {
+                "flex"
+            } else {
+                "none"
+            },
+        )
+        .with("page", &format!("PAGE {}/{}", page + 1, n_pages));
+
+    for slot in 0..LIBRARY_PAGE_SIZE {
+        let entry = library.entries.get(first + slot);
+        properties = properties
+            .with(
+                &format!("story_{}", slot),
+                &entry.map(LibraryEntry::label).unwrap_or_default(),
+            )
+            .with(
+                &format!("thumbnail_{}", slot),
+                &entry
+                    .map(|entry| thumbnail(entry, &generated_assets))
+                    .unwrap_or(THUMBNAIL_PLACEHOLDER.to_string()),
+            )
+            .with(
+                &format!("story_{}_display", slot),
+                if entry.is_some() { "flex" } else { "none" },
+            );
+    }
+
+    commands.spawn((
+        HtmlNode(asset_server.load("menu/library_menu.html")),
+        properties,
+        LibraryMenu {},
+        Name::new("library menu"),
+    ));
+}
+
+/// Saved thumbnail, else the last background if it ships with the game
+fn thumbnail(entry: &LibraryEntry, generated_assets: &GeneratedAssets) -> String {
+    if !entry.thumbnail.is_empty() {
+        return generated_assets.insert_png(
+            &format!("library/{}.png", entry.saved_at),
+            entry.thumbnail.clone(),
+        );
+    }
+
+    match last_scene(&entry.story) {
+        Some(scene) if !scene.ends_with(".jpeg") => format!("plot/{}.png", scene),
+        _ => THUMBNAIL_PLACEHOLDER.to_string(),
+    }
+}
+
+fn despawn_menu(mut commands: Commands, q_menu: Query<(Entity, &LibraryMenu)>) {
+    for (entity, _) in q_menu.iter() {
+        commands.entity(entity).despawn();
+    }
+}
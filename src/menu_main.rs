@@ -16,6 +16,7 @@ use bevy_la_mesa::{
 use rand::Rng;
 
 use crate::{
+    api_wallet::{EventWalletConnect, EventWalletDisconnect, Wallet},
     cards_game::{filter_narrative_cards, VNCard},
     AppState, GameState,
 };
@@ -79,6 +80,7 @@ pub fn show_menu(
     mut standard_materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
     mut html_funcs: HtmlFunctions,
+    wallet: Res<Wallet>,
     game_state: Res<GameState>,
     mut ew_render_deck: EventWriter<RenderDeck<VNCard>>,
 ) {
@@ -94,7 +96,9 @@ pub fn show_menu(
     // menu
     commands.spawn((
         HtmlNode(asset_server.load("menu/main_menu.html")),
-        TemplateProperties::default().with("user_wallet_address", &game_state.wallet.address),
+        TemplateProperties::default()
+            .with("wallet_status", &wallet.status())
+            .with("wallet_button", wallet.button_label()),
         MainMenuResource {},
     ));
 
@@ -115,6 +119,44 @@ pub fn show_menu(
         },
     );
 
+    html_funcs.register(
+        "create_character",
+        |In(_), mut app_state: ResMut<NextState<AppState>>| {
+            app_state.set(AppState::CharacterCreator);
+        },
+    );
+
+    html_funcs.register(
+        "wallet_toggle",
+        |In(_),
+         wallet: Res<Wallet>,
+         mut ew_wallet_connect: EventWriter<EventWalletConnect>,
+         mut ew_wallet_disconnect: EventWriter<EventWalletDisconnect>| {
+            match wallet.address {
+                Some(_) => {
+                    ew_wallet_disconnect.write(EventWalletDisconnect {});
+                }
+                None => {
+                    ew_wallet_connect.write(EventWalletConnect {});
+                }
+            }
+        },
+    );
+
+    html_funcs.register(
+        "open_library",
+        |In(_), mut app_state: ResMut<NextState<AppState>>| {
+            app_state.set(AppState::Library);
+        },
+    );
+
+    html_funcs.register(
+        "open_gallery",
+        |In(_), mut app_state: ResMut<NextState<AppState>>| {
+            app_state.set(AppState::Gallery);
+        },
+    );
+
     // deck
     let deck_shop_cards = commands
         .spawn((
@@ -0,0 +1,227 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cards_game::VNCard, GameState};
+
+/// Trait of the minted story, in the ERC-721 metadata `attributes` format;
+/// traits with several values are repeated
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NftAttribute {
+    pub trait_type: String,
+    pub value: AttributeValue,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Number(i64),
+    Text(String),
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::Number(number) => write!(f, "{}", number),
+            AttributeValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl NftAttribute {
+    fn number(trait_type: &str, value: i64) -> Self {
+        NftAttribute {
+            trait_type: trait_type.to_string(),
+            value: AttributeValue::Number(value),
+        }
+    }
+
+    fn text(trait_type: &str, value: String) -> Self {
+        NftAttribute {
+            trait_type: trait_type.to_string(),
+            value: AttributeValue::Text(value),
+        }
+    }
+}
+
+/// Categories counted in the `... Cards` traits, in display order
+const CARD_CATEGORIES: [(&str, &str); 5] = [
+    ("setting", "Setting Cards"),
+    ("character", "Character Cards"),
+    ("conflict", "Conflict Cards"),
+    ("plot twist", "Plot Twist Cards"),
+    ("psychosis", "Psychosis Cards"),
+];
+
+/// Statistics of the finished run minted with the story
+pub(crate) fn run_attributes(game_state: &GameState) -> Vec<NftAttribute> {
+    let played = game_state
+        .played_cards
+        .iter()
+        .filter_map(|name| find_card(game_state, name))
+        .collect::<Vec<&VNCard>>();
+
+    let mut attributes = vec![NftAttribute::number("Score", game_state.score as i64)];
+
+    if let Some(combination) = &game_state.best_poker_combination {
+        attributes.push(NftAttribute::text(
+            "Best Poker Hand",
+            combination.to_string(),
+        ));
+    }
+
+    for (card_type, trait_type) in CARD_CATEGORIES {
+        let count = played
+            .iter()
+            .filter(|card| card.metadata.card_type().as_deref() == Some(card_type))
+            .count();
+        attributes.push(NftAttribute::number(trait_type, count as i64));
+    }
+
+    let mut genres = played
+        .iter()
+        .filter_map(|card| card.metadata.genre())
+        .filter(|genre| !genre.is_empty())
+        .collect::<Vec<String>>();
+    genres.sort();
+    genres.dedup();
+    attributes.extend(
+        genres
+            .into_iter()
+            .map(|genre| NftAttribute::text("Genre", genre)),
+    );
+
+    attributes.extend(
+        game_state
+            .characters
+            .0
+            .iter()
+            .map(|character| NftAttribute::text("Character", character.name.clone())),
+    );
+
+    attributes.extend(
+        played
+            .iter()
+            .filter(|card| card.metadata.is_psychosis())
+            .filter_map(|card| card.metadata.name())
+            .map(|name| NftAttribute::text("Psychosis", name)),
+    );
+
+    attributes.push(NftAttribute::text(
+        "Seed",
+        format!("{:016x}", game_state.seed),
+    ));
+    attributes.push(NftAttribute::text(
+        "Game Version",
+        env!("CARGO_PKG_VERSION").to_string(),
+    ));
+
+    attributes
+}
+
+/// Played cards are looked up by name, bought and forged cards are only in
+/// the collected deck
+fn find_card<'a>(game_state: &'a GameState, name: &str) -> Option<&'a VNCard> {
+    game_state
+        .collected_deck
+        .iter()
+        .chain(game_state.game_deck.iter())
+        .find(|card| card.metadata.name().as_deref() == Some(name))
+}
+
+/// One line per trait for the replay title screen, repeated traits joined
+pub(crate) fn attribute_lines(attributes: &[NftAttribute]) -> Vec<String> {
+    let mut lines: Vec<(String, Vec<String>)> = vec![];
+    for attribute in attributes {
+        let value = attribute.value.to_string();
+        match lines
+            .iter_mut()
+            .find(|(trait_type, _)| *trait_type == attribute.trait_type)
+        {
+            Some((_, values)) => values.push(value),
+            None => lines.push((attribute.trait_type.clone(), vec![value])),
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(trait_type, values)| format!("{}: {}", trait_type.to_uppercase(), values.join(", ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cards_game::{PokerCombination, PsychosisDebuff, VNCardMetadata};
+
+    fn narrative(card_type: &str, genre: &str, name: &str) -> VNCard {
+        VNCard {
+            filename: String::new(),
+            metadata: VNCardMetadata::Narrative(
+                1,
+                card_type.to_string(),
+                genre.to_string(),
+                name.to_string(),
+                String::new(),
+                10,
+            ),
+        }
+    }
+
+    #[test]
+    fn test_run_attributes() {
+        let mut game_state = GameState {
+            game_deck: vec![
+                narrative("setting", "noir", "Rainy City"),
+                narrative("plot twist", "fantasy", "Time Warp"),
+                narrative("setting", "noir", "Harbor"),
+                VNCard {
+                    filename: String::new(),
+                    metadata: VNCardMetadata::Psychosis(
+                        1,
+                        "Paranoia".to_string(),
+                        String::new(),
+                        PsychosisDebuff::HiddenCells(2),
+                    ),
+                },
+            ],
+            played_cards: vec![
+                "Rainy City".to_string(),
+                "Time Warp".to_string(),
+                "Harbor".to_string(),
+                "Paranoia".to_string(),
+            ],
+            best_poker_combination: Some(PokerCombination::FullHouse),
+            score: 120,
+            seed: 255,
+            ..Default::default()
+        };
+        game_state
+            .characters
+            .add("Igor".to_string(), "A servant".to_string());
+
+        let attributes = run_attributes(&game_state);
+        assert_eq!(
+            serde_json::to_string(&attributes[..2]).unwrap(),
+            r#"[{"trait_type":"Score","value":120},{"trait_type":"Best Poker Hand","value":"Full House"}]"#
+        );
+
+        let lines = attribute_lines(&attributes);
+        assert_eq!(
+            lines[..9],
+            [
+                "SCORE: 120",
+                "BEST POKER HAND: Full House",
+                "SETTING CARDS: 2",
+                "CHARACTER CARDS: 0",
+                "CONFLICT CARDS: 0",
+                "PLOT TWIST CARDS: 1",
+                "PSYCHOSIS CARDS: 1",
+                "GENRE: fantasy, noir",
+                "CHARACTER: Igor",
+            ]
+        );
+        assert_eq!(lines[9], "PSYCHOSIS: Paranoia");
+        assert_eq!(lines[10], "SEED: 00000000000000ff");
+    }
+}
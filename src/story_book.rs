@@ -0,0 +1,553 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Write},
+};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bevy::prelude::*;
+use image::{DynamicImage, ImageFormat};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    api_text2img::{EventDownloadImageRequest, EventDownloadImageResponse},
+    story_export::{StoryBeat, StoryExport},
+    story_library::{format_date, now, story_title},
+};
+
+/// Storybooks are written under this directory, named after the story
+#[cfg(not(target_arch = "wasm32"))]
+const STORYBOOK_DIR: &str = "storybook";
+
+/// Scenes that haven't loaded by then are left out of the book
+const IMAGE_TIMEOUT_SECONDS: f32 = 30.0;
+
+const STYLE: &str = "body { max-width: 720px; margin: 0 auto; padding: 20px; \
+font-family: Georgia, serif; line-height: 1.5; }
+img { width: 100%; }
+.score, .cards { font-style: italic; }
+.who { font-weight: bold; }";
+
+/// Part of the story under one background, with the cards played before it
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Chapter {
+    pub scene: Option<String>,
+    pub cards: Vec<String>,
+    /// speaker, if any, and the line
+    pub lines: Vec<(Option<String>, String)>,
+}
+
+impl Chapter {
+    fn new(scene: Option<String>, cards: Vec<String>) -> Self {
+        Chapter {
+            scene,
+            cards,
+            lines: vec![],
+        }
+    }
+
+    /// Cards and lines as (X)HTML paragraphs
+    fn body(&self) -> String {
+        let mut body = String::new();
+        if !self.cards.is_empty() {
+            body.push_str(&format!(
+                "<p class=\"cards\">Cards played: {}</p>\n",
+                escape(&self.cards.join(", "))
+            ));
+        }
+
+        for (who, what) in self.lines.iter() {
+            match who {
+                Some(who) => body.push_str(&format!(
+                    "<p><span class=\"who\">{}:</span> {}</p>\n",
+                    escape(who),
+                    escape(what)
+                )),
+                None => body.push_str(&format!("<p>{}</p>\n", escape(what))),
+            }
+        }
+
+        body
+    }
+}
+
+/// Every background starts a chapter; cards played after the last one close
+/// the book
+pub(crate) fn chapters(story: &StoryExport) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = vec![];
+    let mut cards = vec![];
+
+    for beat in story.beats.iter() {
+        match beat {
+            StoryBeat::Card { name } => cards.push(name.clone()),
+            StoryBeat::Scene { image } => {
+                chapters.push(Chapter::new(
+                    Some(image.clone()),
+                    std::mem::take(&mut cards),
+                ));
+            }
+            StoryBeat::Say { who, what } => {
+                if chapters.is_empty() {
+                    chapters.push(Chapter::new(None, std::mem::take(&mut cards)));
+                }
+                if let Some(chapter) = chapters.last_mut() {
+                    chapter.lines.push((who.clone(), what.clone()));
+                }
+            }
+            StoryBeat::Show { .. } | StoryBeat::Hide { .. } => (),
+        }
+    }
+
+    if !cards.is_empty() {
+        match chapters.last_mut() {
+            Some(chapter) => chapter.cards.extend(cards),
+            None => chapters.push(Chapter::new(None, cards)),
+        }
+    }
+
+    chapters
+}
+
+/// Single page with the scenes inlined, readable without the game
+pub(crate) fn storybook_html(story: &StoryExport, images: &BTreeMap<String, Vec<u8>>) -> String {
+    let title = escape(&story_title(story));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\"/>\n\
+         <title>{title}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n<p class=\"score\">Final score: ${}</p>\n",
+        story.score
+    );
+
+    for (i, chapter) in chapters(story).iter().enumerate() {
+        html.push_str(&format!("<section>\n<h2>Chapter {}</h2>\n", i + 1));
+        if let Some(jpeg) = chapter.scene.as_ref().and_then(|scene| images.get(scene)) {
+            html.push_str(&format!(
+                "<img src=\"data:image/jpeg;base64,{}\" alt=\"\"/>\n",
+                STANDARD.encode(jpeg)
+            ));
+        }
+        html.push_str(&chapter.body());
+        html.push_str("</section>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// EPUB 3 with one XHTML file per chapter; `modified` is an ISO 8601 UTC
+/// timestamp
+pub(crate) fn storybook_epub(
+    story: &StoryExport,
+    images: &BTreeMap<String, Vec<u8>>,
+    id: &str,
+    modified: &str,
+) -> Result<Vec<u8>> {
+    let title = escape(&story_title(story));
+    let chapters = chapters(story);
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // readers expect the uncompressed mimetype first
+    zip.start_file(
+        "mimetype",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(
+        b"<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+<rootfiles>\n\
+<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n\
+</rootfiles>\n\
+</container>\n",
+    )?;
+
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLE.as_bytes())?;
+
+    let mut manifest = vec![
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>".to_string(),
+        "<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>".to_string(),
+    ];
+    let mut spine = vec![];
+    let mut toc = vec![];
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        let n = i + 1;
+        let mut image = String::new();
+
+        if let Some(jpeg) = chapter.scene.as_ref().and_then(|scene| images.get(scene)) {
+            zip.start_file(format!("OEBPS/images/chapter_{}.jpg", n), deflated)?;
+            zip.write_all(jpeg)?;
+
+            manifest.push(format!(
+                "<item id=\"image_{n}\" href=\"images/chapter_{n}.jpg\" media-type=\"image/jpeg\"/>"
+            ));
+            image = format!("<img src=\"images/chapter_{}.jpg\" alt=\"\"/>\n", n);
+        }
+
+        zip.start_file(format!("OEBPS/chapter_{}.xhtml", n), deflated)?;
+        zip.write_all(
+            xhtml(
+                &format!("Chapter {}", n),
+                &format!(
+                    "<section epub:type=\"chapter\">\n<h2>Chapter {}</h2>\n{}{}</section>\n",
+                    n,
+                    image,
+                    chapter.body()
+                ),
+            )
+            .as_bytes(),
+        )?;
+
+        manifest.push(format!(
+            "<item id=\"chapter_{n}\" href=\"chapter_{n}.xhtml\" media-type=\"application/xhtml+xml\"/>"
+        ));
+        spine.push(format!("<itemref idref=\"chapter_{}\"/>", n));
+        toc.push(format!(
+            "<li><a href=\"chapter_{n}.xhtml\">Chapter {n}</a></li>"
+        ));
+    }
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(
+        xhtml(
+            &title,
+            &format!(
+                "<nav epub:type=\"toc\">\n<h1>{}</h1>\n<p class=\"score\">Final score: ${}</p>\n<ol>\n{}\n</ol>\n</nav>\n",
+                title,
+                story.score,
+                toc.join("\n")
+            ),
+        )
+        .as_bytes(),
+    )?;
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n\
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+<dc:identifier id=\"book-id\">urn:uuid:{}</dc:identifier>\n\
+<dc:title>{}</dc:title>\n\
+<dc:language>en</dc:language>\n\
+<meta property=\"dcterms:modified\">{}</meta>\n\
+</metadata>\n\
+<manifest>\n{}\n</manifest>\n\
+<spine>\n{}\n</spine>\n\
+</package>\n",
+            id,
+            title,
+            modified,
+            manifest.join("\n"),
+            spine.join("\n")
+        )
+        .as_bytes(),
+    )?;
+
+    Ok(zip.finish()?.into_inner())
+}
+
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"en\">\n\
+<head>\n<meta charset=\"utf-8\"/>\n<title>{}</title>\n\
+<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n\
+<body>\n{}</body>\n</html>\n",
+        title, body
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn jpeg(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = vec![];
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg)?;
+    Ok(bytes)
+}
+
+/// Writes a story as `<name>.html` and `<name>.epub`; downloaded by the
+/// browser on the web
+#[derive(Event)]
+pub(crate) struct EventExportStorybook {
+    pub name: String,
+    pub story: StoryExport,
+}
+
+enum SceneImage {
+    /// background shipped with the game
+    Loading(Handle<Image>),
+    /// generated background
+    Downloading,
+    Ready(Vec<u8>),
+    Missing,
+}
+
+struct StorybookJob {
+    name: String,
+    story: StoryExport,
+    scenes: BTreeMap<String, SceneImage>,
+    started_at: f32,
+}
+
+/// Storybooks waiting for their scene images
+#[derive(Resource, Default)]
+struct StorybookJobs(Vec<StorybookJob>);
+
+pub struct StorybookPlugin;
+
+impl Plugin for StorybookPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EventExportStorybook>()
+            .init_resource::<StorybookJobs>()
+            .add_systems(
+                Update,
+                (
+                    handle_export_storybook,
+                    handle_download_image_response,
+                    write_finished_storybooks,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn handle_export_storybook(
+    mut er_export_storybook: EventReader<EventExportStorybook>,
+    mut ew_download_image: EventWriter<EventDownloadImageRequest>,
+    mut jobs: ResMut<StorybookJobs>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+) {
+    for event in er_export_storybook.read() {
+        let mut scenes = BTreeMap::new();
+        for chapter in chapters(&event.story) {
+            let Some(scene) = chapter.scene else {
+                continue;
+            };
+
+            let image = match scene.ends_with(".jpeg") {
+                true => {
+                    ew_download_image.write(EventDownloadImageRequest {
+                        filename: scene.clone(),
+                    });
+                    SceneImage::Downloading
+                }
+                false => SceneImage::Loading(asset_server.load(format!("plot/{}.png", scene))),
+            };
+            scenes.insert(scene, image);
+        }
+
+        jobs.0.push(StorybookJob {
+            name: event.name.clone(),
+            story: event.story.clone(),
+            scenes,
+            started_at: time.elapsed_secs(),
+        });
+    }
+}
+
+fn handle_download_image_response(
+    mut er_download_image: EventReader<EventDownloadImageResponse>,
+    mut jobs: ResMut<StorybookJobs>,
+) {
+    for event in er_download_image.read() {
+        for job in jobs.0.iter_mut() {
+            if let Some(scene) = job.scenes.get_mut(&event.filename)
+                && matches!(scene, SceneImage::Downloading)
+            {
+                *scene = match jpeg(&event.image) {
+                    Ok(bytes) => SceneImage::Ready(bytes),
+                    Err(err) => {
+                        warn!("storybook scene {}: {}", event.filename, err);
+                        SceneImage::Missing
+                    }
+                };
+            }
+        }
+    }
+}
+
+fn write_finished_storybooks(
+    mut jobs: ResMut<StorybookJobs>,
+    images: Res<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+) {
+    for job in jobs.0.iter_mut() {
+        for scene in job.scenes.values_mut() {
+            let SceneImage::Loading(handle) = scene else {
+                continue;
+            };
+
+            if let Some(image) = images.get(&*handle) {
+                *scene = match image.clone().try_into_dynamic() {
+                    Ok(image) => jpeg(&image).map_or(SceneImage::Missing, SceneImage::Ready),
+                    Err(_) => SceneImage::Missing,
+                };
+            } else if asset_server.load_state(handle.id()).is_failed() {
+                *scene = SceneImage::Missing;
+            }
+        }
+    }
+
+    let now_secs = time.elapsed_secs();
+    jobs.0.retain(|job| {
+        let waiting = job
+            .scenes
+            .values()
+            .any(|scene| matches!(scene, SceneImage::Loading(_) | SceneImage::Downloading));
+        if waiting && now_secs - job.started_at < IMAGE_TIMEOUT_SECONDS {
+            return true;
+        }
+
+        let images = job
+            .scenes
+            .iter()
+            .filter_map(|(scene, image)| match image {
+                SceneImage::Ready(bytes) => Some((scene.clone(), bytes.clone())),
+                _ => None,
+            })
+            .collect();
+
+        match write_storybook(&job.name, &job.story, &images) {
+            Ok(()) => info!("storybook {} written", job.name),
+            Err(err) => error!("storybook {}: {}", job.name, err),
+        }
+        false
+    });
+}
+
+fn write_storybook(
+    name: &str,
+    story: &StoryExport,
+    images: &BTreeMap<String, Vec<u8>>,
+) -> Result<()> {
+    let html = storybook_html(story, images);
+    let epub = storybook_epub(
+        story,
+        images,
+        &uuid::Uuid::new_v4().to_string(),
+        &format!("{}T00:00:00Z", format_date(now())),
+    )?;
+
+    save_file(&format!("{}.html", name), "text/html", html.as_bytes())?;
+    save_file(&format!("{}.epub", name), "application/epub+zip", &epub)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_file(filename: &str, _mime_type: &str, bytes: &[u8]) -> Result<()> {
+    std::fs::create_dir_all(STORYBOOK_DIR)?;
+    std::fs::write(std::path::Path::new(STORYBOOK_DIR).join(filename), bytes)?;
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_file(filename: &str, mime_type: &str, bytes: &[u8]) -> Result<()> {
+    crate::wasm::save_file(filename, mime_type, bytes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    fn story() -> StoryExport {
+        StoryExport::new(
+            vec![
+                StoryBeat::Say {
+                    who: None,
+                    what: "Once upon a time.".to_string(),
+                },
+                StoryBeat::Card {
+                    name: "Time Warp".to_string(),
+                },
+                StoryBeat::Scene {
+                    image: "3f9a.jpeg".to_string(),
+                },
+                StoryBeat::Show {
+                    image: "Igor_angry_center".to_string(),
+                    sprite: None,
+                },
+                StoryBeat::Say {
+                    who: Some("Igor".to_string()),
+                    what: "Fish & <chips>".to_string(),
+                },
+                StoryBeat::Card {
+                    name: "Betrayal".to_string(),
+                },
+            ],
+            vec!["Time Warp".to_string(), "Betrayal".to_string()],
+            42,
+        )
+    }
+
+    #[test]
+    fn test_chapters_group_cards_and_lines() {
+        assert_eq!(
+            chapters(&story()),
+            vec![
+                Chapter {
+                    scene: None,
+                    cards: vec![],
+                    lines: vec![(None, "Once upon a time.".to_string())],
+                },
+                Chapter {
+                    scene: Some("3f9a.jpeg".to_string()),
+                    cards: vec!["Time Warp".to_string(), "Betrayal".to_string()],
+                    lines: vec![(Some("Igor".to_string()), "Fish & <chips>".to_string())],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_html_and_epub() {
+        let story = story();
+        let images = [("3f9a.jpeg".to_string(), vec![1, 2, 3])]
+            .into_iter()
+            .collect();
+
+        let html = storybook_html(&story, &images);
+        assert!(html.contains("<title>Time Warp &amp; Betrayal</title>"));
+        assert!(html.contains("<span class=\"who\">Igor:</span> Fish &amp; &lt;chips&gt;"));
+        assert!(html.contains("data:image/jpeg;base64,AQID"));
+
+        let epub = storybook_epub(&story, &images, "id", "2026-10-18T00:00:00Z").unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(epub)).unwrap();
+
+        let mut mimetype = String::new();
+        let mut first = archive.by_index(0).unwrap();
+        assert_eq!(first.name(), "mimetype");
+        assert_eq!(first.compression(), CompressionMethod::Stored);
+        first.read_to_string(&mut mimetype).unwrap();
+        assert_eq!(mimetype, "application/epub+zip");
+        drop(first);
+
+        let names = archive.file_names().collect::<Vec<&str>>();
+        for name in [
+            "OEBPS/content.opf",
+            "OEBPS/nav.xhtml",
+            "OEBPS/chapter_2.xhtml",
+            "OEBPS/images/chapter_2.jpg",
+        ] {
+            assert!(names.contains(&name), "{} missing", name);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use bevy_novel::rpy_asset_loader::Rpy;
+use renpy_parser::parsers::AST;
+use serde::{de::Error, Deserialize, Deserializer};
+
+use crate::{
+    cards_scene::EventGameOver, menu_game::EventRenderUI, story_mechanics::MechanicArgs, GameState,
+};
+
+/// Acts listed on the campaign map
+pub(crate) const MAX_ACTS: usize = 5;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Act {
+    pub name: String,
+    /// scenario path, relative to `assets`
+    pub script: String,
+    /// score the player needs at the end of the act to go on
+    pub score_target: isize,
+}
+
+/// Acts of a run in the order they are played, loaded from `plot/campaign.json`
+#[derive(Deserialize, Asset, Resource, TypePath, Default, Clone, Debug)]
+pub struct Campaign {
+    #[serde(deserialize_with = "deserialize_acts")]
+    pub acts: Vec<Act>,
+}
+
+/// A manifest with more acts than the map has room for fails to load
+fn deserialize_acts<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Act>, D::Error> {
+    let acts = Vec::<Act>::deserialize(deserializer)?;
+    if acts.len() > MAX_ACTS {
+        return Err(D::Error::custom(format!(
+            "campaign has {} acts, at most {} are supported",
+            acts.len(),
+            MAX_ACTS
+        )));
+    }
+
+    Ok(acts)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ActOutcome {
+    Failed,
+    Passed,
+    /// last act passed
+    Won,
+}
+
+impl Campaign {
+    pub(crate) fn act_outcome(&self, act: usize, score: isize) -> ActOutcome {
+        let Some(current) = self.acts.get(act) else {
+            return ActOutcome::Won;
+        };
+
+        if score < current.score_target {
+            ActOutcome::Failed
+        } else if act + 1 >= self.acts.len() {
+            ActOutcome::Won
+        } else {
+            ActOutcome::Passed
+        }
+    }
+
+    /// One line per act for the map shown after `act` is cleared
+    pub(crate) fn map(&self, act: usize) -> Vec<String> {
+        self.acts
+            .iter()
+            .enumerate()
+            .map(|(i, current)| {
+                let status = match i {
+                    i if i <= act => "CLEARED",
+                    i if i == act + 1 => "NEXT",
+                    _ => "LOCKED",
+                };
+                format!(
+                    "ACT {}: {} - TARGET ${} - {}",
+                    i + 1,
+                    current.name.to_uppercase(),
+                    current.score_target,
+                    status
+                )
+            })
+            .collect()
+    }
+}
+
+/// Act being played and the scenarios of all acts
+#[derive(Resource, Default)]
+pub(crate) struct CampaignProgress {
+    pub act: usize,
+    pub scripts: Vec<Handle<Rpy>>,
+}
+
+impl CampaignProgress {
+    pub(crate) fn scenario(&self) -> Option<&Handle<Rpy>> {
+        self.scripts.get(self.act)
+    }
+
+    /// Moves on to the next act; deck and score stay as they are
+    pub(crate) fn next_act(&mut self, rpy_assets: &Assets<Rpy>) -> Option<Vec<AST>> {
+        let rpy = rpy_assets.get(self.scripts.get(self.act + 1)?.id())?;
+        self.act += 1;
+
+        Some(rpy.0.clone())
+    }
+}
+
+#[derive(Event)]
+pub(crate) struct EventEndAct {}
+
+/// `game_mechanic "act end"`
+pub(crate) fn mechanic_act_end(In(_): In<MechanicArgs>, mut ew_end_act: EventWriter<EventEndAct>) {
+    ew_end_act.write(EventEndAct {});
+}
+
+/// The act is scored against its target: falling short ends the run, passing
+/// the last act wins it, otherwise the campaign map is shown until the player
+/// starts the next act
+pub(crate) fn handle_end_act(
+    mut er_end_act: EventReader<EventEndAct>,
+    mut ew_game_over: EventWriter<EventGameOver>,
+    mut ew_render_ui: EventWriter<EventRenderUI>,
+    game_state: Res<GameState>,
+    campaign: Res<Campaign>,
+    campaign_progress: Res<CampaignProgress>,
+) {
+    for _ in er_end_act.read() {
+        match campaign.act_outcome(campaign_progress.act, game_state.score) {
+            ActOutcome::Passed => {
+                ew_render_ui.write(EventRenderUI::CampaignMap(
+                    campaign.map(campaign_progress.act),
+                ));
+            }
+            ActOutcome::Won => {
+                info!("campaign won");
+                ew_game_over.write(EventGameOver { won: true });
+            }
+            ActOutcome::Failed => {
+                info!("campaign lost at act {}", campaign_progress.act + 1);
+                ew_game_over.write(EventGameOver { won: false });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn campaign() -> Campaign {
+        serde_json::from_str(include_str!("../assets/plot/campaign.json")).unwrap()
+    }
+
+    #[test]
+    fn test_act_outcome_checks_score_target() {
+        let campaign = Campaign {
+            acts: vec![
+                Act {
+                    name: "One".to_string(),
+                    script: "plot/one.rpy".to_string(),
+                    score_target: 10,
+                },
+                Act {
+                    name: "Two".to_string(),
+                    script: "plot/two.rpy".to_string(),
+                    score_target: 50,
+                },
+            ],
+        };
+
+        assert_eq!(campaign.act_outcome(0, 9), ActOutcome::Failed);
+        assert_eq!(campaign.act_outcome(0, 10), ActOutcome::Passed);
+        assert_eq!(campaign.act_outcome(1, 20), ActOutcome::Failed);
+        assert_eq!(campaign.act_outcome(1, 50), ActOutcome::Won);
+
+        assert_eq!(
+            campaign.map(0),
+            vec![
+                "ACT 1: ONE - TARGET $10 - CLEARED",
+                "ACT 2: TWO - TARGET $50 - NEXT"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_campaign_rejects_too_many_acts() {
+        let act = r#"{"name": "Act", "script": "plot/act.rpy", "score_target": 10}"#;
+        let manifest = |n: usize| format!(r#"{{"acts": [{}]}}"#, vec![act; n].join(", "));
+
+        assert!(serde_json::from_str::<Campaign>(&manifest(MAX_ACTS)).is_ok());
+        assert!(serde_json::from_str::<Campaign>(&manifest(MAX_ACTS + 1))
+            .unwrap_err()
+            .to_string()
+            .contains("at most 5 are supported"));
+    }
+
+    #[test]
+    fn test_campaign_manifest_escalates() {
+        let campaign = campaign();
+
+        assert!(!campaign.acts.is_empty());
+        assert!(campaign.acts.len() <= MAX_ACTS);
+        assert!(campaign
+            .acts
+            .windows(2)
+            .all(|acts| acts[0].score_target < acts[1].score_target));
+    }
+}
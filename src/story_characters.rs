@@ -0,0 +1,354 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Line that opens the roster section at the end of an LLM reply
+pub(crate) const CHARACTERS_SECTION: &str = "CHARACTERS:";
+
+/// Asks the LLM to report how the cast changed in the chapter
+pub(crate) const CHARACTERS_PROMPT: &str = r#"
+After the story write a line "CHARACTERS:" and then one line per character in format:
+"Name | alive or dead | mood | trait, trait | Other name: relationship; Other name: relationship".
+"#;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct StoryCharacter {
+    pub name: String,
+    pub description: String,
+    pub traits: Vec<String>,
+    /// other character name, relationship
+    pub relationships: Vec<(String, String)>,
+    pub alive: bool,
+    pub mood: String,
+}
+
+impl StoryCharacter {
+    pub(crate) fn new(name: String, description: String) -> Self {
+        StoryCharacter {
+            name,
+            description,
+            traits: vec![],
+            relationships: vec![],
+            alive: true,
+            mood: "neutral".to_string(),
+        }
+    }
+
+    fn to_prompt(&self) -> String {
+        let mut line = format!(
+            "Character name: {}; Character description: {}; Status: {}; Mood: {};",
+            self.name,
+            self.description,
+            if self.alive { "alive" } else { "dead" },
+            self.mood
+        );
+
+        if !self.traits.is_empty() {
+            line.push_str(&format!(" Traits: {};", self.traits.join(", ")));
+        }
+
+        if !self.relationships.is_empty() {
+            let relationships = self
+                .relationships
+                .iter()
+                .map(|(other, relationship)| format!("{} to {}", relationship, other))
+                .collect::<Vec<String>>()
+                .join(", ");
+            line.push_str(&format!(" Relationships: {};", relationships));
+        }
+
+        line
+    }
+}
+
+/// Changes to one character reported by the LLM
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CharacterUpdate {
+    pub name: String,
+    pub alive: Option<bool>,
+    pub mood: Option<String>,
+    pub traits: Vec<String>,
+    pub relationships: Vec<(String, String)>,
+}
+
+impl CharacterUpdate {
+    /// Parses `Name | alive | mood | trait, trait | Other: relationship; ...`
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        let parts = line
+            .trim()
+            .trim_start_matches('-')
+            .split('|')
+            .map(|part| part.trim())
+            .collect::<Vec<&str>>();
+
+        let name = parts.first()?.trim_matches('"').to_string();
+        if name.is_empty() {
+            return None;
+        }
+
+        let alive = parts
+            .get(1)
+            .and_then(|status| match status.to_lowercase().as_str() {
+                "alive" => Some(true),
+                "dead" => Some(false),
+                _ => None,
+            });
+
+        let mood = parts
+            .get(2)
+            .filter(|mood| !mood.is_empty())
+            .map(|mood| mood.to_string());
+
+        let traits = parts
+            .get(3)
+            .map(|traits| {
+                traits
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let relationships = parts
+            .get(4)
+            .map(|relationships| {
+                relationships
+                    .split(';')
+                    .filter_map(|r| r.split_once(':'))
+                    .map(|(other, relationship)| {
+                        (other.trim().to_string(), relationship.trim().to_string())
+                    })
+                    .filter(|(other, relationship)| !other.is_empty() && !relationship.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(CharacterUpdate {
+            name,
+            alive,
+            mood,
+            traits,
+            relationships,
+        })
+    }
+}
+
+/// Cast of the story, kept across chapters and fed back into `{CHARACTERS}`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CharacterRoster(pub Vec<StoryCharacter>);
+
+impl CharacterRoster {
+    pub(crate) fn add(&mut self, name: String, description: String) {
+        if self.get(&name).is_none() {
+            self.0.push(StoryCharacter::new(name, description));
+        }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&StoryCharacter> {
+        self.0.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Applies an update; characters the roster doesn't know yet join the cast
+    pub(crate) fn apply(&mut self, update: CharacterUpdate) {
+        let index = match self
+            .0
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(&update.name))
+        {
+            Some(index) => index,
+            None => {
+                self.0
+                    .push(StoryCharacter::new(update.name.clone(), String::new()));
+                self.0.len() - 1
+            }
+        };
+
+        let character = &mut self.0[index];
+
+        if let Some(alive) = update.alive {
+            character.alive = alive;
+        }
+
+        if let Some(mood) = update.mood {
+            character.mood = mood;
+        }
+
+        if !update.traits.is_empty() {
+            character.traits = update.traits;
+        }
+
+        for (other, relationship) in update.relationships {
+            match character
+                .relationships
+                .iter_mut()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&other))
+            {
+                Some(existing) => existing.1 = relationship,
+                None => character.relationships.push((other, relationship)),
+            }
+        }
+    }
+
+    /// One line per character for `{CHARACTERS}`
+    pub(crate) fn prompt_lines(&self) -> Vec<String> {
+        self.0.iter().map(|c| c.to_prompt()).collect()
+    }
+}
+
+/// Expression sprites of the character cards, `character-cards/cards.json`
+/// maps emotion to a file in `character-cards/`; player-made characters use
+/// full asset paths
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CharacterSprites(pub BTreeMap<String, BTreeMap<String, String>>);
+
+impl CharacterSprites {
+    pub(crate) fn insert(&mut self, name: String, expressions: BTreeMap<String, String>) {
+        self.0.insert(name, expressions);
+    }
+
+    /// Sprite for a character showing an emotion, the neutral portrait when the
+    /// expression is missing; `None` for characters without a card
+    pub(crate) fn sprite(&self, name: &str, emotion: Option<&str>) -> Option<String> {
+        let (name, expressions) = self
+            .0
+            .iter()
+            .find(|(card_name, _)| card_name.eq_ignore_ascii_case(name))?;
+
+        let filename = emotion
+            .and_then(|emotion| expressions.get(emotion))
+            .or_else(|| expressions.get("neutral"))
+            .cloned()
+            .unwrap_or_else(|| format!("{}.png", name));
+
+        match filename.contains("://") {
+            true => Some(filename),
+            false => Some(format!("character-cards/{}", filename)),
+        }
+    }
+}
+
+/// Splits an LLM reply into the story text and the roster updates that follow
+/// the `CHARACTERS:` line
+pub(crate) fn split_character_section(response: &str) -> (String, Vec<CharacterUpdate>) {
+    let mut story = vec![];
+    let mut updates = vec![];
+    let mut in_section = false;
+
+    for line in response.lines() {
+        let trimmed = line.trim().trim_matches('*').trim();
+
+        if trimmed.eq_ignore_ascii_case(CHARACTERS_SECTION) {
+            in_section = true;
+            continue;
+        }
+
+        if in_section {
+            if let Some(update) = CharacterUpdate::parse(trimmed) {
+                updates.push(update);
+            }
+        } else {
+            story.push(line);
+        }
+    }
+
+    (story.join("\n"), updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cards_game::CharacterCard, story_format::EMOTIONS};
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_character_update() {
+        let update =
+            CharacterUpdate::parse("Igor | dead | furious | paranoid, loyal | Madonna: rival")
+                .unwrap();
+
+        assert_eq!(update.name, "Igor");
+        assert_eq!(update.alive, Some(false));
+        assert_eq!(update.mood, Some("furious".to_string()));
+        assert_eq!(update.traits, vec!["paranoid", "loyal"]);
+        assert_eq!(
+            update.relationships,
+            vec![("Madonna".to_string(), "rival".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_split_character_section() {
+        let response = "Igor -> Hello.\nThe night fell.\nCHARACTERS:\nIgor | alive | calm\n";
+        let (story, updates) = split_character_section(response);
+
+        assert_eq!(story, "Igor -> Hello.\nThe night fell.");
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].mood, Some("calm".to_string()));
+    }
+
+    #[test]
+    fn test_roster_keeps_state_between_updates() {
+        let mut roster = CharacterRoster::default();
+        roster.add("Komarito".to_string(), "A sentient mosquito.".to_string());
+
+        roster.apply(CharacterUpdate::parse("komarito | alive | happy | | Igor: friend").unwrap());
+        roster.apply(CharacterUpdate::parse("Komarito | dead").unwrap());
+
+        let komarito = roster.get("Komarito").unwrap();
+        assert!(!komarito.alive);
+        assert_eq!(komarito.mood, "happy");
+        assert_eq!(
+            komarito.relationships,
+            vec![("Igor".to_string(), "friend".to_string())]
+        );
+        assert_eq!(roster.0.len(), 1);
+    }
+
+    #[test]
+    fn test_sprite_falls_back_to_neutral() {
+        let mut sprites = CharacterSprites::default();
+        sprites.insert(
+            "Igor".to_string(),
+            BTreeMap::from([("angry".to_string(), "Igor_angry.png".to_string())]),
+        );
+
+        assert_eq!(
+            sprites.sprite("igor", Some("angry")),
+            Some("character-cards/Igor_angry.png".to_string())
+        );
+        assert_eq!(
+            sprites.sprite("Igor", Some("sad")),
+            Some("character-cards/Igor.png".to_string())
+        );
+        assert_eq!(
+            sprites.sprite("Igor", None),
+            Some("character-cards/Igor.png".to_string())
+        );
+        assert_eq!(sprites.sprite("Madonna", None), None);
+    }
+
+    #[test]
+    fn test_character_cards_ship_every_emotion() {
+        let cards: Vec<CharacterCard> =
+            serde_json::from_str(include_str!("../assets/character-cards/cards.json")).unwrap();
+
+        let mut sprites = CharacterSprites::default();
+        for card in cards.iter() {
+            sprites.insert(card.name.clone(), card.expressions.clone());
+        }
+
+        for card in cards.iter() {
+            for emotion in EMOTIONS {
+                let filename = card
+                    .expressions
+                    .get(emotion)
+                    .unwrap_or_else(|| panic!("{} has no {} sprite", card.name, emotion));
+                let sprite = sprites.sprite(&card.name, Some(emotion)).unwrap();
+
+                assert_eq!(sprite, format!("character-cards/{}", filename));
+                assert!(Path::new("assets").join(&sprite).exists(), "{}", sprite);
+            }
+        }
+    }
+}
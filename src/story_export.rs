@@ -0,0 +1,297 @@
+use anyhow::{bail, Result};
+use bevy::prelude::*;
+use bevy_novel::NovelData;
+use renpy_parser::parsers::AST;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    story_characters::CharacterSprites, story_preload::EventPreloadScenario, story_stage::Actor,
+};
+
+/// Bumped whenever a field changes meaning; older exports are rejected rather
+/// than replayed wrong
+pub(crate) const STORY_EXPORT_VERSION: u32 = 1;
+
+/// Shown while a chapter is being generated, never part of the story
+const LOADING_LINE: &str = "...";
+
+/// One node of a finished story, in the order the player saw it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum StoryBeat {
+    /// background: an asset under `plot` or a generated image hash
+    Scene {
+        image: String,
+    },
+    Say {
+        who: Option<String>,
+        what: String,
+    },
+    /// character portrait, `sprite` is the asset path it was drawn from
+    Show {
+        image: String,
+        sprite: Option<String>,
+    },
+    Hide {
+        image: String,
+    },
+    /// card committed to the story, before the chapter it shaped
+    Card {
+        name: String,
+    },
+}
+
+impl StoryBeat {
+    /// Beat for a scenario node the player reached; flow nodes such as
+    /// `game_mechanic` or `llm_generate` leave no beat
+    pub(crate) fn from_node(node: &AST, character_sprites: &CharacterSprites) -> Option<Self> {
+        match node {
+            AST::Say(_, _, what) if what == LOADING_LINE => None,
+            AST::Say(_, who, what) => Some(StoryBeat::Say {
+                who: who.clone().filter(|who| !who.is_empty()),
+                what: what.clone(),
+            }),
+            AST::Scene(_, Some(image), _) => Some(StoryBeat::Scene {
+                image: image.clone(),
+            }),
+            AST::Show(_, image) => Some(StoryBeat::Show {
+                image: image.clone(),
+                sprite: Actor::from_image_name(image)
+                    .and_then(|actor| character_sprites.sprite(&actor.name, Some(&actor.emotion))),
+            }),
+            AST::Hide(_, image) => Some(StoryBeat::Hide {
+                image: image.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Option the player picked at the end of a chapter, kept as narration so
+    /// every reader of saved stories shows it
+    pub(crate) fn choice(choice: &str) -> Self {
+        StoryBeat::Say {
+            who: None,
+            what: format!("You chose: {}", choice),
+        }
+    }
+
+    fn to_node(&self, index: usize) -> Option<AST> {
+        match self {
+            StoryBeat::Scene { image } => {
+                Some(AST::Scene(index, Some(image.clone()), "master".to_string()))
+            }
+            StoryBeat::Say { who, what } => Some(AST::Say(index, who.clone(), what.clone())),
+            StoryBeat::Show { image, .. } => Some(AST::Show(index, image.clone())),
+            StoryBeat::Hide { image } => Some(AST::Hide(index, image.clone())),
+            StoryBeat::Card { .. } => None,
+        }
+    }
+}
+
+/// A finished run as it is persisted and replayed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct StoryExport {
+    pub version: u32,
+    pub beats: Vec<StoryBeat>,
+    /// names of cards committed to the story, in play order
+    pub played_cards: Vec<String>,
+    pub score: isize,
+}
+
+impl StoryExport {
+    pub(crate) fn new(beats: Vec<StoryBeat>, played_cards: Vec<String>, score: isize) -> Self {
+        StoryExport {
+            version: STORY_EXPORT_VERSION,
+            beats,
+            played_cards,
+            score,
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub(crate) fn from_json(json: &str) -> Result<Self> {
+        let story: StoryExport = serde_json::from_str(json)?;
+        if story.version != STORY_EXPORT_VERSION {
+            bail!(
+                "story export version {} is not supported, expected {}",
+                story.version,
+                STORY_EXPORT_VERSION
+            );
+        }
+
+        Ok(story)
+    }
+
+    /// Scenario the player mode replays: every beat in a single `start` label
+    pub(crate) fn to_scenario(&self) -> Vec<AST> {
+        let mut nodes = vec![];
+        for beat in self.beats.iter() {
+            if let Some(node) = beat.to_node(nodes.len() + 1) {
+                nodes.push(node);
+            }
+        }
+
+        vec![AST::Label(0, "start".to_string(), nodes, None)]
+    }
+}
+
+/// Plays a saved story in `AppState::NovelPlayer`
+#[derive(Event)]
+pub(crate) struct EventReplayStory {
+    pub story: StoryExport,
+}
+
+pub(crate) fn handle_replay_story(
+    mut er_replay_story: EventReader<EventReplayStory>,
+    mut ew_preload_scenario: EventWriter<EventPreloadScenario>,
+    mut novel_data: ResMut<NovelData>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in er_replay_story.read() {
+        // portraits are drawn with the sprites the story was saved with
+        for beat in event.story.beats.iter() {
+            if let StoryBeat::Show {
+                image,
+                sprite: Some(sprite),
+            } = beat
+                && let Some(actor) = Actor::from_image_name(image)
+            {
+                let sprite = Sprite {
+                    image: asset_server.load(sprite),
+                    anchor: actor.position.anchor(),
+                    ..default()
+                };
+                novel_data.write_image_cache(image.clone(), sprite);
+            }
+        }
+
+        // generated backgrounds are downloaded before the story starts
+        ew_preload_scenario.write(EventPreloadScenario {
+            ast: event.story.to_scenario(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::story_preload::generated_scene_images;
+
+    fn story() -> StoryExport {
+        StoryExport::new(
+            vec![
+                StoryBeat::Scene {
+                    image: "intro_1".to_string(),
+                },
+                StoryBeat::Say {
+                    who: None,
+                    what: "This world has lost all meaning.".to_string(),
+                },
+                StoryBeat::Scene {
+                    image: "3f9a.jpeg".to_string(),
+                },
+                StoryBeat::Show {
+                    image: "Igor_angry_center".to_string(),
+                    sprite: Some("character-cards/igor-angry.png".to_string()),
+                },
+                StoryBeat::Say {
+                    who: Some("Igor".to_string()),
+                    what: "He said \"no\" twice, then game_mechanic \"game over\"...".to_string(),
+                },
+                StoryBeat::Hide {
+                    image: "Igor_angry_center".to_string(),
+                },
+            ],
+            vec!["Mysterious Stranger".to_string(), "Igor".to_string()],
+            120,
+        )
+    }
+
+    fn sprites() -> CharacterSprites {
+        let mut sprites = CharacterSprites::default();
+        sprites.insert(
+            "Igor".to_string(),
+            [("angry".to_string(), "igor-angry.png".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        sprites
+    }
+
+    fn replayed_beats(scenario: &[AST]) -> Vec<StoryBeat> {
+        scenario
+            .iter()
+            .flat_map(|node| match node {
+                AST::Label(_, _, children, _) => children.clone(),
+                node => vec![node.clone()],
+            })
+            .filter_map(|node| StoryBeat::from_node(&node, &sprites()))
+            .collect()
+    }
+
+    #[test]
+    fn test_export_import_replay_round_trip() {
+        let story = story();
+
+        let imported = StoryExport::from_json(&story.to_json().unwrap()).unwrap();
+        assert_eq!(imported, story);
+
+        assert_eq!(replayed_beats(&imported.to_scenario()), story.beats);
+        assert_eq!(
+            generated_scene_images(&imported.to_scenario()),
+            vec!["3f9a.jpeg"]
+        );
+    }
+
+    #[test]
+    fn test_choice_is_replayed() {
+        let story = StoryExport::new(
+            vec![
+                StoryBeat::Say {
+                    who: None,
+                    what: "What will you do?".to_string(),
+                },
+                StoryBeat::choice("Follow the river"),
+            ],
+            vec![],
+            0,
+        );
+
+        assert_eq!(
+            replayed_beats(&story.to_scenario())[1],
+            StoryBeat::Say {
+                who: None,
+                what: "You chose: Follow the river".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_loading_lines_and_other_versions_are_dropped() {
+        let sprites = sprites();
+        assert!(StoryBeat::from_node(
+            &AST::Say(3, Some("".to_string()), LOADING_LINE.to_string()),
+            &sprites
+        )
+        .is_none());
+        assert_eq!(
+            StoryBeat::from_node(
+                &AST::Say(4, Some("".to_string()), "Narration.".to_string()),
+                &sprites
+            ),
+            Some(StoryBeat::Say {
+                who: None,
+                what: "Narration.".to_string()
+            })
+        );
+
+        let json = story()
+            .to_json()
+            .unwrap()
+            .replace("\"version\":1", "\"version\":99");
+        assert!(StoryExport::from_json(&json).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::story_characters::{split_character_section, CharacterUpdate, CHARACTERS_SECTION};
+
+/// Emotions a beat may be tagged with
+pub(crate) const EMOTIONS: [&str; 5] = ["neutral", "angry", "sad", "happy", "afraid"];
+
+/// Line that opens the player choices section of an LLM reply
+pub(crate) const CHOICES_SECTION: &str = "CHOICES:";
+
+/// Most choices offered to the player at the end of a chapter
+pub(crate) const MAX_CHOICES: usize = 3;
+
+/// Tells the LLM where choices go in the line based format
+pub(crate) const CHOICES_PROMPT: &str = r#"
+Only if asked for choices, after everything else write a line "CHOICES:" and then one choice per line.
+"#;
+
+/// Replaces `{PROMPT}` when the LLM is asked for structured output
+pub(crate) const STRUCTURED_PROMPT: &str = r#"
+You are narrator in a visual novel.
+Create a script for visual novel based on this setting.
+Do not include any instructions or explanations.
+Do not repeat yourself! Do no repeat character lines!
+If character description is not provided make dialogue third person.
+Respond with at least 20 beats. Each beat text no longer 10 words.
+Respond only with JSON in this format:
+{"beats": [{"speaker": "character name or null for narration", "text": "line", "emotion": "neutral, angry, sad, happy or afraid", "scene_change": "description of a new scene or null"}],
+"characters": [{"name": "character name", "alive": true, "mood": "mood", "traits": ["trait"], "relationships": {"other character name": "relationship"}}],
+"choices": ["what main character may do next"]}
+Only if asked for choices fill "choices", otherwise leave it empty.
+"#;
+
+/// One line of a generated chapter
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub(crate) struct ReplyBeat {
+    #[serde(default)]
+    pub speaker: Option<String>,
+    pub text: String,
+    #[serde(default)]
+    pub emotion: Option<String>,
+    #[serde(default)]
+    pub scene_change: Option<String>,
+}
+
+impl ReplyBeat {
+    /// Line as it is kept in the story so far
+    pub(crate) fn to_story_line(&self) -> String {
+        match &self.speaker {
+            Some(speaker) => format!("{} -> {}", speaker, self.text),
+            None => self.text.clone(),
+        }
+    }
+}
+
+/// Generated chapter together with the cast changes reported in it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct StoryReply {
+    pub beats: Vec<ReplyBeat>,
+    pub characters: Vec<CharacterUpdate>,
+    /// what the player may pick at the end of the chapter, empty or 2 to 3
+    pub choices: Vec<String>,
+}
+
+impl StoryReply {
+    /// Description of the chapter background, if the LLM gave one
+    pub(crate) fn scene_description(&self) -> Option<String> {
+        self.beats.iter().find_map(|beat| beat.scene_change.clone())
+    }
+}
+
+#[derive(Deserialize)]
+struct StructuredReply {
+    beats: Vec<ReplyBeat>,
+    #[serde(default)]
+    characters: Vec<StructuredCharacter>,
+    #[serde(default)]
+    choices: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct StructuredCharacter {
+    name: String,
+    #[serde(default)]
+    alive: Option<bool>,
+    #[serde(default)]
+    mood: Option<String>,
+    #[serde(default)]
+    traits: Vec<String>,
+    #[serde(default)]
+    relationships: BTreeMap<String, String>,
+}
+
+impl From<StructuredCharacter> for CharacterUpdate {
+    fn from(character: StructuredCharacter) -> Self {
+        CharacterUpdate {
+            name: character.name,
+            alive: character.alive,
+            mood: character.mood,
+            traits: character.traits,
+            relationships: character.relationships.into_iter().collect(),
+        }
+    }
+}
+
+/// Reads the outermost JSON object of an LLM reply; models like to wrap it in
+/// prose or code fences
+pub(crate) fn parse_json_reply<T: DeserializeOwned>(response: &str) -> Result<T> {
+    let start = response
+        .find('{')
+        .ok_or_else(|| anyhow!("no JSON object in reply"))?;
+    let end = response
+        .rfind('}')
+        .ok_or_else(|| anyhow!("no JSON object in reply"))?;
+    if end < start {
+        bail!("no JSON object in reply");
+    }
+
+    Ok(serde_json::from_str(&response[start..=end])?)
+}
+
+/// Parses and validates a JSON reply. An emotion outside `EMOTIONS` is dropped
+/// rather than failing the whole chapter
+pub(crate) fn parse_structured_reply(response: &str) -> Result<StoryReply> {
+    let reply: StructuredReply = parse_json_reply(response)?;
+    let mut beats = reply.beats;
+
+    if beats.is_empty() {
+        bail!("reply has no beats");
+    }
+
+    for (i, beat) in beats.iter_mut().enumerate() {
+        beat.text = beat.text.trim().to_string();
+        if beat.text.is_empty() {
+            bail!("beat {} has no text", i);
+        }
+
+        beat.speaker = beat
+            .speaker
+            .take()
+            .map(|speaker| speaker.trim().to_string())
+            .filter(|speaker| !speaker.is_empty() && speaker != "null");
+
+        beat.emotion = beat
+            .emotion
+            .take()
+            .map(|emotion| emotion.trim().to_lowercase())
+            .filter(|emotion| EMOTIONS.contains(&emotion.as_str()));
+
+        beat.scene_change = beat
+            .scene_change
+            .take()
+            .map(|scene| scene.trim().to_string())
+            .filter(|scene| !scene.is_empty() && scene != "null");
+    }
+
+    Ok(StoryReply {
+        beats,
+        characters: reply.characters.into_iter().map(|c| c.into()).collect(),
+        choices: clean_choices(reply.choices),
+    })
+}
+
+/// Reads the plain text format: one sentence per line, `Character -> line`
+/// for dialogue, and optional `CHARACTERS:` and `CHOICES:` sections at the end
+pub(crate) fn parse_heuristic_reply(response: &str) -> StoryReply {
+    let (response, choices) = split_choices_section(response);
+    let (story, characters) = split_character_section(&response);
+
+    let beats = story
+        .split("\n")
+        .map(strip_list_marker)
+        .filter(|s| !s.is_empty())
+        .map(|sentence| match sentence.split_once("->") {
+            Some((who, what)) if is_speaker(who) => {
+                let (speaker, emotion) = split_emotion_tag(who);
+                ReplyBeat {
+                    speaker: Some(speaker),
+                    text: what.replace("\"", "").trim().to_string(),
+                    emotion,
+                    ..Default::default()
+                }
+            }
+            _ => ReplyBeat {
+                text: sentence.to_string(),
+                ..Default::default()
+            },
+        })
+        .filter(|beat| !beat.text.is_empty())
+        .collect();
+
+    StoryReply {
+        beats,
+        characters,
+        choices,
+    }
+}
+
+/// Takes the lines after `CHOICES:` up to the next section out of the reply
+fn split_choices_section(response: &str) -> (String, Vec<String>) {
+    let mut rest = vec![];
+    let mut choices = vec![];
+    let mut in_section = false;
+
+    for line in response.lines() {
+        let trimmed = line.trim().trim_matches('*').trim();
+
+        if trimmed.eq_ignore_ascii_case(CHOICES_SECTION) {
+            in_section = true;
+            continue;
+        }
+
+        if trimmed.eq_ignore_ascii_case(CHARACTERS_SECTION) {
+            in_section = false;
+        }
+
+        if in_section {
+            choices.push(strip_list_marker(trimmed).to_string());
+        } else {
+            rest.push(line);
+        }
+    }
+
+    (rest.join("\n"), clean_choices(choices))
+}
+
+/// A single option is no choice at all, and more than three don't fit the menu
+fn clean_choices(choices: Vec<String>) -> Vec<String> {
+    let choices: Vec<String> = choices
+        .into_iter()
+        .map(|choice| choice.trim().trim_matches('"').trim().to_string())
+        .filter(|choice| !choice.is_empty())
+        .take(MAX_CHOICES)
+        .collect();
+
+    match choices.len() {
+        0 | 1 => vec![],
+        _ => choices,
+    }
+}
+
+/// Drops `1.`, `2)`, `-` and `*` prefixes models add to lines
+fn strip_list_marker(line: &str) -> &str {
+    let line = line.trim();
+    let without_number = line.trim_start_matches(|c: char| c.is_ascii_digit());
+
+    let line = if without_number.len() < line.len()
+        && (without_number.starts_with(". ") || without_number.starts_with(") "))
+    {
+        &without_number[2..]
+    } else {
+        line
+    };
+
+    line.trim_start_matches(['-', '*', '•']).trim()
+}
+
+/// `Igor [angry]` into the name and a known emotion
+fn split_emotion_tag(who: &str) -> (String, Option<String>) {
+    let who = who.trim().trim_matches('*').trim();
+
+    match who.split_once('[') {
+        Some((name, tag)) => {
+            let emotion = tag.trim_end_matches(']').trim().to_lowercase();
+            let emotion = EMOTIONS.contains(&emotion.as_str()).then_some(emotion);
+            (name.trim().to_string(), emotion)
+        }
+        None => (who.to_string(), None),
+    }
+}
+
+/// Text left of an arrow is a speaker only if it looks like a name:
+/// at most three capitalized words and no punctuation, optionally followed by
+/// an emotion tag
+fn is_speaker(who: &str) -> bool {
+    let (who, _) = split_emotion_tag(who);
+    !who.is_empty()
+        && who.split_whitespace().count() <= 3
+        && who
+            .split_whitespace()
+            .all(|word| word.starts_with(|c: char| c.is_uppercase()))
+        && !who.contains(['.', ',', '!', '?', '"'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_structured_reply() {
+        let response = r#"Here is the chapter:
+```json
+{"beats": [
+    {"speaker": null, "text": "The night fell.", "scene_change": "A dark street"},
+    {"speaker": "Igor", "text": "They changed again.", "emotion": "Afraid"}
+],
+"characters": [{"name": "Igor", "mood": "afraid", "relationships": {"Madonna": "rival"}}]}
+```"#;
+
+        let reply = parse_structured_reply(response).unwrap();
+
+        assert_eq!(reply.beats.len(), 2);
+        assert_eq!(reply.beats[0].speaker, None);
+        assert_eq!(reply.beats[1].speaker, Some("Igor".to_string()));
+        assert_eq!(reply.beats[1].emotion, Some("afraid".to_string()));
+        assert_eq!(reply.scene_description(), Some("A dark street".to_string()));
+        assert_eq!(
+            reply.characters[0].relationships,
+            vec![("Madonna".to_string(), "rival".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_structured_reply_is_validated() {
+        assert!(parse_structured_reply("Igor -> hello").is_err());
+        assert!(parse_structured_reply(r#"{"beats": []}"#).is_err());
+        assert!(parse_structured_reply(r#"{"beats": [{"text": " "}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_unknown_emotion_is_dropped() {
+        let reply = parse_structured_reply(
+            r#"{"beats": [{"speaker": "Igor", "text": "Hi.", "emotion": "bored"}, {"text": "Dusk.", "emotion": "sad"}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(reply.beats.len(), 2);
+        assert_eq!(reply.beats[0].text, "Hi.");
+        assert_eq!(reply.beats[0].emotion, None);
+        assert_eq!(reply.beats[1].emotion, Some("sad".to_string()));
+    }
+
+    #[test]
+    fn test_parse_heuristic_reply() {
+        let response = "1. The rain -> the river, and the river -> the sea.\n\
+                        2. Igor -> \"They changed again.\"\n\
+                        \n\
+                        - Komarito [Happy] -> I am just a mosquito.";
+
+        let reply = parse_heuristic_reply(response);
+
+        assert_eq!(reply.beats.len(), 3);
+        assert_eq!(reply.beats[0].speaker, None);
+        assert_eq!(
+            reply.beats[0].text,
+            "The rain -> the river, and the river -> the sea."
+        );
+        assert_eq!(reply.beats[1].speaker, Some("Igor".to_string()));
+        assert_eq!(reply.beats[1].text, "They changed again.");
+        assert_eq!(reply.beats[2].speaker, Some("Komarito".to_string()));
+        assert_eq!(reply.beats[2].emotion, Some("happy".to_string()));
+        assert_eq!(reply.beats[1].emotion, None);
+    }
+
+    #[test]
+    fn test_parse_choices() {
+        let response = "Igor -> Where now?\n\
+                        CHOICES:\n\
+                        1. Follow the river\n\
+                        2. \"Burn the map\"\n\
+                        CHARACTERS:\n\
+                        Igor | alive | lost";
+
+        let reply = parse_heuristic_reply(response);
+
+        assert_eq!(reply.beats.len(), 1);
+        assert_eq!(reply.choices, vec!["Follow the river", "Burn the map"]);
+        assert_eq!(reply.characters.len(), 1);
+
+        let reply = parse_structured_reply(
+            r#"{"beats": [{"text": "Dawn."}], "choices": ["Wait", "", "Run", "Hide", "Pray"]}"#,
+        )
+        .unwrap();
+        assert_eq!(reply.choices, vec!["Wait", "Run", "Hide"]);
+
+        let reply =
+            parse_structured_reply(r#"{"beats": [{"text": "Dawn."}], "choices": ["Wait"]}"#)
+                .unwrap();
+        assert!(reply.choices.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use bevy::prelude::*;
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::story_export::{StoryBeat, StoryExport};
+
+/// Stories kept, the oldest ones are dropped first
+pub(crate) const MAX_LIBRARY_STORIES: usize = 20;
+
+#[cfg(not(target_arch = "wasm32"))]
+const LIBRARY_PATH: &str = "story-library.json";
+
+const THUMBNAIL_WIDTH: u32 = 160;
+const THUMBNAIL_HEIGHT: u32 = 90;
+
+/// A finished run saved on this device
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LibraryEntry {
+    pub title: String,
+    /// seconds since the unix epoch
+    pub saved_at: u64,
+    pub score: isize,
+    /// small PNG of the last background
+    #[serde(default, with = "base64_bytes")]
+    pub thumbnail: Vec<u8>,
+    pub story: StoryExport,
+}
+
+impl LibraryEntry {
+    pub(crate) fn new(story: StoryExport, thumbnail: Vec<u8>, saved_at: u64) -> Self {
+        LibraryEntry {
+            title: story_title(&story),
+            saved_at,
+            score: story.score,
+            thumbnail,
+            story,
+        }
+    }
+
+    /// One line for the library screen
+    pub(crate) fn label(&self) -> String {
+        format!(
+            "{} - {} - ${}",
+            self.title,
+            format_date(self.saved_at),
+            self.score
+        )
+    }
+}
+
+/// Bytes kept as a base64 string, a JSON array of numbers takes about 3.5
+/// characters a byte and the library has to fit in `localStorage`
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Finished runs, newest first; disk on native, `localStorage` on the web
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+pub(crate) struct StoryLibrary {
+    pub entries: Vec<LibraryEntry>,
+}
+
+impl StoryLibrary {
+    pub(crate) fn load() -> Self {
+        let Some(json) = read_library() else {
+            return StoryLibrary::default();
+        };
+
+        serde_json::from_str(&json).unwrap_or_else(|err| {
+            warn!("story library unreadable, starting a new one: {}", err);
+            StoryLibrary::default()
+        })
+    }
+
+    pub(crate) fn save(&self) -> Result<()> {
+        write_library(&serde_json::to_string(self)?)
+    }
+
+    pub(crate) fn add(&mut self, entry: LibraryEntry) {
+        self.entries.insert(0, entry);
+        self.entries.truncate(MAX_LIBRARY_STORIES);
+    }
+}
+
+/// Named after the cards that shaped the story
+pub(crate) fn story_title(story: &StoryExport) -> String {
+    match story.played_cards.as_slice() {
+        [] => "Untitled Story".to_string(),
+        [card] => card.clone(),
+        [first, second, ..] => format!("{} & {}", first, second),
+    }
+}
+
+/// Thumbnail shown in the library, made from a chapter background
+pub(crate) fn thumbnail_png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let thumbnail = image.resize_to_fill(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, FilterType::Triangle);
+
+    let mut bytes: Vec<u8> = vec![];
+    thumbnail.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Scene the story ended on, used when no background was generated
+pub(crate) fn last_scene(story: &StoryExport) -> Option<&str> {
+    story.beats.iter().rev().find_map(|beat| match beat {
+        StoryBeat::Scene { image } => Some(image.as_str()),
+        _ => None,
+    })
+}
+
+/// `YYYY-MM-DD` in UTC
+pub(crate) fn format_date(seconds: u64) -> String {
+    // days to civil date, after Howard Hinnant's `civil_from_days`
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> u64 {
+    crate::wasm::now_seconds() as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_library() -> Option<String> {
+    std::fs::read_to_string(LIBRARY_PATH).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_library(json: &str) -> Result<()> {
+    std::fs::write(LIBRARY_PATH, json)?;
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_library() -> Option<String> {
+    Some(crate::wasm::library_load()).filter(|json| !json.is_empty())
+}
+
+/// `localStorage` throws when the library doesn't fit in its quota
+#[cfg(target_arch = "wasm32")]
+fn write_library(json: &str) -> Result<()> {
+    crate::wasm::library_save(json).map_err(|err| anyhow::anyhow!("localStorage: {:?}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(cards: &[&str], saved_at: u64) -> LibraryEntry {
+        let story = StoryExport::new(
+            vec![StoryBeat::Scene {
+                image: "intro_1".to_string(),
+            }],
+            cards.iter().map(|card| card.to_string()).collect(),
+            75,
+        );
+        LibraryEntry::new(story, vec![], saved_at)
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_792_281_600), "2026-10-18");
+    }
+
+    #[test]
+    fn test_library_keeps_newest_stories() {
+        let mut library = StoryLibrary::default();
+        for i in 0..MAX_LIBRARY_STORIES + 2 {
+            library.add(entry(&["Time Warp"], i as u64));
+        }
+
+        assert_eq!(library.entries.len(), MAX_LIBRARY_STORIES);
+        assert_eq!(library.entries[0].saved_at, MAX_LIBRARY_STORIES as u64 + 1);
+
+        let entry = entry(&["Time Warp", "Betrayal", "Igor"], 0);
+        assert_eq!(entry.label(), "Time Warp & Betrayal - 1970-01-01 - $75");
+        assert_eq!(last_scene(&entry.story), Some("intro_1"));
+    }
+
+    #[test]
+    fn test_thumbnail_is_saved_as_base64() {
+        let mut entry = entry(&["Time Warp"], 0);
+        entry.thumbnail = vec![1, 2, 3];
+
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""thumbnail":"AQID""#));
+        assert_eq!(serde_json::from_str::<LibraryEntry>(&json).unwrap(), entry);
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use bevy::{ecs::system::SystemId, prelude::*};
+
+/// What a `game_mechanic` statement says after the mechanic name, e.g. the
+/// card count in `game_mechanic "card play narrative characters 2"`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MechanicArgs {
+    pub count: Option<usize>,
+}
+
+/// Turns the text after the mechanic name into arguments, an error is shown as
+/// a script error
+pub type MechanicParser = fn(&str) -> Result<MechanicArgs>;
+
+/// Default parser: no arguments or a card count
+pub fn parse_count(text: &str) -> Result<MechanicArgs> {
+    let count = match text {
+        "" => None,
+        text => Some(
+            text.parse::<usize>()
+                .map_err(|_| anyhow!("unexpected argument `{}`", text))?,
+        ),
+    };
+
+    Ok(MechanicArgs { count })
+}
+
+/// Mechanics the game ships with; `main.rs` gives each its handler and
+/// `rpy-lint` checks scenarios against the same names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinMechanic {
+    PlayPoker,
+    PlayNarrativeSetting,
+    PlayNarrativeCharacters,
+    PlayNarrativeConflict,
+    PlayNarrativePsychosis,
+    PlayNarrativePlotTwist,
+    CardShop,
+    GameOver,
+    ActEnd,
+}
+
+impl BuiltinMechanic {
+    pub const ALL: [BuiltinMechanic; 9] = [
+        BuiltinMechanic::PlayPoker,
+        BuiltinMechanic::PlayNarrativeSetting,
+        BuiltinMechanic::PlayNarrativeCharacters,
+        BuiltinMechanic::PlayNarrativeConflict,
+        BuiltinMechanic::PlayNarrativePsychosis,
+        BuiltinMechanic::PlayNarrativePlotTwist,
+        BuiltinMechanic::CardShop,
+        BuiltinMechanic::GameOver,
+        BuiltinMechanic::ActEnd,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinMechanic::PlayPoker => "card play poker",
+            BuiltinMechanic::PlayNarrativeSetting => "card play narrative setting",
+            BuiltinMechanic::PlayNarrativeCharacters => "card play narrative characters",
+            BuiltinMechanic::PlayNarrativeConflict => "card play narrative conflict",
+            BuiltinMechanic::PlayNarrativePsychosis => "card play narrative psychosis",
+            BuiltinMechanic::PlayNarrativePlotTwist => "card play narrative plot twist",
+            BuiltinMechanic::CardShop => "card shop",
+            BuiltinMechanic::GameOver => "game over",
+            BuiltinMechanic::ActEnd => "act end",
+        }
+    }
+
+    /// Built-in mechanics take at most a card count
+    pub fn parser(self) -> MechanicParser {
+        parse_count
+    }
+}
+
+/// Mechanic names and how to read their arguments, no handlers; what the game
+/// and `rpy-lint` both check `game_mechanic` statements against
+#[derive(Clone, Default)]
+pub struct MechanicTable(BTreeMap<String, MechanicParser>);
+
+impl MechanicTable {
+    /// Built-in mechanics and `extra` ones taking a card count
+    pub fn with_builtins(extra: &[String]) -> Self {
+        let mut table = MechanicTable::default();
+        for mechanic in BuiltinMechanic::ALL {
+            table.insert(mechanic.name(), mechanic.parser());
+        }
+        for name in extra {
+            table.insert(name, parse_count);
+        }
+        table
+    }
+
+    pub fn insert(&mut self, name: &str, parse: MechanicParser) {
+        self.0.insert(normalize(name), parse);
+    }
+
+    /// Mechanic names are matched on whole words, the longest registered name
+    /// wins so `card play narrative setting` isn't taken for `card play`
+    fn find(&self, statement: &str) -> Option<(&str, MechanicParser, String)> {
+        let words = normalize(statement);
+
+        self.0
+            .iter()
+            .filter(|(name, _)| {
+                words == **name
+                    || words
+                        .strip_prefix(name.as_str())
+                        .is_some_and(|rest| rest.starts_with(' '))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(name, parse)| {
+                (
+                    name.as_str(),
+                    *parse,
+                    words[name.len()..].trim().to_string(),
+                )
+            })
+    }
+
+    /// Parses a statement into the mechanic name and its arguments
+    pub fn parse(&self, statement: &str) -> Result<(&str, MechanicArgs)> {
+        let Some((name, parse, text)) = self.find(statement) else {
+            bail!(
+                "unknown mechanic `{}`, expected one of: {}",
+                normalize(statement),
+                self.names().collect::<Vec<&str>>().join(", ")
+            );
+        };
+
+        Ok((name, parse(&text)?))
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|name| name.as_str())
+    }
+}
+
+/// `game_mechanic` statements the scenario can use, each with a one-shot system
+/// on the app's world that starts it
+#[derive(Resource, Default)]
+pub struct GameMechanicRegistry {
+    table: MechanicTable,
+    handlers: BTreeMap<String, SystemId<In<MechanicArgs>>>,
+}
+
+impl GameMechanicRegistry {
+    /// Parses a statement into the handler to run and its arguments
+    pub fn resolve(&self, statement: &str) -> Result<(SystemId<In<MechanicArgs>>, MechanicArgs)> {
+        let (name, args) = self.table.parse(statement)?;
+        Ok((self.handlers[name], args))
+    }
+}
+
+/// Quotes inside the statement are dropped, `"card play" 2` and `card play 2`
+/// are the same
+fn normalize(statement: &str) -> String {
+    statement
+        .replace('"', " ")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+pub trait GameMechanicAppExt {
+    /// Makes `game_mechanic "name ..."` run `handler` with the arguments
+    /// `parse` reads
+    fn register_game_mechanic<M>(
+        &mut self,
+        name: &str,
+        parse: MechanicParser,
+        handler: impl IntoSystem<In<MechanicArgs>, (), M> + 'static,
+    ) -> &mut Self;
+}
+
+impl GameMechanicAppExt for App {
+    fn register_game_mechanic<M>(
+        &mut self,
+        name: &str,
+        parse: MechanicParser,
+        handler: impl IntoSystem<In<MechanicArgs>, (), M> + 'static,
+    ) -> &mut Self {
+        let handler = self.world_mut().register_system(handler);
+        let mut registry = self
+            .world_mut()
+            .get_resource_or_init::<GameMechanicRegistry>();
+        registry.table.insert(name, parse);
+        registry.handlers.insert(normalize(name), handler);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> App {
+        let mut app = App::new();
+        app.register_game_mechanic("card play", parse_count, |In(_): In<MechanicArgs>| {})
+            .register_game_mechanic(
+                "card play narrative setting",
+                parse_count,
+                |In(_): In<MechanicArgs>| {},
+            );
+        app
+    }
+
+    #[test]
+    fn test_resolve_picks_longest_mechanic() {
+        let app = registry();
+        let registry = app.world().resource::<GameMechanicRegistry>();
+
+        let (_, args) = registry.resolve("card play narrative setting\" 2").unwrap();
+        assert_eq!(args.count, Some(2));
+
+        let (_, args) = registry.resolve("card play").unwrap();
+        assert_eq!(args, MechanicArgs::default());
+
+        assert_eq!(
+            registry.table.names().collect::<Vec<&str>>(),
+            vec!["card play", "card play narrative setting"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_reports_script_errors() {
+        let app = registry();
+        let registry = app.world().resource::<GameMechanicRegistry>();
+
+        assert!(registry
+            .resolve("card shop")
+            .unwrap_err()
+            .to_string()
+            .contains("unknown mechanic `card shop`"));
+        assert!(registry.resolve("card playing").is_err());
+        assert!(registry.resolve("card play twice").is_err());
+    }
+
+    #[test]
+    fn test_table_has_every_builtin() {
+        let table = MechanicTable::with_builtins(&["card play dice".to_string()]);
+
+        for mechanic in BuiltinMechanic::ALL {
+            assert_eq!(table.parse(mechanic.name()).unwrap().0, mechanic.name());
+        }
+        assert_eq!(
+            table.parse("card play dice 2").unwrap(),
+            ("card play dice", MechanicArgs { count: Some(2) })
+        );
+        assert!(table.parse("card play narative setting").is_err());
+    }
+}
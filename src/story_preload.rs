@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_hui::prelude::*;
+use bevy_novel::{events::EventStartScenario, NovelData, NovelText};
+use renpy_parser::parsers::AST;
+
+use crate::api_text2img::{
+    EventDownloadImageFailed, EventDownloadImageRequest, EventDownloadImageResponse,
+};
+
+/// Background of a scene whose image could not be downloaded
+const SCENE_PLACEHOLDER: &str = "plot/scene_placeholder.png";
+
+/// Seconds to wait for the downloads before playing with placeholders
+const PRELOAD_TIMEOUT_SECONDS: f32 = 30.0;
+
+/// Downloads the generated backgrounds of a scenario before the novel player
+/// starts it, so no scene is shown empty
+pub struct ScenePreloadPlugin;
+
+impl Plugin for ScenePreloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EventPreloadScenario>()
+            .init_resource::<ScenePreload>();
+    }
+}
+
+/// Starts the scenario in the novel player once its backgrounds are cached
+#[derive(Event)]
+pub(crate) struct EventPreloadScenario {
+    pub ast: Vec<AST>,
+}
+
+/// Scenario waiting for its backgrounds
+#[derive(Resource, Default)]
+pub(crate) struct ScenePreload {
+    scenario: Option<Vec<AST>>,
+    /// images not cached in `NovelData` yet
+    pending: HashSet<String>,
+    total: usize,
+    elapsed: f32,
+}
+
+impl ScenePreload {
+    fn progress(&self) -> String {
+        format!(
+            "LOADING SCENES {}/{}",
+            self.total - self.pending.len(),
+            self.total
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct ScenePreloadScreen {}
+
+/// Generated backgrounds of a scenario, labels nested at any depth included,
+/// each listed once
+pub(crate) fn generated_scene_images(ast: &[AST]) -> Vec<String> {
+    let mut images = vec![];
+    collect_scene_images(ast, &mut images);
+    images
+}
+
+fn collect_scene_images(ast: &[AST], images: &mut Vec<String>) {
+    for node in ast.iter() {
+        match node {
+            AST::Label(_, _, children, _) => collect_scene_images(children, images),
+            AST::Scene(_, Some(image), _)
+                if image.ends_with(".jpeg") && !images.contains(image) =>
+            {
+                images.push(image.clone())
+            }
+            _ => {}
+        }
+    }
+}
+
+fn placeholder_sprite(asset_server: &AssetServer) -> Sprite {
+    Sprite {
+        image: asset_server.load(SCENE_PLACEHOLDER),
+        ..default()
+    }
+}
+
+pub(crate) fn handle_preload_scenario(
+    mut commands: Commands,
+    mut er_preload_scenario: EventReader<EventPreloadScenario>,
+    mut ew_download_image: EventWriter<EventDownloadImageRequest>,
+    mut preload: ResMut<ScenePreload>,
+    q_screen: Query<(Entity, &ScenePreloadScreen)>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in er_preload_scenario.read() {
+        let images = generated_scene_images(&event.ast);
+        for filename in images.iter() {
+            ew_download_image.write(EventDownloadImageRequest {
+                filename: filename.clone(),
+            });
+        }
+
+        *preload = ScenePreload {
+            scenario: Some(event.ast.clone()),
+            total: images.len(),
+            pending: images.into_iter().collect(),
+            elapsed: 0.0,
+        };
+
+        for (entity, _) in q_screen.iter() {
+            commands.entity(entity).despawn();
+        }
+        commands.spawn((
+            HtmlNode(asset_server.load("menu/scene_preload.html")),
+            TemplateProperties::default().with("progress", &preload.progress()),
+            ScenePreloadScreen {},
+            Name::new("scene preload screen"),
+        ));
+    }
+}
+
+/// Runs after `handle_download_image_response`, downloaded images are cached
+/// in `NovelData` by then
+pub(crate) fn handle_preload_downloads(
+    mut er_download_image: EventReader<EventDownloadImageResponse>,
+    mut er_download_failed: EventReader<EventDownloadImageFailed>,
+    mut preload: ResMut<ScenePreload>,
+    mut novel_data: ResMut<NovelData>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in er_download_image.read() {
+        preload.pending.remove(&event.filename);
+    }
+
+    for event in er_download_failed.read() {
+        if preload.pending.remove(&event.filename) {
+            warn!("scene {} shown with a placeholder", event.filename);
+            novel_data.write_image_cache(event.filename.clone(), placeholder_sprite(&asset_server));
+        }
+    }
+}
+
+/// Updates the progress every frame, the template may still be loading when
+/// the first images arrive
+pub(crate) fn start_preloaded_scenario(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut preload: ResMut<ScenePreload>,
+    mut ew_start_scenario: EventWriter<EventStartScenario>,
+    mut q_text_labels: Query<(&mut Text, &Tags)>,
+    mut q_novel_text: Query<(Entity, &mut Node, &NovelText)>,
+    q_screen: Query<(Entity, &ScenePreloadScreen)>,
+    mut novel_data: ResMut<NovelData>,
+    asset_server: Res<AssetServer>,
+) {
+    if preload.scenario.is_none() {
+        return;
+    }
+    preload.elapsed += time.delta_secs();
+
+    let progress = preload.progress();
+    for (mut text, tags) in q_text_labels.iter_mut() {
+        if let Some(marker) = tags.get("marker")
+            && marker == "text_preload_progress"
+            && text.0 != progress
+        {
+            *text = Text::new(progress.clone());
+        }
+    }
+
+    if !preload.pending.is_empty() && preload.elapsed < PRELOAD_TIMEOUT_SECONDS {
+        return;
+    }
+
+    // a download answering late still replaces its placeholder
+    for filename in preload.pending.drain() {
+        warn!("scene {} timed out, shown with a placeholder", filename);
+        novel_data.write_image_cache(filename, placeholder_sprite(&asset_server));
+    }
+
+    let Some(ast) = preload.scenario.take() else {
+        return;
+    };
+    ew_start_scenario.write(EventStartScenario { ast });
+
+    for (entity, _) in q_screen.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    for (_, mut node, _) in q_novel_text.iter_mut() {
+        node.left = Val::Percent(20.0);
+        node.margin = UiRect::new(Val::Px(20.0), Val::Px(0.0), Val::Px(0.0), Val::Px(0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(image: &str) -> AST {
+        AST::Scene(0, Some(image.to_string()), "master".to_string())
+    }
+
+    #[test]
+    fn test_nested_label_scenes_are_found_once() {
+        let ast = vec![
+            scene("intro_1"),
+            AST::Label(
+                0,
+                "start".to_string(),
+                vec![
+                    scene("a1.jpeg"),
+                    AST::Label(
+                        0,
+                        "chapter".to_string(),
+                        vec![
+                            AST::Label(0, "ending".to_string(), vec![scene("c3.jpeg")], None),
+                            scene("b2.jpeg"),
+                            scene("a1.jpeg"),
+                        ],
+                        None,
+                    ),
+                ],
+                None,
+            ),
+        ];
+
+        assert_eq!(
+            generated_scene_images(&ast),
+            vec!["a1.jpeg", "c3.jpeg", "b2.jpeg"]
+        );
+    }
+}
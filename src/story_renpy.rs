@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::{anyhow, Result};
+use bevy::prelude::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::api_text2img::{
+    EventDownloadImageRequest, EventDownloadImageResponse, GeneratedAssets, GENERATED_ASSET_SOURCE,
+};
+use crate::{
+    story_export::{StoryBeat, StoryExport},
+    story_stage::Actor,
+};
+
+/// Project folders are written under this directory, one per story
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORT_DIR: &str = "renpy-export";
+
+/// Where a file copied into `game/images` comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ImageSource {
+    /// asset path, either under `assets` or in the `generated://` source
+    Asset(String),
+    /// generated background that has to be downloaded by its filename
+    Download(String),
+}
+
+/// A finished story as a Ren'Py project: `game/script.rpy` and the images it
+/// shows, keyed by their filename in `game/images`
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RenpyProject {
+    pub script: String,
+    pub images: BTreeMap<String, ImageSource>,
+}
+
+impl RenpyProject {
+    /// Every background starts a new label so the script can be jumped around
+    /// in Ren'Py; the first one is `start`
+    pub(crate) fn from_story(story: &StoryExport) -> Self {
+        let mut characters = BTreeMap::new();
+        let mut image_statements = BTreeMap::new();
+        let mut images = BTreeMap::new();
+        let mut body = vec!["label start:".to_string()];
+        let mut n_scenes = 0;
+
+        for beat in story.beats.iter() {
+            match beat {
+                StoryBeat::Scene { image } => {
+                    n_scenes += 1;
+                    if n_scenes > 1 {
+                        body.push(String::new());
+                        body.push(format!("label chapter_{}:", n_scenes));
+                    }
+
+                    let (stem, source) = match image.strip_suffix(".jpeg") {
+                        Some(stem) => (stem, ImageSource::Download(image.clone())),
+                        None => (
+                            image.as_str(),
+                            ImageSource::Asset(format!("plot/{}.png", image)),
+                        ),
+                    };
+                    let name = identifier(&format!("bg_{}", stem));
+                    let file = format!("{}.{}", name, extension(image));
+
+                    image_statements.insert(name.clone(), format!("\"images/{}\"", file));
+                    images.insert(file, source);
+                    body.push(format!("    scene {}", name));
+                }
+                StoryBeat::Say { who: None, what } => {
+                    body.push(format!("    \"{}\"", escape(what)));
+                }
+                StoryBeat::Say {
+                    who: Some(who),
+                    what,
+                } => {
+                    let character = identifier(who);
+                    characters.insert(character.clone(), who.clone());
+                    body.push(format!("    {} \"{}\"", character, escape(what)));
+                }
+                StoryBeat::Show { image, sprite } => {
+                    let Some(actor) = Actor::from_image_name(image) else {
+                        continue;
+                    };
+                    let name =
+                        format!("{} {}", identifier(&actor.name), identifier(&actor.emotion));
+
+                    let displayable = match sprite {
+                        Some(sprite) => {
+                            let file = format!("{}.{}", name.replace(' ', "_"), extension(sprite));
+                            images.insert(file.clone(), ImageSource::Asset(sprite.clone()));
+                            format!("\"images/{}\"", file)
+                        }
+                        None => "Placeholder()".to_string(),
+                    };
+
+                    image_statements.insert(name.clone(), displayable);
+                    body.push(format!("    show {} at {}", name, actor.position));
+                }
+                StoryBeat::Hide { image } => {
+                    if let Some(actor) = Actor::from_image_name(image) {
+                        body.push(format!("    hide {}", identifier(&actor.name)));
+                    }
+                }
+                StoryBeat::Card { name } => {
+                    body.push(format!("    # card played: {}", name));
+                }
+            }
+        }
+        body.push("    return".to_string());
+
+        let mut script = vec![format!(
+            "# {} - score ${}",
+            match story.played_cards.as_slice() {
+                [] => "Untitled Story".to_string(),
+                cards => cards.join(", "),
+            },
+            story.score
+        )];
+
+        script.push(String::new());
+        for (variable, name) in characters.iter() {
+            script.push(format!(
+                "define {} = Character(\"{}\")",
+                variable,
+                escape(name)
+            ));
+        }
+
+        script.push(String::new());
+        for (name, displayable) in image_statements.iter() {
+            script.push(format!("image {} = {}", name, displayable));
+        }
+
+        script.push(String::new());
+        script.extend(body);
+        script.push(String::new());
+
+        RenpyProject {
+            script: script.join("\n"),
+            images,
+        }
+    }
+}
+
+/// Ren'Py names are lowercase identifiers, `Mysterious Stranger` is
+/// `mysterious_stranger`
+fn identifier(name: &str) -> String {
+    let identifier: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    match identifier.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => identifier,
+        _ => format!("n_{}", identifier),
+    }
+}
+
+fn extension(path: &str) -> &str {
+    path.rsplit_once('.')
+        .map(|(_, extension)| extension)
+        .filter(|extension| !extension.contains('/'))
+        .unwrap_or("png")
+}
+
+/// Dialogue is a quoted string where `[` and `{` start substitutions and
+/// text tags
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('[', "[[")
+        .replace('{', "{{")
+}
+
+/// Writes a story as a Ren'Py project folder
+#[derive(Event)]
+pub(crate) struct EventExportRenpyProject {
+    /// folder name under the export directory
+    pub name: String,
+    pub story: StoryExport,
+}
+
+pub struct RenpyExportPlugin;
+
+impl Plugin for RenpyExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EventExportRenpyProject>();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.init_resource::<PendingDownloads>().add_systems(
+            Update,
+            (handle_export_renpy_project, handle_download_image_response).chain(),
+        );
+
+        #[cfg(target_arch = "wasm32")]
+        app.add_systems(Update, handle_export_renpy_project);
+    }
+}
+
+/// Generated backgrounds still downloading, by filename, with the files they
+/// are saved to
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Resource, Default)]
+struct PendingDownloads(std::collections::HashMap<String, Vec<std::path::PathBuf>>);
+
+#[cfg(not(target_arch = "wasm32"))]
+fn handle_export_renpy_project(
+    mut er_export_renpy_project: EventReader<EventExportRenpyProject>,
+    mut ew_download_image: EventWriter<EventDownloadImageRequest>,
+    mut pending_downloads: ResMut<PendingDownloads>,
+    generated_assets: Res<GeneratedAssets>,
+) {
+    for event in er_export_renpy_project.read() {
+        let project = RenpyProject::from_story(&event.story);
+        let game_dir = std::path::Path::new(EXPORT_DIR)
+            .join(&event.name)
+            .join("game");
+
+        if let Err(err) = write_project(&project, &game_dir, &generated_assets) {
+            error!("Ren'Py export {}: {}", event.name, err);
+            continue;
+        }
+
+        for (file, source) in project.images.iter() {
+            if let ImageSource::Download(filename) = source {
+                pending_downloads
+                    .0
+                    .entry(filename.clone())
+                    .or_default()
+                    .push(game_dir.join("images").join(file));
+                ew_download_image.write(EventDownloadImageRequest {
+                    filename: filename.clone(),
+                });
+            }
+        }
+
+        info!("Ren'Py project written to {}", game_dir.display());
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn handle_export_renpy_project(mut er_export_renpy_project: EventReader<EventExportRenpyProject>) {
+    for _ in er_export_renpy_project.read() {
+        warn!("Ren'Py export writes a project folder and needs the desktop build");
+    }
+}
+
+/// Script and bundled images; downloads are saved when they arrive
+#[cfg(not(target_arch = "wasm32"))]
+fn write_project(
+    project: &RenpyProject,
+    game_dir: &std::path::Path,
+    generated_assets: &GeneratedAssets,
+) -> Result<()> {
+    let images_dir = game_dir.join("images");
+    std::fs::create_dir_all(&images_dir)?;
+    std::fs::write(game_dir.join("script.rpy"), &project.script)?;
+
+    for (file, source) in project.images.iter() {
+        let ImageSource::Asset(path) = source else {
+            continue;
+        };
+
+        let bytes = match path.strip_prefix(&format!("{}://", GENERATED_ASSET_SOURCE)) {
+            Some(path) => generated_assets
+                .read(path)
+                .ok_or_else(|| anyhow!("{} is no longer in memory", path))?,
+            None => std::fs::read(std::path::Path::new("assets").join(path))?,
+        };
+        std::fs::write(images_dir.join(file), bytes)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn handle_download_image_response(
+    mut er_download_image: EventReader<EventDownloadImageResponse>,
+    mut pending_downloads: ResMut<PendingDownloads>,
+) {
+    for event in er_download_image.read() {
+        let Some(files) = pending_downloads.0.remove(&event.filename) else {
+            continue;
+        };
+
+        for file in files {
+            if let Err(err) = event.image.save(&file) {
+                error!("Ren'Py export {}: {}", file.display(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_from_story() {
+        let story = StoryExport::new(
+            vec![
+                StoryBeat::Scene {
+                    image: "intro_1".to_string(),
+                },
+                StoryBeat::Say {
+                    who: None,
+                    what: "This world has lost all meaning.".to_string(),
+                },
+                StoryBeat::Scene {
+                    image: "3f9a.jpeg".to_string(),
+                },
+                StoryBeat::Show {
+                    image: "Mysterious Stranger_angry_left".to_string(),
+                    sprite: Some("character-cards/stranger-angry.png".to_string()),
+                },
+                StoryBeat::Say {
+                    who: Some("Mysterious Stranger".to_string()),
+                    what: "He said \"no\" [twice] {b}".to_string(),
+                },
+                StoryBeat::Hide {
+                    image: "Mysterious Stranger_angry_left".to_string(),
+                },
+            ],
+            vec!["Time Warp".to_string()],
+            120,
+        );
+
+        let project = RenpyProject::from_story(&story);
+
+        assert_eq!(
+            project.script,
+            r#"# Time Warp - score $120
+
+define mysterious_stranger = Character("Mysterious Stranger")
+
+image bg_3f9a = "images/bg_3f9a.jpeg"
+image bg_intro_1 = "images/bg_intro_1.png"
+image mysterious_stranger angry = "images/mysterious_stranger_angry.png"
+
+label start:
+    scene bg_intro_1
+    "This world has lost all meaning."
+
+label chapter_2:
+    scene bg_3f9a
+    show mysterious_stranger angry at left
+    mysterious_stranger "He said \"no\" [[twice] {{b}"
+    hide mysterious_stranger
+    return
+"#
+        );
+
+        assert_eq!(
+            project.images.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "bg_3f9a.jpeg".to_string(),
+                    ImageSource::Download("3f9a.jpeg".to_string())
+                ),
+                (
+                    "bg_intro_1.png".to_string(),
+                    ImageSource::Asset("plot/intro_1.png".to_string())
+                ),
+                (
+                    "mysterious_stranger_angry.png".to_string(),
+                    ImageSource::Asset("character-cards/stranger-angry.png".to_string())
+                ),
+            ]
+        );
+    }
+}
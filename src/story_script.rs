@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use renpy_parser::parsers::AST;
+
+/// Scenario flow written as `game_mechanic` statements:
+///
+/// - `game_mechanic "set rage = 3"`, also `+=` and `-=`
+/// - `game_mechanic "jump finale"`
+/// - `game_mechanic "if score < 50 jump bad_end"`, comparing numbers and
+///   variables with `<`, `<=`, `>`, `>=`, `==` and `!=`; `score` and
+///   `chapters` are set by the game
+/// - `game_mechanic "if played 'Mysterious Stranger' jump stranger"`, also
+///   `if not played ...`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptCommand {
+    Set(String, Assignment, i64),
+    Jump(Option<Condition>, String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Assignment {
+    Assign,
+    Add,
+    Subtract,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Number(i64),
+    Variable(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Operand, String, Operand),
+    Played(String),
+    NotPlayed(String),
+}
+
+/// Values scenario conditions read: built-ins from the game state and the
+/// variables set by the scenario itself
+pub struct ScriptScope<'a> {
+    pub builtins: Vec<(&'static str, i64)>,
+    pub variables: &'a BTreeMap<String, i64>,
+    pub played_cards: &'a [String],
+}
+
+impl ScriptScope<'_> {
+    fn value(&self, operand: &Operand) -> Result<i64> {
+        match operand {
+            Operand::Number(n) => Ok(*n),
+            Operand::Variable(name) => self
+                .builtins
+                .iter()
+                .find(|(builtin, _)| builtin == name)
+                .map(|(_, value)| *value)
+                .or_else(|| self.variables.get(name).copied())
+                .ok_or_else(|| anyhow!("unknown variable `{}`", name)),
+        }
+    }
+
+    fn played(&self, card: &str) -> bool {
+        self.played_cards
+            .iter()
+            .any(|played| played.eq_ignore_ascii_case(card))
+    }
+}
+
+impl Condition {
+    pub fn eval(&self, scope: &ScriptScope) -> Result<bool> {
+        match self {
+            Condition::Compare(left, op, right) => {
+                let (left, right) = (scope.value(left)?, scope.value(right)?);
+                Ok(match op.as_str() {
+                    "<" => left < right,
+                    "<=" => left <= right,
+                    ">" => left > right,
+                    ">=" => left >= right,
+                    "==" => left == right,
+                    "!=" => left != right,
+                    _ => bail!("unknown comparison `{}`", op),
+                })
+            }
+            Condition::Played(card) => Ok(scope.played(card)),
+            Condition::NotPlayed(card) => Ok(!scope.played(card)),
+        }
+    }
+}
+
+impl ScriptCommand {
+    /// `None` when the statement is not a scripting form, so other mechanics
+    /// can handle it
+    pub fn parse(statement: &str) -> Option<Result<Self>> {
+        let statement = statement.trim();
+        let (keyword, rest) = statement
+            .split_once(char::is_whitespace)
+            .unwrap_or((statement, ""));
+        let rest = rest.trim();
+
+        match keyword {
+            "set" => Some(parse_set(rest)),
+            "jump" => Some(parse_label(rest).map(|label| ScriptCommand::Jump(None, label))),
+            "if" => Some(parse_if(rest)),
+            _ => None,
+        }
+    }
+}
+
+fn parse_set(rest: &str) -> Result<ScriptCommand> {
+    let (name, assignment, value) = if let Some((name, value)) = rest.split_once("+=") {
+        (name, Assignment::Add, value)
+    } else if let Some((name, value)) = rest.split_once("-=") {
+        (name, Assignment::Subtract, value)
+    } else if let Some((name, value)) = rest.split_once('=') {
+        (name, Assignment::Assign, value)
+    } else {
+        bail!("expected `set name = value`, got `set {}`", rest);
+    };
+
+    let name = parse_variable(name.trim())?;
+    let value = value
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| anyhow!("`{}` is not a number", value.trim()))?;
+
+    Ok(ScriptCommand::Set(name, assignment, value))
+}
+
+fn parse_if(rest: &str) -> Result<ScriptCommand> {
+    let (condition, label) = rest
+        .rsplit_once(" jump ")
+        .ok_or_else(|| anyhow!("expected `if condition jump label`, got `if {}`", rest))?;
+    let condition = condition.trim();
+    let label = parse_label(label)?;
+
+    if let Some(card) = condition.strip_prefix("not played ") {
+        return Ok(ScriptCommand::Jump(
+            Some(Condition::NotPlayed(parse_card_name(card)?)),
+            label,
+        ));
+    }
+
+    if let Some(card) = condition.strip_prefix("played ") {
+        return Ok(ScriptCommand::Jump(
+            Some(Condition::Played(parse_card_name(card)?)),
+            label,
+        ));
+    }
+
+    let parts = condition.split_whitespace().collect::<Vec<&str>>();
+    let [left, op, right] = parts[..] else {
+        bail!("expected `left op right`, got `{}`", condition);
+    };
+    if !["<", "<=", ">", ">=", "==", "!="].contains(&op) {
+        bail!("unknown comparison `{}`", op);
+    }
+
+    Ok(ScriptCommand::Jump(
+        Some(Condition::Compare(
+            parse_operand(left)?,
+            op.to_string(),
+            parse_operand(right)?,
+        )),
+        label,
+    ))
+}
+
+fn parse_operand(operand: &str) -> Result<Operand> {
+    match operand.parse::<i64>() {
+        Ok(n) => Ok(Operand::Number(n)),
+        Err(_) => parse_variable(operand).map(Operand::Variable),
+    }
+}
+
+fn parse_variable(name: &str) -> Result<String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match valid {
+        true => Ok(name.to_lowercase()),
+        false => bail!("`{}` is not a variable name", name),
+    }
+}
+
+fn parse_label(label: &str) -> Result<String> {
+    let label = label.trim();
+    match !label.is_empty() && !label.contains(char::is_whitespace) {
+        true => Ok(label.to_string()),
+        false => bail!("`{}` is not a label", label),
+    }
+}
+
+/// Card names are quoted; the statement itself is quoted in `.rpy`, so single
+/// quotes work as well
+fn parse_card_name(card: &str) -> Result<String> {
+    let card = card.trim().trim_matches(['"', '\'']).trim();
+    match card.is_empty() {
+        true => bail!("expected a card name"),
+        false => Ok(card.to_string()),
+    }
+}
+
+pub fn apply_assignment(
+    variables: &mut BTreeMap<String, i64>,
+    name: &str,
+    assignment: Assignment,
+    value: i64,
+) {
+    let variable = variables.entry(name.to_string()).or_default();
+    match assignment {
+        Assignment::Assign => *variable = value,
+        Assignment::Add => *variable += value,
+        Assignment::Subtract => *variable -= value,
+    }
+}
+
+/// Index of a label node anywhere in the scenario
+pub fn find_label_index(ast: &[AST], name: &str) -> Option<usize> {
+    ast.iter().find_map(|node| match node {
+        AST::Label(index, label, children, _) => match label == name {
+            true => Some(*index),
+            false => find_label_index(children, name),
+        },
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script_commands() {
+        assert_eq!(
+            ScriptCommand::parse("if score < 50 jump bad_end")
+                .unwrap()
+                .unwrap(),
+            ScriptCommand::Jump(
+                Some(Condition::Compare(
+                    Operand::Variable("score".to_string()),
+                    "<".to_string(),
+                    Operand::Number(50)
+                )),
+                "bad_end".to_string()
+            )
+        );
+        assert_eq!(
+            ScriptCommand::parse("set Rage += 2").unwrap().unwrap(),
+            ScriptCommand::Set("rage".to_string(), Assignment::Add, 2)
+        );
+        assert_eq!(
+            ScriptCommand::parse("if not played 'Mysterious Stranger' jump alone")
+                .unwrap()
+                .unwrap(),
+            ScriptCommand::Jump(
+                Some(Condition::NotPlayed("Mysterious Stranger".to_string())),
+                "alone".to_string()
+            )
+        );
+
+        assert!(ScriptCommand::parse("card play poker").is_none());
+        assert!(ScriptCommand::parse("if score < jump x").unwrap().is_err());
+        assert!(ScriptCommand::parse("set rage = lots").unwrap().is_err());
+        assert!(ScriptCommand::parse("if score ~ 3 jump x")
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_conditions_read_scope() {
+        let mut variables = BTreeMap::new();
+        apply_assignment(&mut variables, "rage", Assignment::Assign, 3);
+        apply_assignment(&mut variables, "rage", Assignment::Subtract, 1);
+
+        let played_cards = vec!["Mysterious Stranger".to_string()];
+        let scope = ScriptScope {
+            builtins: vec![("score", 40)],
+            variables: &variables,
+            played_cards: &played_cards,
+        };
+
+        let condition = |statement: &str| match ScriptCommand::parse(statement) {
+            Some(Ok(ScriptCommand::Jump(Some(condition), _))) => condition.eval(&scope),
+            _ => panic!("not a condition: {}", statement),
+        };
+
+        assert!(condition("if score < 50 jump x").unwrap());
+        assert!(condition("if rage == 2 jump x").unwrap());
+        assert!(!condition("if rage > score jump x").unwrap());
+        assert!(condition("if played \"mysterious stranger\" jump x").unwrap());
+        assert!(condition("if calm > 1 jump x").is_err());
+    }
+}
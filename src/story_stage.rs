@@ -0,0 +1,227 @@
+use std::fmt;
+
+use bevy::{math::Vec2, sprite::Anchor};
+
+/// Most characters on screen at once, one per position
+pub(crate) const MAX_ON_STAGE: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StagePosition {
+    Left,
+    Center,
+    Right,
+}
+
+impl StagePosition {
+    /// Order in which free positions are taken
+    const ENTRANCES: [StagePosition; MAX_ON_STAGE] = [
+        StagePosition::Center,
+        StagePosition::Left,
+        StagePosition::Right,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(StagePosition::Left),
+            "center" => Some(StagePosition::Center),
+            "right" => Some(StagePosition::Right),
+            _ => None,
+        }
+    }
+
+    /// Portraits are drawn at the screen center, the anchor shifts them by
+    /// their own width to the side
+    pub(crate) fn anchor(&self) -> Anchor {
+        match self {
+            StagePosition::Left => Anchor::Custom(Vec2::new(1.0, 0.0)),
+            StagePosition::Center => Anchor::Center,
+            StagePosition::Right => Anchor::Custom(Vec2::new(-1.0, 0.0)),
+        }
+    }
+}
+
+impl fmt::Display for StagePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StagePosition::Left => write!(f, "left"),
+            StagePosition::Center => write!(f, "center"),
+            StagePosition::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// Character on screen
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Actor {
+    pub name: String,
+    pub emotion: String,
+    pub position: StagePosition,
+    /// line the character last spoke, the quietest one leaves first
+    spoke_at: usize,
+}
+
+impl Actor {
+    /// Image cache key, one sprite per expression and position
+    pub(crate) fn image_name(&self) -> String {
+        format!("{}_{}_{}", self.name, self.emotion, self.position)
+    }
+
+    /// Reads an actor back from its image cache key, for replaying a story
+    pub(crate) fn from_image_name(image_name: &str) -> Option<Self> {
+        let mut parts = image_name.rsplitn(3, '_');
+        let position = StagePosition::from_name(parts.next()?)?;
+        let emotion = parts.next()?.to_string();
+        let name = parts.next()?.to_string();
+
+        Some(Actor {
+            name,
+            emotion,
+            position,
+            spoke_at: 0,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum StageDirection {
+    Show(Actor),
+    Hide(Actor),
+}
+
+/// Keeps speaking characters on screen across lines and only emits show/hide
+/// nodes when someone enters, changes expression or leaves
+#[derive(Default)]
+pub(crate) struct Stage {
+    actors: Vec<Actor>,
+    n_lines: usize,
+}
+
+impl Stage {
+    /// Puts a speaking character on stage, making room if all positions are taken
+    pub(crate) fn speak(&mut self, name: &str, emotion: Option<&str>) -> Vec<StageDirection> {
+        self.n_lines += 1;
+        let emotion = emotion.unwrap_or("neutral").to_string();
+        let mut directions = vec![];
+
+        if let Some(actor) = self
+            .actors
+            .iter_mut()
+            .find(|actor| actor.name.eq_ignore_ascii_case(name))
+        {
+            actor.spoke_at = self.n_lines;
+            if actor.emotion != emotion {
+                directions.push(StageDirection::Hide(actor.clone()));
+                actor.emotion = emotion;
+                directions.push(StageDirection::Show(actor.clone()));
+            }
+            return directions;
+        }
+
+        if self.actors.len() == MAX_ON_STAGE
+            && let Some(quietest) = self
+                .actors
+                .iter()
+                .min_by_key(|actor| actor.spoke_at)
+                .map(|actor| actor.name.clone())
+        {
+            directions.extend(self.leave(&quietest));
+        }
+
+        let position = StagePosition::ENTRANCES
+            .into_iter()
+            .find(|position| self.actors.iter().all(|actor| actor.position != *position))
+            .unwrap_or(StagePosition::Center);
+
+        let actor = Actor {
+            name: name.to_string(),
+            emotion,
+            position,
+            spoke_at: self.n_lines,
+        };
+        directions.push(StageDirection::Show(actor.clone()));
+        self.actors.push(actor);
+
+        directions
+    }
+
+    pub(crate) fn leave(&mut self, name: &str) -> Vec<StageDirection> {
+        let mut directions = vec![];
+        self.actors.retain(|actor| {
+            let leaving = actor.name.eq_ignore_ascii_case(name);
+            if leaving {
+                directions.push(StageDirection::Hide(actor.clone()));
+            }
+            !leaving
+        });
+        directions
+    }
+
+    /// Everyone leaves, on a scene change or at the end of a chapter
+    pub(crate) fn clear(&mut self) -> Vec<StageDirection> {
+        self.actors.drain(..).map(StageDirection::Hide).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(directions: &[StageDirection]) -> Vec<String> {
+        directions
+            .iter()
+            .filter_map(|direction| match direction {
+                StageDirection::Show(actor) => Some(actor.image_name()),
+                StageDirection::Hide(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_characters_stay_on_stage() {
+        let mut stage = Stage::default();
+
+        assert_eq!(
+            shown(&stage.speak("Igor", None)),
+            vec!["Igor_neutral_center"]
+        );
+        assert_eq!(
+            shown(&stage.speak("Madonna", Some("happy"))),
+            vec!["Madonna_happy_left"]
+        );
+        assert!(stage.speak("Igor", None).is_empty());
+
+        let directions = stage.speak("Igor", Some("angry"));
+        assert!(
+            matches!(&directions[0], StageDirection::Hide(actor) if actor.emotion == "neutral")
+        );
+        assert_eq!(shown(&directions), vec!["Igor_angry_center"]);
+    }
+
+    #[test]
+    fn test_quietest_character_leaves_a_full_stage() {
+        let mut stage = Stage::default();
+        stage.speak("Igor", None);
+        stage.speak("Madonna", None);
+        stage.speak("Komarito", None);
+        stage.speak("Igor", None);
+
+        let directions = stage.speak("Narrator", None);
+        assert!(matches!(&directions[0], StageDirection::Hide(actor) if actor.name == "Madonna"));
+        assert_eq!(shown(&directions), vec!["Narrator_neutral_left"]);
+
+        assert_eq!(stage.clear().len(), MAX_ON_STAGE);
+        assert!(stage.clear().is_empty());
+    }
+
+    #[test]
+    fn test_actor_from_image_name() {
+        let actor = Actor::from_image_name("Old_Man_afraid_right").unwrap();
+
+        assert_eq!(actor.name, "Old_Man");
+        assert_eq!(actor.emotion, "afraid");
+        assert_eq!(actor.position, StagePosition::Right);
+        assert_eq!(actor.image_name(), "Old_Man_afraid_right");
+
+        assert!(Actor::from_image_name("intro_1").is_none());
+    }
+}
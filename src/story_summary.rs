@@ -0,0 +1,138 @@
+use crate::story_template::{count_tokens, truncate_to_token_budget};
+
+/// Asks the LLM to fold older chapters into the synopsis
+const SUMMARY_PROMPT: &str = r#"
+You are editor of a visual novel.
+Summarise the story below in a short synopsis of at most 150 words.
+Keep names of characters, who is dead and who is alive, and unresolved conflicts.
+Do not include any instructions or explanations.
+Respond only with the synopsis.
+"#;
+
+/// Story told so far: a synopsis of older chapters followed by the most recent
+/// lines verbatim
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StorySoFar {
+    pub synopsis: String,
+    pub lines: Vec<String>,
+    /// oldest lines sent to the LLM and not yet folded into the synopsis
+    summarising: usize,
+}
+
+impl StorySoFar {
+    pub(crate) fn push(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    fn lines_tokens(&self) -> usize {
+        self.lines.iter().map(|line| count_tokens(line)).sum()
+    }
+
+    /// Returns the summary prompt once the verbatim lines exceed `threshold`
+    /// tokens; lines within the last `keep_recent` tokens stay verbatim.
+    /// Only one summary is in flight at a time
+    pub(crate) fn start_summary(&mut self, threshold: usize, keep_recent: usize) -> Option<String> {
+        if self.summarising > 0 || self.lines_tokens() <= threshold {
+            return None;
+        }
+
+        let recent = truncate_to_token_budget(&self.lines, keep_recent).len();
+        let older = self.lines.len() - recent;
+        if older == 0 {
+            return None;
+        }
+
+        self.summarising = older;
+
+        Some(format!(
+            "{}Synopsis so far: ```{}```. Story to add to synopsis: ```{}```.",
+            SUMMARY_PROMPT,
+            self.synopsis,
+            self.lines[..older].join(" ")
+        ))
+    }
+
+    /// Replaces the synopsis and drops the lines it now covers; lines pushed
+    /// while the summary was being written are kept
+    pub(crate) fn finish_summary(&mut self, synopsis: &str) {
+        let synopsis = synopsis.trim();
+        if !synopsis.is_empty() {
+            self.synopsis = synopsis.to_string();
+            self.lines.drain(..self.summarising.min(self.lines.len()));
+        }
+
+        self.summarising = 0;
+    }
+
+    /// The summary request failed; its lines stay verbatim and go into the next
+    /// summary
+    pub(crate) fn cancel_summary(&mut self) {
+        self.summarising = 0;
+    }
+
+    /// Synopsis and as many recent lines as fit into `budget` tokens, for `{STORY}`
+    pub(crate) fn prompt_lines(&self, budget: usize) -> Vec<String> {
+        let mut lines = vec![];
+        let mut budget = budget;
+
+        if !self.synopsis.is_empty() {
+            let synopsis = format!("Synopsis: {}", self.synopsis);
+            budget = budget.saturating_sub(count_tokens(&synopsis));
+            lines.push(synopsis);
+        }
+
+        lines.extend(truncate_to_token_budget(&self.lines, budget));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story(n: usize) -> StorySoFar {
+        let mut story = StorySoFar::default();
+        for i in 0..n {
+            story.push(format!("Line number {} of the story.", i));
+        }
+        story
+    }
+
+    #[test]
+    fn test_summary_starts_over_threshold() {
+        let mut story = story(4);
+        assert!(story.start_summary(100, 20).is_none());
+
+        let prompt = story.start_summary(20, 20).unwrap();
+        assert!(prompt.contains("Line number 0"));
+        assert!(!prompt.contains("Line number 3"));
+
+        // one summary at a time
+        assert!(story.start_summary(20, 20).is_none());
+    }
+
+    #[test]
+    fn test_finish_summary_keeps_new_lines() {
+        let mut story = story(4);
+        story.start_summary(20, 20).unwrap();
+        story.push("A late line.".to_string());
+
+        story.finish_summary(" Igor met Komarito. ");
+
+        assert_eq!(story.synopsis, "Igor met Komarito.");
+        assert_eq!(story.lines.last().unwrap(), "A late line.");
+        assert_eq!(story.prompt_lines(1000)[0], "Synopsis: Igor met Komarito.");
+        assert!(story.start_summary(1000, 20).is_none());
+    }
+
+    #[test]
+    fn test_cancelled_summary_starts_again() {
+        let mut story = story(4);
+        story.start_summary(20, 20).unwrap();
+
+        story.cancel_summary();
+
+        assert_eq!(story.lines.len(), 4);
+        assert!(story.start_summary(20, 20).is_some());
+    }
+}
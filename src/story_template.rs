@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use bevy::asset::Asset;
+use bevy::prelude::{Deref, DerefMut, Resource};
+use bevy::reflect::TypePath;
+use serde::Deserialize;
+
+/// Named prompt pieces loaded from `plot/fragments.json`, used with `{% include name %}`
+#[derive(Deserialize, Asset, Resource, TypePath, Deref, DerefMut, Default, Clone)]
+pub struct PromptFragments(pub HashMap<String, String>);
+
+/// Fragments may include each other, but not forever
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Variables `prompt_context` in `visual_novel.rs` sets for every
+/// `llm_generate` prompt, and the ones `rpy-lint` accepts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptVariable {
+    Score,
+    Seed,
+    Chapters,
+    BestHand,
+    Choice,
+    Prompt,
+    Combinations,
+    Setting,
+    PlotTwist,
+    Conflict,
+    Psychosis,
+    Characters,
+    Cards,
+    PlayedCards,
+    Choices,
+    Variables,
+    Story,
+}
+
+impl PromptVariable {
+    pub const ALL: [PromptVariable; 17] = [
+        PromptVariable::Score,
+        PromptVariable::Seed,
+        PromptVariable::Chapters,
+        PromptVariable::BestHand,
+        PromptVariable::Choice,
+        PromptVariable::Prompt,
+        PromptVariable::Combinations,
+        PromptVariable::Setting,
+        PromptVariable::PlotTwist,
+        PromptVariable::Conflict,
+        PromptVariable::Psychosis,
+        PromptVariable::Characters,
+        PromptVariable::Cards,
+        PromptVariable::PlayedCards,
+        PromptVariable::Choices,
+        PromptVariable::Variables,
+        PromptVariable::Story,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PromptVariable::Score => "SCORE",
+            PromptVariable::Seed => "SEED",
+            PromptVariable::Chapters => "CHAPTERS",
+            PromptVariable::BestHand => "BEST HAND",
+            PromptVariable::Choice => "CHOICE",
+            PromptVariable::Prompt => "PROMPT",
+            PromptVariable::Combinations => "COMBINATIONS",
+            PromptVariable::Setting => "SETTING",
+            PromptVariable::PlotTwist => "PLOT TWIST",
+            PromptVariable::Conflict => "CONFLICT",
+            PromptVariable::Psychosis => "PSYCHOSIS",
+            PromptVariable::Characters => "CHARACTERS",
+            PromptVariable::Cards => "CARDS",
+            PromptVariable::PlayedCards => "PLAYED CARDS",
+            PromptVariable::Choices => "CHOICES",
+            PromptVariable::Variables => "VARIABLES",
+            PromptVariable::Story => "STORY",
+        }
+    }
+
+    /// List variables, prompts may loop over these
+    pub fn is_list(self) -> bool {
+        !matches!(
+            self,
+            PromptVariable::Score
+                | PromptVariable::Seed
+                | PromptVariable::Chapters
+                | PromptVariable::BestHand
+                | PromptVariable::Choice
+                | PromptVariable::Prompt
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    UnknownVariable(String),
+    UnknownFragment(String),
+    NotAList(String),
+    Syntax(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownVariable(name) => write!(f, "unknown variable `{}`", name),
+            TemplateError::UnknownFragment(name) => write!(f, "unknown fragment `{}`", name),
+            TemplateError::NotAList(name) => write!(f, "`{}` is not a list", name),
+            TemplateError::Syntax(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateValue {
+    Text(String),
+    /// items, separator used when the list is printed as a whole
+    List(Vec<String>, String),
+}
+
+impl TemplateValue {
+    pub fn list(items: Vec<String>, separator: &str) -> Self {
+        TemplateValue::List(items, separator.to_string())
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            TemplateValue::Text(text) => !text.trim().is_empty(),
+            TemplateValue::List(items, _) => !items.is_empty(),
+        }
+    }
+
+    fn render(&self) -> String {
+        match self {
+            TemplateValue::Text(text) => text.clone(),
+            TemplateValue::List(items, separator) => items.join(separator),
+        }
+    }
+}
+
+/// Variables and fragments a prompt template is rendered with
+#[derive(Default)]
+pub struct TemplateContext {
+    values: HashMap<String, TemplateValue>,
+    fragments: HashMap<String, String>,
+}
+
+impl TemplateContext {
+    pub fn with_fragments(fragments: HashMap<String, String>) -> Self {
+        TemplateContext {
+            values: HashMap::new(),
+            fragments,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: TemplateValue) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn set_text(&mut self, name: &str, text: impl Into<String>) {
+        self.set(name, TemplateValue::Text(text.into()));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TemplateValue> {
+        self.values.get(name)
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(|name| name.as_str())
+    }
+
+    /// Renders `{VARIABLE}`, `{% if VARIABLE %}`/`{% else %}`/`{% endif %}`,
+    /// `{% for item in LIST %}{item}{% endfor %}` and `{% include fragment %}`
+    pub fn render(&self, template: &str) -> Result<String, TemplateError> {
+        self.render_with_depth(template, 0)
+    }
+
+    fn render_with_depth(&self, template: &str, depth: usize) -> Result<String, TemplateError> {
+        let tokens = tokenize(template)?;
+        let (nodes, _, _) = parse_until(&tokens, 0, &[])?;
+
+        let mut output = String::new();
+        self.render_nodes(&nodes, &HashMap::new(), depth, &mut output)?;
+        Ok(output)
+    }
+
+    fn lookup<'a>(
+        &'a self,
+        name: &str,
+        scope: &'a HashMap<String, TemplateValue>,
+    ) -> Result<&'a TemplateValue, TemplateError> {
+        scope
+            .get(name)
+            .or_else(|| self.values.get(name))
+            .ok_or_else(|| TemplateError::UnknownVariable(name.to_string()))
+    }
+
+    fn render_nodes(
+        &self,
+        nodes: &[TemplateNode],
+        scope: &HashMap<String, TemplateValue>,
+        depth: usize,
+        output: &mut String,
+    ) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                TemplateNode::Text(text) => output.push_str(text),
+                TemplateNode::Variable(name) => {
+                    output.push_str(&self.lookup(name, scope)?.render())
+                }
+                TemplateNode::If {
+                    variable,
+                    negate,
+                    then,
+                    otherwise,
+                } => {
+                    let truthy = self.lookup(variable, scope)?.is_truthy();
+                    let branch = if truthy != *negate { then } else { otherwise };
+                    self.render_nodes(branch, scope, depth, output)?;
+                }
+                TemplateNode::For {
+                    item,
+                    variable,
+                    body,
+                } => {
+                    let items = match self.lookup(variable, scope)? {
+                        TemplateValue::List(items, _) => items.clone(),
+                        TemplateValue::Text(_) => {
+                            return Err(TemplateError::NotAList(variable.clone()))
+                        }
+                    };
+
+                    for value in items {
+                        let mut scope = scope.clone();
+                        scope.insert(item.clone(), TemplateValue::Text(value));
+                        self.render_nodes(body, &scope, depth, output)?;
+                    }
+                }
+                TemplateNode::Include(name) => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(TemplateError::Syntax(format!(
+                            "fragment `{}` includes itself",
+                            name
+                        )));
+                    }
+
+                    let fragment = self
+                        .fragments
+                        .get(name)
+                        .ok_or_else(|| TemplateError::UnknownFragment(name.clone()))?;
+                    output.push_str(&self.render_with_depth(fragment, depth + 1)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Approximate token count: models average a bit over one token per word
+pub fn count_tokens(text: &str) -> usize {
+    (text.split_whitespace().count() * 4).div_ceil(3)
+}
+
+/// Keeps the most recent lines that fit into `budget` tokens
+pub fn truncate_to_token_budget(lines: &[String], budget: usize) -> Vec<String> {
+    let mut used = 0;
+    let mut kept = vec![];
+
+    for line in lines.iter().rev() {
+        used += count_tokens(line);
+        if used > budget {
+            break;
+        }
+        kept.push(line.clone());
+    }
+
+    kept.reverse();
+    kept
+}
+
+// ------
+// Parser
+// ------
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Text(String),
+    Variable(String),
+    Tag(String),
+}
+
+#[derive(Debug)]
+enum TemplateNode {
+    Text(String),
+    Variable(String),
+    If {
+        variable: String,
+        negate: bool,
+        then: Vec<TemplateNode>,
+        otherwise: Vec<TemplateNode>,
+    },
+    For {
+        item: String,
+        variable: String,
+        body: Vec<TemplateNode>,
+    },
+    Include(String),
+}
+
+/// Upper case names are story variables (`{PLOT TWIST}`), lower case ones are
+/// loop items; anything else in braces is literal text, e.g. JSON examples
+fn is_variable_name(name: &str) -> bool {
+    let upper = name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == ' ');
+    let lower = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+
+    upper || lower
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>, TemplateError> {
+    let mut tokens = vec![];
+    let mut text = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let candidate = &rest[start..];
+
+        if let Some(tag) = candidate.strip_prefix("{%") {
+            let end = tag
+                .find("%}")
+                .ok_or_else(|| TemplateError::Syntax("unclosed `{%` tag".to_string()))?;
+            if !text.is_empty() {
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+            }
+            tokens.push(Token::Tag(tag[..end].trim().to_string()));
+            rest = &tag[end + 2..];
+            continue;
+        }
+
+        if let Some(end) = candidate.find('}')
+            && is_variable_name(&candidate[1..end])
+        {
+            if !text.is_empty() {
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+            }
+            tokens.push(Token::Variable(candidate[1..end].to_string()));
+            rest = &candidate[end + 1..];
+            continue;
+        }
+
+        text.push('{');
+        rest = &candidate[1..];
+    }
+
+    text.push_str(rest);
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+
+    Ok(tokens)
+}
+
+/// Parses tokens until one of the `stop` tags; returns the nodes, the position
+/// after the stop tag and the tag itself
+fn parse_until<'a>(
+    tokens: &'a [Token],
+    mut position: usize,
+    stop: &[&str],
+) -> Result<(Vec<TemplateNode>, usize, Option<&'a str>), TemplateError> {
+    let mut nodes = vec![];
+
+    while position < tokens.len() {
+        match &tokens[position] {
+            Token::Text(text) => nodes.push(TemplateNode::Text(text.clone())),
+            Token::Variable(name) => nodes.push(TemplateNode::Variable(name.clone())),
+            Token::Tag(tag) => {
+                let words = tag.split_whitespace().collect::<Vec<&str>>();
+
+                if words.first().is_some_and(|word| stop.contains(word)) {
+                    return Ok((nodes, position + 1, Some(tag.as_str())));
+                }
+
+                match words.as_slice() {
+                    ["if", "not", variable @ ..] | ["if", variable @ ..] => {
+                        let negate = words.get(1) == Some(&"not");
+                        let variable = variable.join(" ");
+                        let (then, next, tag) =
+                            parse_until(tokens, position + 1, &["else", "endif"])?;
+                        let (otherwise, next) = match tag {
+                            Some("else") => {
+                                let (otherwise, next, tag) = parse_until(tokens, next, &["endif"])?;
+                                if tag.is_none() {
+                                    return Err(TemplateError::Syntax("missing `endif`".into()));
+                                }
+                                (otherwise, next)
+                            }
+                            Some(_) => (vec![], next),
+                            None => return Err(TemplateError::Syntax("missing `endif`".into())),
+                        };
+
+                        nodes.push(TemplateNode::If {
+                            variable,
+                            negate,
+                            then,
+                            otherwise,
+                        });
+                        position = next;
+                        continue;
+                    }
+                    ["for", item, "in", variable @ ..] if !variable.is_empty() => {
+                        let (body, next, tag) = parse_until(tokens, position + 1, &["endfor"])?;
+                        if tag.is_none() {
+                            return Err(TemplateError::Syntax("missing `endfor`".into()));
+                        }
+
+                        nodes.push(TemplateNode::For {
+                            item: item.to_string(),
+                            variable: variable.join(" "),
+                            body,
+                        });
+                        position = next;
+                        continue;
+                    }
+                    ["include", name] => {
+                        nodes.push(TemplateNode::Include(name.trim_matches('"').to_string()));
+                    }
+                    _ => {
+                        return Err(TemplateError::Syntax(format!(
+                            "unknown tag `{{% {} %}}`",
+                            tag
+                        )))
+                    }
+                }
+            }
+        }
+
+        position += 1;
+    }
+
+    Ok((nodes, position, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        let mut fragments = HashMap::new();
+        fragments.insert("story".to_string(), "Story so far: {STORY}.".to_string());
+        fragments.insert("loop".to_string(), "{% include loop %}".to_string());
+
+        let mut context = TemplateContext::with_fragments(fragments);
+        context.set_text("SCORE", "42");
+        context.set_text("PSYCHOSIS", "");
+        context.set_text("STORY", "It rained");
+        context.set(
+            "PLOT TWIST",
+            TemplateValue::list(vec!["Time Warp".into(), "Betrayal".into()], " "),
+        );
+        context
+    }
+
+    #[test]
+    fn test_variables_and_literal_braces() {
+        let rendered = context()
+            .render(r#"Score {SCORE}, twists {PLOT TWIST}, json {"beats": []}"#)
+            .unwrap();
+
+        assert_eq!(
+            rendered,
+            r#"Score 42, twists Time Warp Betrayal, json {"beats": []}"#
+        );
+    }
+
+    #[test]
+    fn test_conditionals_loops_and_includes() {
+        let template = "{% if PSYCHOSIS %}mad{% else %}sane{% endif %}|\
+                        {% if not PSYCHOSIS %}calm{% endif %}|\
+                        {% for twist in PLOT TWIST %}[{twist}]{% endfor %}|\
+                        {% include story %}";
+
+        assert_eq!(
+            context().render(template).unwrap(),
+            "sane|calm|[Time Warp][Betrayal]|Story so far: It rained."
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            context().render("{SETTNG}"),
+            Err(TemplateError::UnknownVariable("SETTNG".to_string()))
+        );
+        assert_eq!(
+            context().render("{% include nope %}"),
+            Err(TemplateError::UnknownFragment("nope".to_string()))
+        );
+        assert!(context().render("{% if SCORE %}no end").is_err());
+        assert!(context().render("{% include loop %}").is_err());
+        assert!(context()
+            .render("{% for x in SCORE %}{x}{% endfor %}")
+            .is_err());
+    }
+
+    #[test]
+    fn test_truncate_to_token_budget() {
+        let lines = vec![
+            "one two three".to_string(),
+            "four five six".to_string(),
+            "seven eight nine".to_string(),
+        ];
+
+        assert_eq!(truncate_to_token_budget(&lines, 8), lines[1..].to_vec());
+        assert_eq!(truncate_to_token_budget(&lines, 100), lines);
+    }
+}
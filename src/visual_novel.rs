@@ -1,3 +1,4 @@
+use anyhow::{anyhow, Result};
 use bevy::{
     asset::RenderAssetUsages,
     prelude::*,
@@ -18,42 +19,59 @@ use renpy_parser::parsers::AST;
 use crate::{
     api_llm::*,
     api_nft::EventPersistScenarioRequest,
-    api_text2img::{EventDownloadImageResponse, EventText2ImageRequest, EventText2ImageResponse},
-    cards_game::{
-        filter_character_deck, filter_initial_character_cards, filter_initial_narrative_cards,
-        filter_psychosis_cards,
+    api_text2img::{
+        EventDownloadImageResponse, EventText2ImageRequest, EventText2ImageResponse,
+        Text2ImageRequestType,
     },
-    menu_game::{EventRefreshUI, EventRenderUI, PokerMenuSettings},
-    AppState, EventGameOver, EventStartNarrativeCardShop, EventStartNarrativeGame,
-    EventStartPokerGame, GameState, GameType, ScenarioHandle,
+    cards_forge::CardForge,
+    menu_game::{EventRefreshUI, EventRenderUI},
+    story_attributes::run_attributes,
+    story_campaign::CampaignProgress,
+    story_characters::{CharacterSprites, CHARACTERS_PROMPT},
+    story_export::{StoryBeat, StoryExport},
+    story_format::{
+        parse_heuristic_reply, parse_structured_reply, CHOICES_PROMPT, STRUCTURED_PROMPT,
+    },
+    story_library::{now, thumbnail_png, LibraryEntry, StoryLibrary},
+    story_mechanics::GameMechanicRegistry,
+    story_script::{apply_assignment, find_label_index, ScriptCommand, ScriptScope},
+    story_stage::{Stage, StageDirection},
+    story_template::{PromptFragments, PromptVariable, TemplateContext, TemplateValue},
+    AppState, EventGameOver, GameState, GameType, StorySettings,
 };
 
+/// Last line of a chapter that ends with player choices
+const CHOICES_LINE: &str = "What will you do?";
+
 const PROMPT: &str = r#"
 You are narrator in a visual novel.
 Create a script for visual novel based on this setting.
 Respond only with story sentences and character lines.
 Do not include any instructions or explanations.
 Do not repeat yourself! Do no repeat character lines!
-Include dialogues for provived characters in format: "Character -> line". If character description is not provided make dialogue third person.
+Include dialogues for provived characters in format: "Character [emotion] -> line", emotion is one of neutral, angry, sad, happy, afraid. If character description is not provided make dialogue third person.
 Respond with at least 20 sentences each separated with new line. Each sentence no longer 10 words.
 "#;
 
 pub fn start_visual_novel(
     mut ew_start_scenario: EventWriter<EventStartScenario>,
-    scenario_handle: Res<ScenarioHandle>,
+    mut campaign_progress: ResMut<CampaignProgress>,
+    mut card_forge: ResMut<CardForge>,
     rpy_assets: Res<Assets<Rpy>>,
     mut game_state: ResMut<GameState>,
     mut q_novel_text: Query<(Entity, &mut Node, &NovelText)>,
 ) {
-    if let Some(rpy) = rpy_assets.get(scenario_handle.id()) {
+    // every run starts from the first act
+    campaign_progress.act = 0;
+
+    if let Some(scenario) = campaign_progress.scenario()
+        && let Some(rpy) = rpy_assets.get(scenario.id())
+    {
         ew_start_scenario.write(EventStartScenario { ast: rpy.0.clone() });
 
-        game_state.collected_deck = [
-            filter_initial_narrative_cards(game_state.game_deck.clone()),
-            filter_initial_character_cards(game_state.game_deck.clone()),
-            filter_psychosis_cards(game_state.game_deck.clone()).unwrap(),
-        ]
-        .concat();
+        game_state.reset_run();
+        // forge selections are positions in the deck being replaced
+        *card_forge = CardForge::default();
     }
 
     for (_, mut node, _) in q_novel_text.iter_mut() {
@@ -63,13 +81,17 @@ pub fn start_visual_novel(
 }
 
 pub(crate) fn handle_text_2_image_response(
-    game_state: ResMut<GameState>,
+    mut game_state: ResMut<GameState>,
     mut novel_data: ResMut<NovelData>,
     mut ew_switch_next_node: EventWriter<EventSwitchNextNode>,
     mut er_text_2_image_response: EventReader<EventText2ImageResponse>,
     mut textures: ResMut<Assets<Image>>,
 ) {
     for event in er_text_2_image_response.read() {
+        if event.request_type != Text2ImageRequestType::Scene {
+            continue;
+        }
+
         let image_name = event.filename.clone();
         let dynamic_image: DynamicImage = event.image.clone();
         let rgba_image = dynamic_image.to_rgba8();
@@ -85,6 +107,12 @@ pub(crate) fn handle_text_2_image_response(
             RenderAssetUsages::all(),
         );
 
+        // the latest background stands for the story in the library
+        match thumbnail_png(&dynamic_image) {
+            Ok(thumbnail) => game_state.story_thumbnail = thumbnail,
+            Err(err) => warn!("story thumbnail: {}", err),
+        }
+
         let texture_handle = textures.add(texture);
         let sprite = Sprite {
             image: texture_handle.clone(),
@@ -137,112 +165,236 @@ pub(crate) fn handle_llm_response(
     mut ew_text_2_image_request: EventWriter<EventText2ImageRequest>,
     mut ew_refresh_ui: EventWriter<EventRefreshUI>,
     assets: Res<AssetServer>,
+    story_settings: Res<StorySettings>,
 ) {
     for event in er_llm_response.read() {
         match event.request_type {
             LLMRequestType::Story => {
                 ew_refresh_ui.write(EventRefreshUI::LoadingMenu);
+                game_state.n_chapters += 1;
+
+                let reply = match story_settings.structured_output {
+                    true => parse_structured_reply(&event.response).unwrap_or_else(|err| {
+                        warn!("structured story reply rejected, reading as text: {}", err);
+                        parse_heuristic_reply(&event.response)
+                    }),
+                    false => parse_heuristic_reply(&event.response),
+                };
+
+                for update in reply.characters.iter() {
+                    game_state.characters.apply(update.clone());
+                }
 
-                let sentences = event
-                    .response
-                    .split("\n")
-                    .map(|s| s.trim())
-                    .filter(|s| !s.is_empty())
-                    .collect::<Vec<_>>();
-
+                let mut stage = Stage::default();
                 let mut ast_position: usize = 0;
-                for sentence in sentences.iter() {
-                    let sentence = sentence.to_string();
-                    game_state.narrative_story_so_far.push(sentence.clone());
-
-                    if sentence.contains("->") {
-                        let parts: Vec<_> = sentence.split("->").collect();
-                        let who = parts[0];
-                        let who = who.trim().to_string();
-                        let what = parts[1].replace("\"", "");
-                        let what = what.trim().to_string();
-
-                        // find appropriate image
-                        let character_cards =
-                            filter_character_deck(game_state.game_deck.clone()).unwrap();
-                        if let Some(character_card) = character_cards
-                            .iter()
-                            .find(|card| card.metadata.name().unwrap() == who)
-                        {
-                            let character_name = character_card.metadata.name().unwrap();
-                            let image_path = format!("character-cards/{}.png", character_name);
-                            let sprite = Sprite::from_image(assets.load(image_path));
-
-                            novel_data.write_image_cache(character_name.clone(), sprite);
-
-                            novel_data.push_show_node(
-                                character_name.clone(),
-                                game_state.n_vn_node + 1 + ast_position,
-                            );
-                            ast_position += 1;
-
-                            novel_data.push_text_node(
-                                Some(who),
-                                what,
-                                game_state.n_vn_node + 1 + ast_position,
-                            );
-                            ast_position += 1;
-
-                            novel_data.push_hide_node(
-                                character_name.clone(),
-                                game_state.n_vn_node + 1 + ast_position,
-                            );
-                            ast_position += 1;
-                        } else {
-                            novel_data.push_text_node(
-                                Some(who),
-                                what,
-                                game_state.n_vn_node + 1 + ast_position,
-                            );
-
-                            ast_position += 1;
-                        }
-                    } else {
-                        novel_data.push_text_node(
-                            None,
-                            sentence.clone(),
+                for beat in reply.beats.iter() {
+                    game_state.narrative_story_so_far.push(beat.to_story_line());
+
+                    let mut directions = vec![];
+                    if beat.scene_change.is_some() {
+                        directions.extend(stage.clear());
+                    }
+
+                    // characters with a card stay on stage between their lines
+                    if let Some(who) = &beat.speaker
+                        && game_state.character_sprites.sprite(who, None).is_some()
+                    {
+                        directions.extend(stage.speak(who, beat.emotion.as_deref()));
+                    }
+
+                    for direction in directions {
+                        push_stage_direction(
+                            &mut novel_data,
+                            &assets,
+                            &game_state.character_sprites,
+                            direction,
                             game_state.n_vn_node + 1 + ast_position,
                         );
-
                         ast_position += 1;
                     }
 
+                    novel_data.push_text_node(
+                        beat.speaker.clone(),
+                        beat.text.clone(),
+                        game_state.n_vn_node + 1 + ast_position,
+                    );
+                    ast_position += 1;
+
                     novel_settings.pause_handle_switch_node = false;
                 }
 
-                let text_2_image_prompt = format!(
-                    r#"
-                    Create prompt for text-to-image model based short story.
-                    Image style: realistic.
-                    Respond only with one prompt.
-                    Do not include any explanations.
-                    Story:`{}`
-                    "#,
-                    sentences.join(" ")
-                );
+                for direction in stage.clear() {
+                    push_stage_direction(
+                        &mut novel_data,
+                        &assets,
+                        &game_state.character_sprites,
+                        direction,
+                        game_state.n_vn_node + 1 + ast_position,
+                    );
+                    ast_position += 1;
+                }
 
-                ew_llm_request.write(EventLLMRequest {
-                    prompt: text_2_image_prompt,
-                    who: None,
-                    request_type: LLMRequestType::Text2ImagePrompt,
-                });
+                if !reply.choices.is_empty() {
+                    novel_data.push_text_node(
+                        None,
+                        CHOICES_LINE.to_string(),
+                        game_state.n_vn_node + 1 + ast_position,
+                    );
+                    game_state.story_choices = reply.choices.clone();
+                }
+
+                // one generated background per chapter: a scene change the LLM
+                // described is used as is, otherwise the LLM writes the prompt
+                if let Some(scene_description) = reply.scene_description() {
+                    ew_text_2_image_request.write(EventText2ImageRequest {
+                        prompt: scene_description,
+                        request_type: Text2ImageRequestType::Scene,
+                    });
+                } else {
+                    let text_2_image_prompt = format!(
+                        r#"
+                        Create prompt for text-to-image model based short story.
+                        Image style: realistic.
+                        Respond only with one prompt.
+                        Do not include any explanations.
+                        Story:`{}`
+                        "#,
+                        reply
+                            .beats
+                            .iter()
+                            .map(|beat| beat.to_story_line())
+                            .collect::<Vec<String>>()
+                            .join(" ")
+                    );
+
+                    ew_llm_request.write(EventLLMRequest {
+                        prompt: text_2_image_prompt,
+                        request_type: LLMRequestType::Text2ImagePrompt,
+                    });
+                }
 
                 game_state.n_vn_node_scene_request = game_state.n_vn_node;
+
+                if let Some(summary_prompt) = game_state.narrative_story_so_far.start_summary(
+                    story_settings.summary_threshold,
+                    story_settings.summary_keep_recent,
+                ) {
+                    ew_llm_request.write(EventLLMRequest {
+                        prompt: summary_prompt,
+                        request_type: LLMRequestType::Summary,
+                    });
+                }
             }
             LLMRequestType::Text2ImagePrompt => {
                 ew_text_2_image_request.write(EventText2ImageRequest {
                     prompt: event.response.clone(),
+                    request_type: Text2ImageRequestType::Scene,
                 });
             }
+            LLMRequestType::Summary => {
+                game_state
+                    .narrative_story_so_far
+                    .finish_summary(&event.response);
+            }
+            // read by the card forge
+            LLMRequestType::ForgeCard => {}
+        }
+    }
+}
+
+/// The story goes on without the chapter; a failed summary is tried again
+/// after the next one
+pub(crate) fn handle_llm_failed(
+    mut game_state: ResMut<GameState>,
+    mut novel_settings: ResMut<NovelSettings>,
+    mut novel_data: ResMut<NovelData>,
+    mut er_llm_failed: EventReader<EventLLMFailed>,
+) {
+    for event in er_llm_failed.read() {
+        match event.request_type {
+            LLMRequestType::Story => {
+                novel_data.push_text_node(
+                    None,
+                    "The story could not be written this time.".to_string(),
+                    game_state.n_vn_node + 1,
+                );
+                novel_settings.pause_handle_switch_node = false;
+            }
+            LLMRequestType::Summary => game_state.narrative_story_so_far.cancel_summary(),
+            // the chapter keeps the previous background
+            LLMRequestType::Text2ImagePrompt => {}
+            // read by the card forge
+            LLMRequestType::ForgeCard => {}
+        }
+    }
+}
+
+/// Shows or hides a character portrait, each expression and position is its
+/// own cached sprite
+fn push_stage_direction(
+    novel_data: &mut NovelData,
+    assets: &AssetServer,
+    character_sprites: &CharacterSprites,
+    direction: StageDirection,
+    index: usize,
+) {
+    match direction {
+        StageDirection::Show(actor) => {
+            let Some(image_path) = character_sprites.sprite(&actor.name, Some(&actor.emotion))
+            else {
+                return;
+            };
+
+            let sprite = Sprite {
+                image: assets.load(image_path),
+                anchor: actor.position.anchor(),
+                ..Default::default()
+            };
+
+            novel_data.write_image_cache(actor.image_name(), sprite);
+            novel_data.push_show_node(actor.image_name(), index);
+        }
+        StageDirection::Hide(actor) => {
+            novel_data.push_hide_node(actor.image_name(), index);
         }
     }
 }
 
+/// Sets a scenario variable or moves the scenario to the label a jump points at
+fn run_script_command(
+    command: ScriptCommand,
+    novel_data: &mut NovelData,
+    game_state: &mut GameState,
+) -> Result<()> {
+    match command {
+        ScriptCommand::Set(name, assignment, value) => {
+            apply_assignment(&mut game_state.script_variables, &name, assignment, value);
+        }
+        ScriptCommand::Jump(condition, label) => {
+            let index = find_label_index(&novel_data.ast, &label)
+                .ok_or_else(|| anyhow!("unknown label `{}`", label))?;
+
+            let jump = match condition {
+                Some(condition) => condition.eval(&ScriptScope {
+                    builtins: vec![
+                        ("score", game_state.score as i64),
+                        ("chapters", game_state.n_chapters as i64),
+                    ],
+                    variables: &game_state.script_variables,
+                    played_cards: &game_state.played_cards,
+                })?,
+                None => true,
+            };
+
+            if jump {
+                novel_data.current_index = index;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub(crate) fn handle_new_vn_node(
     mut novel_data: ResMut<NovelData>,
     mut game_state: ResMut<GameState>,
@@ -250,62 +402,67 @@ pub(crate) fn handle_new_vn_node(
     mut ew_switch_next_node: EventWriter<EventSwitchNextNode>,
     mut er_handle_node: EventReader<EventHandleNode>,
     mut ew_llm_request: EventWriter<EventLLMRequest>,
-    mut ew_start_poker_game: EventWriter<EventStartPokerGame>,
-    mut ew_start_narrative_game: EventWriter<EventStartNarrativeGame>,
-    mut ew_start_narrative_card_shop: EventWriter<EventStartNarrativeCardShop>,
+    mut commands: Commands,
+    game_mechanics: Res<GameMechanicRegistry>,
     mut ew_hide_vn_text_node: EventWriter<EventHideTextNode>,
     mut ew_show_vn_text_node: EventWriter<EventShowTextNode>,
     mut ew_render_ui: EventWriter<EventRenderUI>,
     mut ew_refresh_ui: EventWriter<EventRefreshUI>,
-    mut ew_game_over: EventWriter<EventGameOver>,
     app_state: Res<State<AppState>>,
+    (story_settings, prompt_fragments): (Res<StorySettings>, Res<PromptFragments>),
 ) {
     for event in er_handle_node.read() {
         game_state.n_vn_node = event.ast.index();
 
-        if let AST::LLMGenerate(_, who, prompt) = event.ast.clone() {
+        if *app_state.get() != AppState::NovelPlayer
+            && let Some(beat) = StoryBeat::from_node(&event.ast, &game_state.character_sprites)
+        {
+            game_state.story_beats.push(beat);
+        }
+
+        if let AST::LLMGenerate(_, _who, prompt) = event.ast.clone() {
             if *app_state.get() == AppState::NovelPlayer {
                 ew_switch_next_node.write(EventSwitchNextNode {});
                 continue;
             }
 
-            novel_data.push_text_node(
-                Some("".to_string()),
-                "...".to_string(),
-                game_state.n_vn_node + 1,
-            );
-            ew_switch_next_node.write(EventSwitchNextNode {});
-            novel_settings.pause_handle_switch_node = true;
-
-            let prompt = prompt
-                .unwrap()
-                .replace(
-                    "{COMBINATIONS}",
-                    &game_state
-                        .poker_combinations
-                        .iter()
-                        .map(|c| c.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", "),
-                )
-                .replace("{SCORE}", &game_state.score.to_string())
-                .replace("{SETTING}", &game_state.narrative_settings.join(" "))
-                .replace("{PLOT TWIST}", &game_state.narrative_plot_twists.join(" "))
-                .replace("{CONFLICT}", &game_state.narrative_conflicts.join(" "))
-                .replace("{STORY}", &game_state.narrative_story_so_far.join(" "))
-                .replace("{CHARACTERS}", &game_state.characters.join(" "))
-                .replace("{PSYCHOSIS}", &game_state.psychosis.join(" "))
-                .replace("{PROMPT}", PROMPT);
-
-            ew_llm_request.write(EventLLMRequest {
-                prompt,
-                who: Some(who),
-                request_type: LLMRequestType::Story,
-            });
-
-            if game_state.current_menu_type != EventRenderUI::Loading {
-                ew_render_ui.write(EventRenderUI::Loading);
-                game_state.current_menu_type = EventRenderUI::Loading;
+            let prompt = prompt_context(&game_state, &story_settings, &prompt_fragments)
+                .render(&prompt.unwrap_or_default());
+
+            match prompt {
+                Ok(prompt) => {
+                    novel_data.push_text_node(
+                        Some("".to_string()),
+                        "...".to_string(),
+                        game_state.n_vn_node + 1,
+                    );
+                    ew_switch_next_node.write(EventSwitchNextNode {});
+                    novel_settings.pause_handle_switch_node = true;
+
+                    ew_llm_request.write(EventLLMRequest {
+                        prompt,
+                        request_type: LLMRequestType::Story,
+                    });
+
+                    // a choice steers the one chapter asked for after it
+                    game_state.story_choice = None;
+                    game_state.story_choices.clear();
+
+                    if game_state.current_menu_type != EventRenderUI::Loading {
+                        ew_render_ui.write(EventRenderUI::Loading);
+                        game_state.current_menu_type = EventRenderUI::Loading;
+                    }
+                }
+                Err(err) => {
+                    error!("llm_generate prompt: {}", err);
+
+                    novel_data.push_text_node(
+                        Some("".to_string()),
+                        format!("Script error in llm_generate prompt: {}", err),
+                        game_state.n_vn_node + 1,
+                    );
+                    ew_switch_next_node.write(EventSwitchNextNode {});
+                }
             }
 
             game_state.game_type = GameType::VisualNovel;
@@ -319,50 +476,63 @@ pub(crate) fn handle_new_vn_node(
                 continue;
             }
 
-            ew_hide_vn_text_node.write(EventHideTextNode {});
-            novel_settings.pause_handle_switch_node = true;
+            if let Some(command) = ScriptCommand::parse(&mechanic) {
+                let result = match command {
+                    Ok(command) => run_script_command(command, &mut novel_data, &mut game_state),
+                    Err(err) => Err(err),
+                };
 
-            match mechanic.as_str() {
-                "card play poker" => {
-                    ew_start_poker_game.write(EventStartPokerGame {});
-                    game_state.current_menu_type =
-                        EventRenderUI::Poker(PokerMenuSettings { ..default() });
-                }
-                "card play narrative setting" => {
-                    ew_start_narrative_game.write(EventStartNarrativeGame::Setting);
-                }
-                "card play narrative characters" => {
-                    ew_start_narrative_game.write(EventStartNarrativeGame::Characters);
-                }
-                "card play narrative conflict" => {
-                    ew_start_narrative_game.write(EventStartNarrativeGame::Conflict);
-                }
-                "card play narrative psychosis" => {
-                    ew_start_narrative_game.write(EventStartNarrativeGame::Psychosis);
-                }
-                "card play narrative plot twist" => {
-                    ew_start_narrative_game.write(EventStartNarrativeGame::PlotTwist);
+                if let Err(err) = result {
+                    error!("game_mechanic {}: {}", mechanic, err);
+
+                    novel_data.push_text_node(
+                        Some("".to_string()),
+                        format!("Script error in game_mechanic: {}", err),
+                        game_state.n_vn_node + 1,
+                    );
                 }
-                "card shop" => {
-                    ew_start_narrative_card_shop.write(EventStartNarrativeCardShop {});
+                ew_switch_next_node.write(EventSwitchNextNode {});
+                continue;
+            }
+
+            match game_mechanics.resolve(&mechanic) {
+                Ok((handler, args)) => {
+                    ew_hide_vn_text_node.write(EventHideTextNode {});
+                    novel_settings.pause_handle_switch_node = true;
+
+                    commands.run_system_with(handler, args);
                 }
-                "game over" => {
-                    ew_game_over.write(EventGameOver {});
+                Err(err) => {
+                    error!("game_mechanic {}: {}", mechanic, err);
+
+                    novel_data.push_text_node(
+                        Some("".to_string()),
+                        format!("Script error in game_mechanic: {}", err),
+                        game_state.n_vn_node + 1,
+                    );
+                    ew_switch_next_node.write(EventSwitchNextNode {});
                 }
-                _ => (),
             }
         } else {
             ew_show_vn_text_node.write(EventShowTextNode {});
 
-            if let Some(node) =
+            if let Some(AST::Say(_index, _who, what)) =
                 find_element_with_index(novel_data.ast.clone(), novel_data.current_index)
             {
-                if let AST::Say(_index, _who, what) = node {
-                    if what == *"..." {
-                        ew_render_ui.write(EventRenderUI::Loading);
-                        game_state.current_menu_type = EventRenderUI::Loading;
-                        continue;
-                    }
+                if what == *"..." {
+                    ew_render_ui.write(EventRenderUI::Loading);
+                    game_state.current_menu_type = EventRenderUI::Loading;
+                    continue;
+                }
+
+                // wait for the player to pick one of the options
+                if what == *CHOICES_LINE && !game_state.story_choices.is_empty() {
+                    novel_settings.pause_handle_switch_node = true;
+                    let choices = game_state.story_choices.clone();
+                    ew_render_ui.write(EventRenderUI::Choices(choices.clone()));
+                    game_state.current_menu_type = EventRenderUI::Choices(choices);
+                    game_state.game_type = GameType::VisualNovel;
+                    continue;
                 }
             }
 
@@ -377,19 +547,172 @@ pub(crate) fn handle_new_vn_node(
     }
 }
 
+/// Variables available to `llm_generate` prompt templates, one per
+/// `PromptVariable`; `{STORY}` is the synopsis and recent lines cut to the
+/// token budget
+fn prompt_context(
+    game_state: &GameState,
+    story_settings: &StorySettings,
+    prompt_fragments: &PromptFragments,
+) -> TemplateContext {
+    let mut context = TemplateContext::with_fragments(prompt_fragments.0.clone());
+
+    for variable in PromptVariable::ALL {
+        let value = match variable {
+            PromptVariable::Score => TemplateValue::Text(game_state.score.to_string()),
+            PromptVariable::Seed => TemplateValue::Text(game_state.seed.to_string()),
+            PromptVariable::Chapters => TemplateValue::Text(game_state.n_chapters.to_string()),
+            PromptVariable::BestHand => TemplateValue::Text(
+                game_state
+                    .best_poker_combination
+                    .as_ref()
+                    .map(|c| c.to_string())
+                    .unwrap_or_default(),
+            ),
+            PromptVariable::Choice => {
+                TemplateValue::Text(game_state.story_choice.clone().unwrap_or_default())
+            }
+            PromptVariable::Prompt => TemplateValue::Text(match story_settings.structured_output {
+                true => STRUCTURED_PROMPT.to_string(),
+                false => format!("{}{}{}", PROMPT, CHARACTERS_PROMPT, CHOICES_PROMPT),
+            }),
+            PromptVariable::Combinations => TemplateValue::list(
+                game_state
+                    .poker_combinations
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect(),
+                ", ",
+            ),
+            PromptVariable::Setting => {
+                TemplateValue::list(game_state.narrative_settings.clone(), " ")
+            }
+            PromptVariable::PlotTwist => {
+                TemplateValue::list(game_state.narrative_plot_twists.clone(), " ")
+            }
+            PromptVariable::Conflict => {
+                TemplateValue::list(game_state.narrative_conflicts.clone(), " ")
+            }
+            PromptVariable::Psychosis => TemplateValue::list(game_state.psychosis.clone(), " "),
+            PromptVariable::Characters => {
+                TemplateValue::list(game_state.characters.prompt_lines(), " ")
+            }
+            PromptVariable::Cards => TemplateValue::list(
+                game_state
+                    .collected_deck
+                    .iter()
+                    .filter_map(|card| card.metadata.name())
+                    .collect(),
+                ", ",
+            ),
+            PromptVariable::PlayedCards => {
+                TemplateValue::list(game_state.played_cards.clone(), ", ")
+            }
+            PromptVariable::Choices => TemplateValue::list(game_state.story_choices.clone(), ", "),
+            PromptVariable::Variables => TemplateValue::list(
+                game_state
+                    .script_variables
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect(),
+                ", ",
+            ),
+            PromptVariable::Story => TemplateValue::list(
+                game_state
+                    .narrative_story_so_far
+                    .prompt_lines(story_settings.story_token_budget),
+                " ",
+            ),
+        };
+        context.set(variable.name(), value);
+    }
+
+    context
+}
+
 pub(crate) fn handle_event_game_over(
     mut er_game_over: EventReader<EventGameOver>,
     mut ew_render_ui: EventWriter<EventRenderUI>,
     mut ew_persist_scenario: EventWriter<EventPersistScenarioRequest>,
-    novel_data: Res<NovelData>,
+    mut library: ResMut<StoryLibrary>,
+    game_state: Res<GameState>,
 ) {
-    for _ in er_game_over.read() {
+    for event in er_game_over.read() {
         // show menu
-        ew_render_ui.write(EventRenderUI::GameOver);
+        ew_render_ui.write(match event.won {
+            true => EventRenderUI::CampaignWon,
+            false => EventRenderUI::GameOver,
+        });
 
-        // save scenario to server
+        let story = StoryExport::new(
+            game_state.story_beats.clone(),
+            game_state.played_cards.clone(),
+            game_state.score,
+        );
+
+        // keep a copy on this device
+        library.add(LibraryEntry::new(
+            story.clone(),
+            game_state.story_thumbnail.clone(),
+            now(),
+        ));
+        if let Err(err) = library.save() {
+            error!("saving story library: {}", err);
+        }
+
+        // save story to server
         ew_persist_scenario.write(EventPersistScenarioRequest {
-            scenario: novel_data.ast.clone(),
+            story,
+            attributes: run_attributes(&game_state),
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_context_sets_lint_variables() {
+        let context = prompt_context(
+            &GameState::default(),
+            &StorySettings::default(),
+            &PromptFragments::default(),
+        );
+
+        for variable in PromptVariable::ALL {
+            match context.get(variable.name()) {
+                Some(TemplateValue::List(..)) => assert!(variable.is_list()),
+                Some(TemplateValue::Text(_)) => assert!(!variable.is_list()),
+                None => panic!("{} is not set", variable.name()),
+            }
+        }
+        assert_eq!(context.variables().count(), PromptVariable::ALL.len());
+    }
+
+    #[test]
+    fn test_reset_run_starts_from_scratch() {
+        let mut game_state = GameState {
+            max_n_poker_draws: 25,
+            score: 120,
+            narrative_settings: vec!["Kherson".to_string()],
+            story_choice: Some("run".to_string()),
+            story_choices: vec!["run".to_string(), "hide".to_string()],
+            played_cards: vec!["Mysterious Stranger".to_string()],
+            ..default()
+        };
+        game_state
+            .narrative_story_so_far
+            .push("Igor met Komarito.".to_string());
+
+        game_state.reset_run();
+
+        assert_eq!(game_state.score, 0);
+        assert_eq!(game_state.max_n_poker_draws, 25);
+        assert!(game_state.narrative_settings.is_empty());
+        assert!(game_state.story_choice.is_none());
+        assert!(game_state.story_choices.is_empty());
+        assert!(game_state.played_cards.is_empty());
+        assert!(game_state.narrative_story_so_far.lines.is_empty());
+    }
+}
@@ -5,4 +5,15 @@ extern "C" {
     pub fn user_connected_wallet() -> String;
     pub fn mode() -> String;
     pub fn nft_link() -> String;
+    pub fn now_seconds() -> f64;
+    pub fn library_load() -> String;
+    #[wasm_bindgen(catch)]
+    pub fn library_save(json: &str) -> Result<(), JsValue>;
+    pub fn save_file(filename: &str, mime_type: &str, bytes: &[u8]);
+    pub fn wallet_listen();
+    pub fn wallet_connect();
+    pub fn wallet_disconnect();
+    pub fn wallet_poll() -> Option<String>;
+    pub fn wallet_sign(message: &str, address: &str);
+    pub fn wallet_poll_signature() -> Option<String>;
 }